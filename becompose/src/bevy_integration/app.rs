@@ -3,16 +3,19 @@
 //! Provides a high-level API for creating BECOMPOSE applications
 //! that hides the complexity of Bevy setup.
 
+use bevy::ecs::system::SystemState;
 use bevy::prelude::*;
 use bevy::window::{Window, WindowPlugin, WindowResolution};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use super::composables::{
-    begin_incremental_composition, clear_parent_stack, clear_scope_mapping, end_composition,
-    enter_scope, exit_scope, get_scope_info, has_dirty_scopes, set_parent_for_scope,
-    take_dirty_scopes, ScopeId, ScopeMarker,
+    begin_composition, begin_incremental_composition, clear_parent_stack, end_composition,
+    enter_scope, exit_scope, get_scope_info, recompose_within_boundary, release_movable_content,
+    set_parent_for_scope, with_composition_tree, with_installed_world, ScopeId,
 };
-use super::BecomposePlugin;
+use super::{with_runtime, BecomposePlugin, ComposeRoot, ComposeRuntime, SaveableStateRegistry};
+use crate::composition::{process_recompositions, CompositionTree, DirtyFlags, RecomposeScheduler};
 
 /// Configuration for a BECOMPOSE application window
 #[derive(Clone)]
@@ -57,6 +60,15 @@ impl WindowConfig {
         self.resizable = resizable;
         self
     }
+
+    fn to_window(&self) -> Window {
+        Window {
+            title: self.title.clone(),
+            resolution: WindowResolution::new(self.width, self.height),
+            resizable: self.resizable,
+            ..default()
+        }
+    }
 }
 
 /// Marker component for UI root entities that should be cleared on recomposition
@@ -65,13 +77,13 @@ pub struct CompositionRoot;
 
 /// Resource to store the content function for recomposition
 #[derive(Resource)]
-struct ContentFn {
-    compose_fn: Arc<Mutex<Box<dyn Fn() + Send + Sync>>>,
+pub(crate) struct ContentFn {
+    pub(crate) compose_fn: Arc<Mutex<Box<dyn Fn() + Send + Sync>>>,
 }
 
 /// Resource to track scope-to-entity mappings for incremental updates
 #[derive(Resource, Default)]
-struct ScopeRegistry {
+pub(crate) struct ScopeRegistry {
     /// Tracks if initial composition has happened
     initial_composition_done: bool,
 }
@@ -80,6 +92,9 @@ struct ScopeRegistry {
 pub struct BecomposeApp {
     window_config: WindowConfig,
     content: Option<Box<dyn Fn() + Send + Sync>>,
+    windows: Vec<(WindowConfig, ComposeRoot)>,
+    frame_budget: Option<Duration>,
+    saveable_registry: Option<Box<dyn SaveableStateRegistry>>,
 }
 
 impl Default for BecomposeApp {
@@ -94,6 +109,9 @@ impl BecomposeApp {
         Self {
             window_config: WindowConfig::default(),
             content: None,
+            windows: Vec::new(),
+            frame_budget: None,
+            saveable_registry: None,
         }
     }
 
@@ -116,6 +134,20 @@ impl BecomposeApp {
         self
     }
 
+    /// Limit the time each frame spends recomposing; scopes left over are
+    /// rebuilt on the next frame
+    pub fn frame_budget(mut self, budget: Duration) -> Self {
+        self.frame_budget = Some(budget);
+        self
+    }
+
+    /// Save and restore `remember_saveable` states with `registry`, e.g. a
+    /// `FileSaveableRegistry` to keep them across restarts
+    pub fn saveable_registry(mut self, registry: impl SaveableStateRegistry) -> Self {
+        self.saveable_registry = Some(Box::new(registry));
+        self
+    }
+
     /// Set the content composable function
     /// This function will be called on recomposition to rebuild the UI
     pub fn content<F>(mut self, content_fn: F) -> Self
@@ -126,29 +158,37 @@ impl BecomposeApp {
         self
     }
 
+    /// Open another window composing `content`, independently of the main
+    /// content and of other windows
+    pub fn add_window<F>(mut self, config: WindowConfig, content: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.windows.push((config, ComposeRoot::new(content)));
+        self
+    }
+
     /// Run the application
     pub fn run(self) {
         let mut app = App::new();
 
         // Configure window
         app.add_plugins(DefaultPlugins.set(WindowPlugin {
-            primary_window: Some(Window {
-                title: self.window_config.title,
-                resolution: WindowResolution::new(
-                    self.window_config.width,
-                    self.window_config.height,
-                ),
-                resizable: self.window_config.resizable,
-                ..default()
-            }),
+            primary_window: Some(self.window_config.to_window()),
             ..default()
         }));
 
         // Add BECOMPOSE plugin
         app.add_plugins(BecomposePlugin);
-
-        // Initialize scope registry
-        app.init_resource::<ScopeRegistry>();
+        if let Some(budget) = self.frame_budget {
+            app.insert_resource(RecomposeScheduler::with_frame_budget(budget));
+        }
+        if let Some(registry) = self.saveable_registry {
+            app.world()
+                .resource::<ComposeRuntime>()
+                .saveable_states()
+                .set_boxed_registry(registry);
+        }
 
         // Store content as a resource for continuous recomposition
         if let Some(content) = self.content {
@@ -158,14 +198,22 @@ impl BecomposeApp {
             app.insert_resource(content_fn);
         }
 
-        // Setup camera on startup
-        app.add_systems(Startup, setup_camera);
-
-        // Initial composition on first frame
-        app.add_systems(Startup, initial_composition.after(setup_camera));
-
-        // Incremental recompose UI when scopes are dirty
-        app.add_systems(Update, incremental_recompose_ui);
+        // Setup camera before the initial composition
+        app.add_systems(Startup, setup_camera.before(initial_composition));
+
+        // Each extra window gets its own compose root
+        for (config, root) in self.windows {
+            let window = app.world_mut().spawn(config.to_window()).id();
+            app.world_mut().spawn((
+                Node {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                root.window(window),
+            ));
+        }
 
         app.run();
     }
@@ -176,83 +224,50 @@ fn setup_camera(mut commands: Commands) {
     commands.spawn(Camera2d);
 }
 
-/// System that performs the initial full composition
-fn initial_composition(
-    mut commands: Commands,
-    content: Option<Res<ContentFn>>,
-    mut registry: ResMut<ScopeRegistry>,
-) {
-    use super::composables::begin_composition;
-
-    let Some(content) = content else { return };
-
-    let compose_fn = content.compose_fn.clone();
-
-    // Initialize thread-local composition context
-    begin_composition(&mut commands);
-
-    // Enter root scope (ScopeId(0)) for initial composition
-    enter_scope(ScopeId(0));
-
-    // Compose UI
-    if let Ok(guard) = compose_fn.lock() {
-        guard();
-    };
-
-    exit_scope();
-
-    // Clean up composition context
-    end_composition();
-
-    registry.initial_composition_done = true;
+/// Run a composition pass with the app's runtime, composition tree and world
+/// installed on this thread
+///
+/// The commands composables queue are applied to `world` once the pass is
+/// done, with the composition tree back in place. State written during the
+/// pass is applied right away rather than in a snapshot: the pass builds the
+/// composition tree as it goes, so it could not be discarded on a conflict
+/// anyway. No lock is held during the pass, so writes from other threads are
+/// never held up by it.
+pub(crate) fn compose_pass(world: &mut World, f: impl FnOnce()) {
+    let runtime = world.resource::<ComposeRuntime>().clone();
+    let mut commands = world.resource_scope(|world, mut tree: Mut<CompositionTree>| {
+        with_runtime(&runtime, || {
+            with_installed_world(world, || with_composition_tree(&mut tree, f)).1
+        })
+    });
+    commands.apply(world);
 }
 
-/// System that performs incremental recomposition for dirty scopes only
-fn incremental_recompose_ui(
-    mut commands: Commands,
-    content: Option<Res<ContentFn>>,
-    roots: Query<Entity, With<CompositionRoot>>,
-    scope_markers: Query<(Entity, &ScopeMarker, Option<&ChildOf>)>,
-    registry: Res<ScopeRegistry>,
-) {
-    // Only proceed if there are dirty scopes
-    if !has_dirty_scopes() {
+/// System that releases movable content dropped this frame and not emitted
+/// again by any composition pass since
+pub(crate) fn release_dropped_movables(world: &mut World) {
+    if !world.resource::<CompositionTree>().has_frame_movables() {
         return;
     }
+    compose_pass(world, release_movable_content);
+}
 
-    // Only recompose if we have content and initial composition is done
-    let Some(content) = content else { return };
-    if !registry.initial_composition_done {
+/// System that performs the initial full composition
+pub(crate) fn initial_composition(world: &mut World) {
+    let Some(content) = world.get_resource::<ContentFn>() else {
         return;
-    }
-
-    let dirty_scopes = take_dirty_scopes();
+    };
 
-    // Clone the Arc to avoid lifetime issues with the Res
     let compose_fn = content.compose_fn.clone();
 
-    // Check if root scope (0) is dirty - means full recomposition
-    let full_recompose =
-        dirty_scopes.contains(&ScopeId(0)) || dirty_scopes.contains(&ScopeId::root());
-
-    if full_recompose {
-        // Full recomposition: clear everything and rebuild
-        for entity in roots.iter() {
-            commands.entity(entity).despawn();
-        }
-
-        // Clear all scope mappings
-        for scope_id in dirty_scopes.iter() {
-            clear_scope_mapping(*scope_id);
-        }
-
+    let compose = || {
         // Initialize thread-local composition context
-        begin_incremental_composition(&mut commands);
+        begin_composition();
 
-        // Enter root scope for full recomposition
-        enter_scope(ScopeId(0));
+        // Enter root scope for initial composition
+        enter_scope(ScopeId::root());
 
-        // Recompose UI
+        // Compose UI
         if let Ok(guard) = compose_fn.lock() {
             guard();
         };
@@ -261,47 +276,128 @@ fn incremental_recompose_ui(
 
         // Clean up composition context
         end_composition();
-    } else {
-        // Granular recomposition: only rebuild dirty scope subtrees
+    };
+    compose_pass(world, compose);
 
-        // Find scope entities that need rebuilding
-        let mut scopes_to_rebuild: Vec<(ScopeId, Entity)> = Vec::new();
+    world
+        .resource_mut::<ScopeRegistry>()
+        .initial_composition_done = true;
+}
 
-        for (entity, marker, _parent) in scope_markers.iter() {
-            if dirty_scopes.contains(&marker.0) {
-                scopes_to_rebuild.push((marker.0, entity));
-            }
-        }
+/// System that performs incremental recomposition for dirty scopes only
+///
+/// Invalidated scopes are resolved to the nearest scope that can recompose on
+/// its own and handed to the `RecomposeScheduler`, which rebuilds them
+/// outermost first and within the frame budget. Rebuilt scopes are reconciled
+/// against the entities they emitted last time: matching entities are patched
+/// in place and only the difference is spawned or despawned.
+///
+/// Scopes under a `ComposeRoot` are rebuilt the same way; the app's root
+/// content is only rebuilt once it has been composed.
+#[allow(clippy::type_complexity)]
+pub(crate) fn incremental_recompose_ui(
+    world: &mut World,
+    params: &mut SystemState<(
+        Option<Res<ContentFn>>,
+        Res<ScopeRegistry>,
+        Res<ComposeRuntime>,
+        ResMut<CompositionTree>,
+        ResMut<DirtyFlags>,
+        ResMut<RecomposeScheduler>,
+    )>,
+) {
+    let (content, registry, runtime, mut tree, mut dirty, mut scheduler) = params.get_mut(world);
 
-        // Rebuild each dirty scope
-        for (scope_id, scope_entity) in scopes_to_rebuild {
-            // Get the scope's content function
-            if let Some(scope_info) = get_scope_info(scope_id) {
-                // Despawn all children of the scope container (preserve the container itself)
-                commands.entity(scope_entity).despawn_related::<Children>();
+    // Only proceed if there are dirty or deferred scopes
+    if !runtime.has_dirty_scopes() && !scheduler.has_pending() {
+        return;
+    }
 
-                // Clear scope mapping for this scope
-                clear_scope_mapping(scope_id);
+    for scope_id in runtime.take_dirty_scopes() {
+        if let Some(target) = tree.invalidate(scope_id) {
+            scheduler.schedule(target);
+        }
+    }
 
-                // Set up composition context for this scope
-                begin_incremental_composition(&mut commands);
+    let batch = scheduler.plan(&mut tree);
+    for &scope_id in &batch.coalesced {
+        dirty.mark_recomposition(scope_id);
+    }
 
-                // Rebuild inside the scope container
-                set_parent_for_scope(scope_entity);
+    // Clone the Arc to avoid lifetime issues with the Res
+    let compose_fn = content
+        .filter(|_| registry.initial_composition_done)
+        .map(|content| content.compose_fn.clone());
+    let frame = scheduler.start_frame();
+    let mut rebuilt = Vec::new();
+
+    let recompose = || {
+        for scope_id in batch.scopes {
+            // Leave the remaining scopes for the next frame once the budget is
+            // spent, but always make progress
+            if !rebuilt.is_empty() && frame.is_exceeded() {
+                break;
+            }
+            rebuilt.push(scope_id);
 
-                // Enter the scope and recompose
-                enter_scope(scope_id);
+            if scope_id == ScopeId::root() {
+                let Some(compose_fn) = &compose_fn else {
+                    continue;
+                };
 
-                // Call the scope's content function
-                (scope_info.content_fn)();
+                // Full recomposition: rebuild from the root, reusing existing entities
+                begin_composition();
+                enter_scope(ScopeId::root());
 
-                exit_scope();
-                clear_parent_stack();
+                if let Ok(guard) = compose_fn.lock() {
+                    guard();
+                };
 
+                exit_scope();
                 end_composition();
+            } else {
+                // Granular recomposition: only rebuild the dirty scope's subtree
+                recompose_within_boundary(scope_id, &recompose_scope);
             }
         }
+    };
+    compose_pass(world, recompose);
+
+    let (_, _, _, mut tree, mut dirty, mut scheduler) = params.get_mut(world);
+    for scope_id in rebuilt {
+        scheduler.complete(scope_id);
+        dirty.mark_recomposition(scope_id);
     }
+    process_recompositions(&mut tree, &mut dirty);
+    dirty.clear();
+}
+
+/// Rebuild a restartable scope inside its container entity
+pub(crate) fn recompose_scope(scope_id: ScopeId) {
+    // Scopes removed by an earlier rebuild have no info left
+    let Some(scope_info) = get_scope_info(scope_id) else {
+        return;
+    };
+    let Some(scope_entity) = scope_info.root_entity else {
+        return;
+    };
+
+    // Set up composition context for this scope
+    begin_incremental_composition();
+
+    // Rebuild inside the scope container, reconciling its existing children
+    set_parent_for_scope(scope_entity);
+
+    // Enter the scope and recompose
+    enter_scope(scope_id);
+
+    // Call the scope's content function
+    (scope_info.content_fn)();
+
+    exit_scope();
+    clear_parent_stack();
+
+    end_composition();
 }
 
 /// Create and run a simple BECOMPOSE app with just a content function
@@ -320,3 +416,146 @@ where
 {
     BecomposeApp::new().window(config).content(content).run()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bevy_integration::{remember_state, Column, Scope, State, Text};
+    use crate::components::TextStyle;
+    use crate::composable;
+    use crate::modifier::Modifiers;
+    use crate::testing::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    /// Scope showing a remembered counter, prefixed with `label`
+    fn counter_scope(label: &'static str, counter: Captured<State<i32>>) {
+        Scope(move || {
+            let count = remember_state(0);
+            counter.set(count);
+            Text(format!("{label} {}", count.get()), TextStyle::body());
+        });
+    }
+
+    #[test]
+    fn dirty_scopes_inside_a_dirty_scope_are_rebuilt_once() {
+        let outer = Captured::new();
+        let inner = Captured::new();
+        let inner_runs = Arc::new(AtomicUsize::new(0));
+        let mut app = compose_app({
+            let outer = outer.clone();
+            let inner = inner.clone();
+            let inner_runs = inner_runs.clone();
+            move || {
+                let outer = outer.clone();
+                let inner = inner.clone();
+                let inner_runs = inner_runs.clone();
+                Scope(move || {
+                    let count = remember_state(0);
+                    outer.set(count);
+                    Text(format!("outer {}", count.get()), TextStyle::body());
+                    let inner = inner.clone();
+                    let inner_runs = inner_runs.clone();
+                    Scope(move || {
+                        inner_runs.fetch_add(1, Ordering::SeqCst);
+                        let count = remember_state(0);
+                        inner.set(count);
+                        Text(format!("inner {}", count.get()), TextStyle::body());
+                    });
+                });
+            }
+        });
+        assert_eq!(inner_runs.load(Ordering::SeqCst), 1);
+
+        inner.get().set(1);
+        outer.get().set(1);
+        app.update();
+
+        assert_eq!(inner_runs.load(Ordering::SeqCst), 2);
+        assert_eq!(texts(&mut app), ["outer 1", "inner 1"]);
+    }
+
+    /// Skippable group holding a scope that reads `count`
+    #[composable]
+    fn inner_counter(count: State<i32>) {
+        Scope(move || Text(format!("inner {}", count.get()), TextStyle::body()));
+    }
+
+    #[test]
+    fn dirty_scopes_under_a_skipped_group_are_rebuilt_with_their_ancestor() {
+        let outer = Captured::new();
+        let inner = Captured::new();
+        let mut app = compose_app({
+            let outer = outer.clone();
+            let inner = inner.clone();
+            move || {
+                let outer = outer.clone();
+                let inner = inner.clone();
+                Scope(move || {
+                    let outer_count = remember_state(0);
+                    let inner_count = remember_state(0);
+                    outer.set(outer_count);
+                    inner.set(inner_count);
+                    Column(Modifiers::new(), move || {
+                        Text(format!("outer {}", outer_count.get()), TextStyle::body());
+                        inner_counter(inner_count);
+                    });
+                });
+            }
+        });
+        assert_eq!(texts(&mut app), ["outer 0", "inner 0"]);
+
+        inner.get().set(1);
+        outer.get().set(1);
+        app.update();
+
+        assert_eq!(texts(&mut app), ["outer 1", "inner 1"]);
+    }
+
+    #[test]
+    fn state_written_during_composition_is_kept_when_written_elsewhere_too() {
+        let total = State::new(0);
+        compose_app(move || {
+            total.update(|n| *n += 1);
+            // Written by another thread while the pass runs
+            thread::spawn(move || total.update(|n| *n += 10))
+                .join()
+                .unwrap();
+        });
+
+        assert_eq!(total.get_untracked(), 11);
+    }
+
+    #[test]
+    fn scopes_over_the_frame_budget_wait_for_the_next_frame() {
+        let first = Captured::new();
+        let second = Captured::new();
+        let mut app = compose_app({
+            let first = first.clone();
+            let second = second.clone();
+            move || {
+                counter_scope("first", first.clone());
+                counter_scope("second", second.clone());
+            }
+        });
+        app.insert_resource(RecomposeScheduler::with_frame_budget(Duration::ZERO));
+
+        first.get().set(1);
+        second.get().set(1);
+        app.update();
+
+        let texts_after_one_frame = texts(&mut app);
+        assert!(
+            texts_after_one_frame.contains(&"first 0".to_string())
+                ^ texts_after_one_frame.contains(&"second 0".to_string())
+        );
+        assert_eq!(
+            app.world().resource::<RecomposeScheduler>().pending_count(),
+            1
+        );
+
+        app.update();
+
+        assert_eq!(texts(&mut app), ["first 1", "second 1"]);
+    }
+}
@@ -21,52 +21,44 @@
 // Allow PascalCase function names to match Jetpack Compose conventions
 #![allow(non_snake_case)]
 
+use bevy::ecs::component::ComponentId;
+use bevy::ecs::world::CommandQueue;
 use bevy::prelude::*;
-use generational_box::{AnyStorage, GenerationalBox, Owner, SyncStorage};
+use generational_box::{GenerationalBox, GenerationalBoxId, SyncStorage};
+use std::any::{Any, TypeId};
 use std::cell::RefCell;
 use std::collections::HashSet;
-use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
 
-use crate::components::TextStyle;
+use crate::components::{IntoOnClick, TextStyle};
+use crate::composition::{
+    remove_subtree, CallSite, ComposableType, CompositionError, CompositionKey, CompositionNode,
+    CompositionTree, ErrorHandler, IntoCompositionResult, LayoutType, LeafType, SkipParams,
+    SkipRecord, Stable,
+};
+use crate::layout::Constraints;
 use crate::modifier::Modifiers;
+use crate::state::snapshot::{self, SnapshotWrite, WriteLock, WriteMode};
+use crate::state::{record_dependency, Dependency};
+
+use super::effects::polling_cancelled_effect;
+use super::layout_bridge::{estimated_constraints, MeasuredConstraints};
+use super::runtime::{current_runtime, unscoped_state_owner, RuntimeHandle, Subscriber};
 
 pub use super::app::CompositionRoot;
+pub use crate::composition::ScopedContentFn;
 
 // ============================================================================
 // Scope-based Dirty Tracking
 // ============================================================================
 
-/// Unique identifier for a composition scope
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct ScopeId(pub u64);
-
-impl ScopeId {
-    pub fn new() -> Self {
-        static COUNTER: AtomicU64 = AtomicU64::new(1);
-        Self(COUNTER.fetch_add(1, Ordering::SeqCst))
-    }
-
-    /// Root scope ID (always 0)
-    pub fn root() -> Self {
-        Self(0)
-    }
-}
-
-impl Default for ScopeId {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-/// Stored content function for a scope
-pub type ScopedContentFn = Arc<dyn Fn() + Send + Sync>;
-
-/// Registry of scope content functions for granular recomposition
-static SCOPE_REGISTRY: RwLock<Option<std::collections::HashMap<ScopeId, ScopeInfo>>> =
-    RwLock::new(None);
+/// Unique identifier for a composition scope.
+///
+/// Scopes are the nodes of the `CompositionTree`, so a `ScopeId` is the
+/// `CompositionId` of the node a composable was emitted as.
+pub use crate::composition::CompositionId as ScopeId;
 
-/// Information about a registered scope
+/// Information about a restartable scope
 #[derive(Clone)]
 pub struct ScopeInfo {
     /// The content function to call when recomposing this scope
@@ -77,122 +69,374 @@ pub struct ScopeInfo {
     pub root_entity: Option<Entity>,
 }
 
-/// Registry of scope owners for state lifetime management
-/// Each scope has its own Owner - when the scope is destroyed, all states created
-/// within that scope are automatically freed.
-static SCOPE_OWNERS: RwLock<Option<std::collections::HashMap<ScopeId, Owner<SyncStorage>>>> =
-    RwLock::new(None);
+/// Register a scope with its content function, making it restartable
+pub fn register_scope(
+    scope_id: ScopeId,
+    content_fn: ScopedContentFn,
+    parent_scope: Option<ScopeId>,
+) {
+    with_tree(|tree| {
+        let node = tree.get_or_insert(
+            scope_id,
+            ComposableType::Custom("Scope".to_string()),
+            parent_scope,
+        );
+        node.content = Some(content_fn);
+        if node.parent.is_none() {
+            node.parent = parent_scope;
+        }
+    });
+}
+
+/// Update the root entity for a scope
+pub fn set_scope_root_entity(scope_id: ScopeId, entity: Entity) {
+    with_tree(|tree| tree.set_entity(scope_id, entity));
+}
 
-/// Get or create an owner for a scope
-fn get_or_create_scope_owner(scope_id: ScopeId) -> Owner<SyncStorage> {
-    {
-        let guard = SCOPE_OWNERS.read().unwrap();
-        if let Some(map) = guard.as_ref() {
-            if let Some(owner) = map.get(&scope_id) {
-                return owner.clone();
+/// Get scope info (only restartable scopes have any)
+pub fn get_scope_info(scope_id: ScopeId) -> Option<ScopeInfo> {
+    with_tree(|tree| {
+        let node = tree.get(scope_id)?;
+        Some(ScopeInfo {
+            content_fn: node.content.clone()?,
+            parent_scope: node.parent,
+            root_entity: node.entity,
+        })
+    })
+}
+
+/// Unregister a scope (for cleanup)
+/// This removes the scope's node and its descendants from the composition
+/// tree, freeing all states created within them.
+pub fn unregister_scope(scope_id: ScopeId) {
+    with_tree(|tree| remove_subtree(tree, scope_id));
+    run_disposals();
+}
+
+/// Despawn movable content that was dropped this frame and not emitted again
+pub(crate) fn release_movable_content() {
+    let released = COMPOSITION_CTX.with(|ctx| {
+        let mut ctx = ctx.borrow_mut();
+        let released = ctx.tree.release_parked_movables();
+        let mut detached = Vec::new();
+        for entity in &released {
+            ctx.forget_entity(*entity, &mut detached);
+        }
+        released
+    });
+    run_disposals();
+
+    if !released.is_empty() {
+        with_commands(|commands| {
+            for entity in released {
+                commands.entity(entity).try_despawn();
             }
+        });
+    }
+}
+
+/// Remove a scope composed directly under `host`, such as a `ComposeRoot`,
+/// and despawn the entities it emitted there.
+///
+/// `host` itself is left alone and may already be despawned.
+pub(crate) fn unmount_scope(scope_id: ScopeId, host: Entity) {
+    let (emitted, detached) = COMPOSITION_CTX.with(|ctx| {
+        let mut ctx = ctx.borrow_mut();
+        remove_subtree(&mut ctx.tree, scope_id);
+        let mut emitted = ctx
+            .tree
+            .emitted_children
+            .remove(&Some(host))
+            .unwrap_or_default();
+        let mut detached = Vec::new();
+        for entity in &emitted {
+            ctx.forget_entity(*entity, &mut detached);
+        }
+        emitted.retain(|entity| ctx.tree.movable_for_entity(*entity).is_none());
+        (emitted, detached)
+    });
+    run_disposals();
+
+    with_commands(|commands| {
+        for entity in detached {
+            commands.entity(entity).try_remove::<ChildOf>();
+        }
+        for entity in emitted {
+            commands.entity(entity).try_despawn();
         }
+    });
+}
+
+/// Run the cleanup of nodes removed from the tree being composed: unsubscribe
+/// them from the states they read and free the states they own
+fn run_disposals() {
+    for dispose in with_tree(|tree| tree.take_disposals()) {
+        dispose();
     }
-    let mut guard = SCOPE_OWNERS.write().unwrap();
-    if guard.is_none() {
-        *guard = Some(std::collections::HashMap::new());
+}
+
+// ============================================================================
+// Positional Memoization
+// ============================================================================
+
+/// Run a closure with mutable access to a scope's node, if it is in the tree
+pub(crate) fn with_scope_node<R>(
+    scope_id: ScopeId,
+    f: impl FnOnce(&mut CompositionNode) -> R,
+) -> Option<R> {
+    with_tree(|tree| tree.get_mut(scope_id).map(f))
+}
+
+/// Enter a child group of the current scope for a composable of the given type.
+///
+/// The group is matched against the children the current scope emitted during
+/// its previous pass (by key, or else by call site and index), so a composable
+/// emitted at the same place keeps its node and its remembered values. The
+/// call site is the caller of the outermost `#[track_caller]` function, which
+/// for built-in and `#[composable]` functions is where they are called in user
+/// code. Outside of composition a detached ID is returned and no scope is
+/// entered.
+#[track_caller]
+pub fn start_group(composable_type: ComposableType, key: Option<CompositionKey>) -> ScopeId {
+    match child_group(composable_type, key, Some(CallSite::caller())) {
+        Some(scope_id) => {
+            enter_scope(scope_id);
+            scope_id
+        }
+        None => ScopeId::new(),
     }
-    let map = guard.as_mut().unwrap();
-    map.entry(scope_id)
-        .or_insert_with(SyncStorage::owner)
-        .clone()
 }
 
-/// Drop the owner for a scope, freeing all states created within it
-fn drop_scope_owner(scope_id: ScopeId) {
-    let mut guard = SCOPE_OWNERS.write().unwrap();
-    if let Some(map) = guard.as_mut() {
-        map.remove(&scope_id);
+/// Get the child group of the current scope for a composable, without
+/// entering it. A duplicate `key` falls back to positional identity. Returns
+/// `None` outside of composition.
+fn child_group(
+    composable_type: ComposableType,
+    key: Option<CompositionKey>,
+    call_site: Option<CallSite>,
+) -> Option<ScopeId> {
+    let parent = current_scope_id()?;
+    if let Some(key) = key {
+        let child = with_tree(|tree| {
+            tree.child_for(
+                parent,
+                composable_type.clone(),
+                Some(key.clone()),
+                call_site,
+            )
+        });
+        match child {
+            Some(scope_id) => return Some(scope_id),
+            None => warn!("duplicate composition key {:?}", key),
+        }
     }
+    with_tree(|tree| tree.child_for(parent, composable_type, None, call_site))
 }
 
-/// Register a scope with its content function
-pub fn register_scope(
-    scope_id: ScopeId,
-    content_fn: ScopedContentFn,
-    parent_scope: Option<ScopeId>,
-) {
-    let mut guard = SCOPE_REGISTRY.write().unwrap();
-    if guard.is_none() {
-        *guard = Some(std::collections::HashMap::new());
+/// Start the group of a skippable composable called with `params`.
+///
+/// If the group ran before with equal parameters and nothing it read has
+/// changed since, it is skipped: the entities it emitted last time are emitted
+/// again as they are and `None` is returned. Otherwise the group is entered
+/// like with `start_group`; end it with `end_skippable_group`, which records
+/// the parameters for the next pass. Groups with parameters that cannot be
+/// compared (`params` is `None`) always run.
+#[track_caller]
+pub fn start_skippable_group(
+    composable_type: ComposableType,
+    key: Option<CompositionKey>,
+    params: SkipParams,
+) -> Option<ScopeId> {
+    let Some(scope_id) = child_group(composable_type, key, Some(CallSite::caller())) else {
+        return Some(ScopeId::new());
+    };
+
+    let skipped = COMPOSITION_CTX.with(|ctx| {
+        let mut ctx = ctx.borrow_mut();
+        // Without a parent being reconciled there is nothing to keep
+        if ctx.child_frames.is_empty() {
+            return None;
+        }
+        let node = ctx.tree.get(scope_id)?;
+        let record = node.skip.as_ref()?;
+        if node.is_dirty() || !record.matches(params.as_deref()?) {
+            return None;
+        }
+        let entities = record.entities.clone();
+        for &entity in &entities {
+            ctx.reemit_child(entity);
+        }
+        Some(())
+    });
+    if skipped.is_some() {
+        return None;
     }
-    if let Some(map) = guard.as_mut() {
-        map.insert(
+
+    enter_scope(scope_id);
+    COMPOSITION_CTX.with(|ctx| {
+        let mut ctx = ctx.borrow_mut();
+        let frames = ctx.child_frames.len();
+        let start = ctx.child_frames.last().map_or(0, |frame| frame.new.len());
+        ctx.skippable_groups.push(SkippableGroup {
             scope_id,
-            ScopeInfo {
-                content_fn,
-                parent_scope,
-                root_entity: None,
-            },
-        );
-    }
+            params,
+            frames,
+            start,
+        });
+    });
+    Some(scope_id)
 }
 
-/// Update the root entity for a scope
-pub fn set_scope_root_entity(scope_id: ScopeId, entity: Entity) {
-    let mut guard = SCOPE_REGISTRY.write().unwrap();
-    if let Some(map) = guard.as_mut() {
-        if let Some(info) = map.get_mut(&scope_id) {
-            info.root_entity = Some(entity);
+/// End a group started by `start_skippable_group`, recording its parameters
+/// and the entities it emitted
+pub fn end_skippable_group(scope_id: ScopeId) {
+    COMPOSITION_CTX.with(|ctx| {
+        let mut ctx = ctx.borrow_mut();
+        // Groups left open by content that bailed out are dropped
+        let group = loop {
+            match ctx.skippable_groups.pop() {
+                Some(group) if group.scope_id == scope_id => break Some(group),
+                Some(_) => continue,
+                None => break None,
+            }
+        };
+        let record = group.and_then(|group| {
+            let frame = ctx.child_frames.last()?;
+            let params = group.params?;
+            (ctx.child_frames.len() == group.frames && frame.new.len() >= group.start).then(|| {
+                SkipRecord {
+                    params,
+                    entities: frame.new[group.start..].to_vec(),
+                }
+            })
+        });
+        if let Some(node) = ctx.tree.get_mut(scope_id) {
+            node.skip = record;
         }
+    });
+    end_group(scope_id);
+}
+
+/// Enter a child group of the current scope, returning `None` if `key` was
+/// already used by a sibling during this pass
+fn enter_child_group(
+    composable_type: ComposableType,
+    key: Option<CompositionKey>,
+    call_site: Option<CallSite>,
+) -> Option<ScopeId> {
+    let Some(parent) = current_scope_id() else {
+        return Some(ScopeId::new());
+    };
+    let scope_id = with_tree(|tree| tree.child_for(parent, composable_type, key, call_site))?;
+    enter_scope(scope_id);
+    Some(scope_id)
+}
+
+/// End a group started by `start_group`
+pub fn end_group(scope_id: ScopeId) {
+    if current_scope_id() == Some(scope_id) {
+        exit_scope();
     }
 }
 
-/// Get scope info
-pub fn get_scope_info(scope_id: ScopeId) -> Option<ScopeInfo> {
-    let guard = SCOPE_REGISTRY.read().unwrap();
-    guard.as_ref().and_then(|map| map.get(&scope_id).cloned())
+/// Remember a value across recompositions of the current scope.
+///
+/// The value is stored in the current scope's node at the position of this
+/// call. On the first composition `init` is called; on every later
+/// recomposition the stored value is returned instead. Outside of composition
+/// `init` is simply called.
+///
+/// # Example
+/// ```ignore
+/// Column(Modifiers::new(), || {
+///     let expanded = remember(|| State::new(false));
+///     Button("Toggle", Modifiers::new(), move || expanded.update(|v| *v = !*v));
+///     If(expanded.get(), || Text("Details", TextStyle::body()));
+/// });
+/// ```
+pub fn remember<T, F>(init: F) -> T
+where
+    T: Clone + Send + Sync + 'static,
+    F: FnOnce() -> T,
+{
+    match current_scope_id() {
+        Some(scope_id) => remember_in_scope(scope_id, init),
+        None => init(),
+    }
 }
 
-/// Unregister a scope (for cleanup)
-/// This also drops the scope's Owner, freeing all states created within the scope.
-pub fn unregister_scope(scope_id: ScopeId) {
-    // First drop the scope's owner to free all states created in this scope
-    drop_scope_owner(scope_id);
+/// Remember a value in the slots of a specific scope
+pub(crate) fn remember_in_scope<T, F>(scope_id: ScopeId, init: F) -> T
+where
+    T: Clone + Send + Sync + 'static,
+    F: FnOnce() -> T,
+{
+    let Some((index, existing)) = with_scope_node(scope_id, |node| node.next_slot::<T>()) else {
+        return init();
+    };
 
-    // Then remove the scope from the registry
-    let mut guard = SCOPE_REGISTRY.write().unwrap();
-    if let Some(map) = guard.as_mut() {
-        map.remove(&scope_id);
+    if let Some(value) = existing {
+        return value;
     }
+
+    // The tree is not borrowed while `init` runs, so it may compose or
+    // create state freely.
+    let value = init();
+    with_scope_node(scope_id, |node| node.set_slot(index, value.clone()));
+    value
 }
 
-/// Global dirty scope registry
-static DIRTY_SCOPES: RwLock<Option<HashSet<ScopeId>>> = RwLock::new(None);
+/// Remember a reactive `State` across recompositions of the current scope.
+///
+/// This is the component-local counterpart of `State::new`: the state is
+/// created once, the first time the scope composes, and the same handle is
+/// returned on every recomposition.
+///
+/// # Example
+/// ```ignore
+/// Column(Modifiers::new(), || {
+///     let draft = remember_state(String::new());
+///     Text(draft.get(), TextStyle::body());
+/// });
+/// ```
+pub fn remember_state<T>(initial: T) -> State<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    remember(|| State::new(initial))
+}
 
-/// Mark a specific scope as dirty (needs recomposition)
+/// Mark a specific scope of the current runtime as dirty (needs recomposition)
+///
+/// Scopes that cannot recompose on their own are resolved to their nearest
+/// restartable ancestor when the recomposition runs.
 pub fn mark_scope_dirty(scope_id: ScopeId) {
-    let mut guard = DIRTY_SCOPES.write().unwrap();
-    if guard.is_none() {
-        *guard = Some(HashSet::new());
-    }
-    if let Some(set) = guard.as_mut() {
-        set.insert(scope_id);
+    if let Some(runtime) = current_runtime() {
+        runtime.mark_dirty(scope_id);
     }
 }
 
-/// Check if any scopes are dirty
+/// Check if any scopes of the current runtime are dirty
 pub fn has_dirty_scopes() -> bool {
-    let guard = DIRTY_SCOPES.read().unwrap();
-    guard.as_ref().map(|s| !s.is_empty()).unwrap_or(false)
+    current_runtime().is_some_and(|runtime| runtime.has_dirty_scopes())
 }
 
-/// Take all dirty scopes (clears the set)
+/// Take all dirty scopes of the current runtime (clears the set)
 pub fn take_dirty_scopes() -> HashSet<ScopeId> {
-    let mut guard = DIRTY_SCOPES.write().unwrap();
-    guard.take().unwrap_or_default()
+    current_runtime()
+        .map(|runtime| runtime.take_dirty_scopes())
+        .unwrap_or_default()
 }
 
-/// Legacy invalidate function - marks the root scope dirty for full recomposition
+/// Legacy invalidate function - marks the root scope of the current runtime
+/// dirty for full recomposition
 /// Consider using State<T> which automatically tracks scopes for granular updates
+///
+/// Only the app's main content is recomposed; `ComposeRoot`s are invalidated
+/// one at a time with `ComposeRoots::invalidate`.
 pub fn invalidate() {
-    // For backward compatibility, mark scope 0 as dirty (root)
-    mark_scope_dirty(ScopeId(0));
+    // For backward compatibility, mark the root scope as dirty
+    mark_scope_dirty(ScopeId::root());
 }
 
 // ============================================================================
@@ -202,26 +446,174 @@ pub fn invalidate() {
 /// Internal composition context stored in thread-local
 pub struct CompositionContext {
     pub parent_stack: Vec<Entity>,
-    pub commands: *mut Commands<'static, 'static>,
     /// Stack of scope IDs for tracking which scope we're in
     pub scope_stack: Vec<ScopeId>,
-    /// Map of scope ID to its root entity (for selective rebuilding)
-    pub scope_root_entities: std::collections::HashMap<ScopeId, Entity>,
-    /// Map of scope ID to ALL entities in that scope (for cleanup)
-    pub scope_all_entities: std::collections::HashMap<ScopeId, Vec<Entity>>,
-    /// Map of entity to its scope (for cleanup)
-    pub entity_scopes: std::collections::HashMap<Entity, ScopeId>,
+    /// Composition tree being composed. The app's `CompositionTree` resource
+    /// is swapped in here by `with_composition_tree` for the duration of a pass.
+    pub tree: CompositionTree,
+    /// Parents whose children are being reconciled during the current pass
+    child_frames: Vec<ChildFrame>,
+    /// Skippable groups that are running, innermost last
+    skippable_groups: Vec<SkippableGroup>,
+}
+
+/// A skippable group that is running
+struct SkippableGroup {
+    scope_id: ScopeId,
+    params: SkipParams,
+    /// Depth of the child frames when the group started
+    frames: usize,
+    /// Number of children emitted into the innermost frame before the group
+    start: usize,
+}
+
+/// Children emitted under one parent during the current pass, matched
+/// against the children emitted there during the previous pass
+struct ChildFrame {
+    /// Parent entity (`None` for root-level entities)
+    parent: Option<Entity>,
+    /// Children from the previous pass
+    old: Vec<Entity>,
+    /// Unkeyed children from the previous pass, matched by position
+    old_positional: Vec<Entity>,
+    /// Keyed children from the previous pass, matched by item key
+    old_keyed: std::collections::HashMap<(CompositionKey, usize), Entity>,
+    /// Index of the first positional child that has not been matched yet
+    cursor: usize,
+    /// Key of the list item currently emitting into this parent
+    key: Option<CompositionKey>,
+    /// Number of entities the current list item has emitted so far
+    key_index: usize,
+    /// Children emitted during this pass, in order
+    new: Vec<Entity>,
 }
 
 impl CompositionContext {
     fn new() -> Self {
         Self {
             parent_stack: Vec::new(),
-            commands: std::ptr::null_mut(),
             scope_stack: Vec::new(),
-            scope_root_entities: std::collections::HashMap::new(),
-            scope_all_entities: std::collections::HashMap::new(),
-            entity_scopes: std::collections::HashMap::new(),
+            tree: CompositionTree::new(),
+            child_frames: Vec::new(),
+            skippable_groups: Vec::new(),
+        }
+    }
+
+    /// Start reconciling the children of `parent`
+    fn push_child_frame(&mut self, parent: Option<Entity>) {
+        let old = self
+            .tree
+            .emitted_children
+            .remove(&parent)
+            .unwrap_or_default();
+        let mut old_positional = Vec::new();
+        let mut old_keyed = std::collections::HashMap::new();
+        for entity in &old {
+            match self.tree.entity_keys.get(entity) {
+                Some(key) => {
+                    old_keyed.insert(key.clone(), *entity);
+                }
+                None => old_positional.push(*entity),
+            }
+        }
+        self.child_frames.push(ChildFrame {
+            parent,
+            old,
+            old_positional,
+            old_keyed,
+            cursor: 0,
+            key: None,
+            key_index: 0,
+            new: Vec::new(),
+        });
+    }
+
+    /// Find an existing child of the current parent that was spawned with the
+    /// same bundle type and can be patched in place.
+    ///
+    /// Inside a keyed list item, children are matched by the item key and their
+    /// index within the item, wherever they were before. Otherwise old children
+    /// are matched in order; any old children skipped over to find a match are
+    /// left unmatched and despawned when the frame finishes.
+    fn match_existing_child(&mut self, kind: TypeId) -> Option<Entity> {
+        let frame = self.child_frames.last_mut()?;
+        let entity_kinds = &self.tree.entity_kinds;
+
+        if let Some(key) = &frame.key {
+            let entity = frame.old_keyed.remove(&(key.clone(), frame.key_index))?;
+            return (entity_kinds.get(&entity) == Some(&kind)).then_some(entity);
+        }
+
+        let offset = frame.old_positional[frame.cursor..]
+            .iter()
+            .position(|entity| entity_kinds.get(entity) == Some(&kind))?;
+        let index = frame.cursor + offset;
+        frame.cursor = index + 1;
+        Some(frame.old_positional[index])
+    }
+
+    /// Emit a child again from a skipped group.
+    ///
+    /// The child keeps the scope it was registered with, and is taken out of
+    /// the children left to match so no other composable patches it.
+    fn reemit_child(&mut self, entity: Entity) {
+        let Some(frame) = self.child_frames.last_mut() else {
+            return;
+        };
+        if let Some(index) = frame.old_positional.iter().position(|e| *e == entity) {
+            frame.old_positional.remove(index);
+            if index < frame.cursor {
+                frame.cursor -= 1;
+            }
+        }
+        frame.old_keyed.retain(|_, e| *e != entity);
+        frame.new.push(entity);
+        let key = frame.key.clone().map(|key| {
+            frame.key_index += 1;
+            (key, frame.key_index - 1)
+        });
+        match key {
+            Some(key) => self.tree.entity_keys.insert(entity, key),
+            None => self.tree.entity_keys.remove(&entity),
+        };
+    }
+
+    /// Abandon a pass that unwound without finishing.
+    ///
+    /// Open parents keep the children they had after the previous pass and
+    /// open scopes end their pass, so the next pass starts from a consistent
+    /// tree.
+    fn abort_pass(&mut self) {
+        while let Some(frame) = self.child_frames.pop() {
+            self.tree.emitted_children.insert(frame.parent, frame.old);
+        }
+        while let Some(scope_id) = self.scope_stack.pop() {
+            self.tree.end_pass(scope_id);
+        }
+        self.parent_stack.clear();
+        self.skippable_groups.clear();
+    }
+
+    /// Forget all bookkeeping for an entity and the children we emitted under it
+    ///
+    /// Movable content containers are kept, since the content may be emitted
+    /// elsewhere in the same frame. Those not yet attached to a new parent are
+    /// added to `detached`, to be taken out of the despawned subtree.
+    fn forget_entity(&mut self, entity: Entity, detached: &mut Vec<Entity>) {
+        if self.tree.movable_for_entity(entity).is_some() {
+            if !self.tree.attached_movables.contains(&entity) {
+                detached.push(entity);
+            }
+            return;
+        }
+
+        self.tree.entity_kinds.remove(&entity);
+        self.tree.entity_keys.remove(&entity);
+        self.tree.entity_nodes.remove(&entity);
+        if let Some(children) = self.tree.emitted_children.remove(&Some(entity)) {
+            for child in children {
+                self.forget_entity(child, detached);
+            }
         }
     }
 }
@@ -230,41 +622,69 @@ thread_local! {
     pub static COMPOSITION_CTX: RefCell<CompositionContext> = RefCell::new(CompositionContext::new());
 }
 
+/// Run a closure with mutable access to the composition tree being composed
+pub(crate) fn with_tree<R>(f: impl FnOnce(&mut CompositionTree) -> R) -> R {
+    COMPOSITION_CTX.with(|ctx| f(&mut ctx.borrow_mut().tree))
+}
+
+/// Make `tree` the composition tree that composables on this thread operate on
+/// until `f` returns.
+///
+/// Called by the framework around every composition pass, so the
+/// `CompositionTree` resource always reflects the composed UI between passes.
+pub fn with_composition_tree<R>(tree: &mut CompositionTree, f: impl FnOnce() -> R) -> R {
+    fn swap_tree(tree: &mut CompositionTree) {
+        COMPOSITION_CTX.with(|ctx| std::mem::swap(&mut ctx.borrow_mut().tree, tree));
+    }
+
+    /// Swaps the tree back even if composition panics, abandoning the
+    /// unfinished pass first
+    struct Installed<'a>(&'a mut CompositionTree);
+
+    impl Drop for Installed<'_> {
+        fn drop(&mut self) {
+            if std::thread::panicking() {
+                COMPOSITION_CTX.with(|ctx| ctx.borrow_mut().abort_pass());
+            }
+            swap_tree(self.0);
+        }
+    }
+
+    swap_tree(tree);
+    let _installed = Installed(tree);
+    f()
+}
+
 /// Initialize the composition context for this frame
 /// Called by the framework - users don't need to call this
-pub fn begin_composition(commands: &mut Commands) {
+///
+/// Root-level entities from the previous pass are reconciled against the
+/// entities emitted during this pass.
+pub fn begin_composition() {
     COMPOSITION_CTX.with(|ctx| {
         let mut ctx = ctx.borrow_mut();
         ctx.parent_stack.clear();
-        // Note: Keep scope_entities and entity_scopes for incremental updates
-        // SAFETY: We ensure this pointer is only valid during composition
-        #[allow(clippy::unnecessary_cast)]
-        {
-            ctx.commands = commands as *mut Commands as *mut Commands<'static, 'static>;
-        }
+        ctx.push_child_frame(None);
     });
 }
 
 /// Begin composition for incremental updates (preserves scope mappings)
-pub fn begin_incremental_composition(commands: &mut Commands) {
+pub fn begin_incremental_composition() {
     COMPOSITION_CTX.with(|ctx| {
         let mut ctx = ctx.borrow_mut();
         ctx.parent_stack.clear();
         ctx.scope_stack.clear();
-        #[allow(clippy::unnecessary_cast)]
-        {
-            ctx.commands = commands as *mut Commands as *mut Commands<'static, 'static>;
-        }
     });
 }
 
 /// End the composition context for this frame
 pub fn end_composition() {
+    finish_child_frames();
     COMPOSITION_CTX.with(|ctx| {
         let mut ctx = ctx.borrow_mut();
         ctx.parent_stack.clear();
         ctx.scope_stack.clear();
-        ctx.commands = std::ptr::null_mut();
+        ctx.skippable_groups.clear();
     });
 }
 
@@ -273,62 +693,118 @@ pub fn current_scope_id() -> Option<ScopeId> {
     COMPOSITION_CTX.with(|ctx| ctx.borrow().scope_stack.last().copied())
 }
 
-/// Enter a new scope for composition tracking
+/// Enter a scope for composition tracking, starting a new pass over its
+/// slots and children
 pub fn enter_scope(scope_id: ScopeId) {
     COMPOSITION_CTX.with(|ctx| {
-        ctx.borrow_mut().scope_stack.push(scope_id);
+        let mut ctx = ctx.borrow_mut();
+        let parent = ctx.scope_stack.last().copied();
+        ctx.tree.get_or_insert(
+            scope_id,
+            ComposableType::Custom("Scope".to_string()),
+            parent,
+        );
+        ctx.tree.begin_pass(scope_id);
+        ctx.scope_stack.push(scope_id);
     });
+    // Drop the previous pass's subscriptions before the scope reads again
+    run_disposals();
 }
 
-/// Exit the current scope
+/// Exit the current scope, disposing child scopes it did not emit again
 pub fn exit_scope() {
     COMPOSITION_CTX.with(|ctx| {
-        ctx.borrow_mut().scope_stack.pop();
+        let mut ctx = ctx.borrow_mut();
+        if let Some(scope_id) = ctx.scope_stack.pop() {
+            ctx.tree.end_pass(scope_id);
+        }
     });
+    run_disposals();
 }
 
-/// Register an entity with the current scope
-pub fn register_entity_scope(entity: Entity, scope_id: ScopeId) {
+/// Depths of the composition context stacks at some point of a pass
+#[derive(Clone, Copy)]
+struct StackDepths {
+    parents: usize,
+    scopes: usize,
+    frames: usize,
+}
+
+fn stack_depths() -> StackDepths {
     COMPOSITION_CTX.with(|ctx| {
-        let mut ctx = ctx.borrow_mut();
-        ctx.entity_scopes.insert(entity, scope_id);
-        // Track all entities in this scope
-        ctx.scope_all_entities
-            .entry(scope_id)
-            .or_default()
-            .push(entity);
-        // Only set scope root if not already set
-        ctx.scope_root_entities.entry(scope_id).or_insert(entity);
+        let ctx = ctx.borrow();
+        StackDepths {
+            parents: ctx.parent_stack.len(),
+            scopes: ctx.scope_stack.len(),
+            frames: ctx.child_frames.len(),
+        }
+    })
+}
+
+/// Unwind the context back to `depths` after content bailed out part way:
+/// parents it opened are finished and scopes it entered end their pass
+fn unwind_to(depths: StackDepths) {
+    while COMPOSITION_CTX.with(|ctx| ctx.borrow().child_frames.len() > depths.frames) {
+        finish_child_frame();
+    }
+    COMPOSITION_CTX.with(|ctx| ctx.borrow_mut().parent_stack.truncate(depths.parents));
+    while COMPOSITION_CTX.with(|ctx| ctx.borrow().scope_stack.len() > depths.scopes) {
+        exit_scope();
+    }
+}
+
+/// Start the pass of the current scope over.
+///
+/// Whatever the scope emitted so far is treated as its previous pass, so the
+/// restarted pass reuses or disposes it like in any recomposition.
+fn restart_current_scope() {
+    let Some(scope_id) = current_scope_id() else {
+        return;
+    };
+    let container = with_tree(|tree| tree.get(scope_id).and_then(|node| node.entity));
+    let owns_frame = container.is_some()
+        && COMPOSITION_CTX.with(|ctx| {
+            ctx.borrow()
+                .child_frames
+                .last()
+                .is_some_and(|frame| frame.parent == container)
+        });
+
+    if owns_frame {
+        finish_child_frame();
+    }
+    with_tree(|tree| {
+        tree.end_pass(scope_id);
+        tree.begin_pass(scope_id);
     });
+    if owns_frame {
+        COMPOSITION_CTX.with(|ctx| ctx.borrow_mut().push_child_frame(container));
+    }
+    run_disposals();
+}
+
+/// Register an entity with the current scope
+pub fn register_entity_scope(entity: Entity, scope_id: ScopeId) {
+    with_tree(|tree| tree.register_entity(entity, scope_id));
 }
 
 /// Get the root entity for a scope
 pub fn get_scope_root_entity(scope_id: ScopeId) -> Option<Entity> {
-    COMPOSITION_CTX.with(|ctx| ctx.borrow().scope_root_entities.get(&scope_id).copied())
+    with_tree(|tree| tree.get(scope_id)?.entities.first().copied())
 }
 
 /// Get all entities belonging to a scope
 pub fn get_scope_entities(scope_id: ScopeId) -> Vec<Entity> {
-    COMPOSITION_CTX.with(|ctx| {
-        ctx.borrow()
-            .scope_all_entities
-            .get(&scope_id)
-            .cloned()
+    with_tree(|tree| {
+        tree.get(scope_id)
+            .map(|node| node.entities.clone())
             .unwrap_or_default()
     })
 }
 
 /// Clear scope mapping for a specific scope (for recomposition)
 pub fn clear_scope_mapping(scope_id: ScopeId) {
-    COMPOSITION_CTX.with(|ctx| {
-        let mut ctx = ctx.borrow_mut();
-        ctx.scope_root_entities.remove(&scope_id);
-        if let Some(entities) = ctx.scope_all_entities.remove(&scope_id) {
-            for entity in entities {
-                ctx.entity_scopes.remove(&entity);
-            }
-        }
-    });
+    with_tree(|tree| tree.clear_entities(scope_id));
 }
 
 /// Get the parent entity for inserting scope content
@@ -337,14 +813,16 @@ pub fn get_current_parent() -> Option<Entity> {
 }
 
 /// Set parent for scope recomposition
+///
+/// The children of `entity` are reconciled against what was emitted during
+/// the content that follows, until the parent is popped again.
 pub fn set_parent_for_scope(entity: Entity) {
-    COMPOSITION_CTX.with(|ctx| {
-        ctx.borrow_mut().parent_stack.push(entity);
-    });
+    push_parent(entity);
 }
 
-/// Clear parent stack
+/// Clear parent stack, finishing reconciliation of every open parent
 pub fn clear_parent_stack() {
+    finish_child_frames();
     COMPOSITION_CTX.with(|ctx| {
         ctx.borrow_mut().parent_stack.clear();
     });
@@ -353,115 +831,498 @@ pub fn clear_parent_stack() {
 /// Push a new parent onto the stack
 pub fn push_parent(entity: Entity) {
     COMPOSITION_CTX.with(|ctx| {
-        ctx.borrow_mut().parent_stack.push(entity);
+        let mut ctx = ctx.borrow_mut();
+        ctx.parent_stack.push(entity);
+        ctx.push_child_frame(Some(entity));
     });
 }
 
 /// Pop the current parent from the stack
 pub fn pop_parent() {
+    finish_child_frame();
     COMPOSITION_CTX.with(|ctx| {
         ctx.borrow_mut().parent_stack.pop();
     });
 }
 
-/// Spawn an entity and add it as a child of the current parent
-fn spawn_child(bundle: impl Bundle) -> Entity {
-    COMPOSITION_CTX.with(|ctx| {
-        let ctx = ctx.borrow();
-        // SAFETY: We ensure commands is valid during composition
-        let commands = unsafe { &mut *ctx.commands };
-        let entity = commands.spawn(bundle).id();
-
-        // Track which scope this entity belongs to
-        if let Some(&scope_id) = ctx.scope_stack.last() {
-            // Release borrow before calling register_entity_scope
-            drop(ctx);
-            register_entity_scope(entity, scope_id);
-            // Re-borrow to continue
-            let ctx = COMPOSITION_CTX.with(|c| c.borrow().parent_stack.last().copied());
-            if let Some(parent) = ctx {
-                let ctx_ref = COMPOSITION_CTX.with(|c| c.borrow().commands);
-                let commands = unsafe { &mut *ctx_ref };
-                commands.entity(parent).add_child(entity);
-            } else {
-                let ctx_ref = COMPOSITION_CTX.with(|c| c.borrow().commands);
-                let commands = unsafe { &mut *ctx_ref };
-                commands.entity(entity).insert(CompositionRoot);
+/// Finish reconciling the innermost parent.
+///
+/// Old children that were not matched during this pass are despawned, and the
+/// parent's children are reordered to match the order they were emitted in.
+fn finish_child_frame() {
+    let finished = COMPOSITION_CTX.with(|ctx| {
+        let mut ctx = ctx.borrow_mut();
+        let frame = ctx.child_frames.pop()?;
+
+        let kept: HashSet<Entity> = frame.new.iter().copied().collect();
+        let mut removed: Vec<Entity> = frame
+            .old
+            .iter()
+            .copied()
+            .filter(|entity| !kept.contains(entity))
+            .collect();
+        let mut detached = Vec::new();
+        for entity in &removed {
+            ctx.forget_entity(*entity, &mut detached);
+        }
+        removed.retain(|entity| ctx.tree.movable_for_entity(*entity).is_none());
+        for entity in &frame.new {
+            if ctx.tree.movable_for_entity(*entity).is_some() {
+                ctx.tree.attached_movables.insert(*entity);
             }
-            return entity;
         }
 
-        if let Some(parent) = ctx.parent_stack.last() {
-            commands.entity(*parent).add_child(entity);
+        // Entities spawned through `spawn_child` only ever hold children we
+        // emitted, so their child list can be replaced wholesale. Other parents
+        // (e.g. material widgets) may own internal children of their own.
+        let owned = frame
+            .parent
+            .is_some_and(|parent| ctx.tree.entity_kinds.contains_key(&parent));
+        let attach = if owned {
+            if frame.new != frame.old {
+                ChildUpdate::Replace(frame.new.clone())
+            } else {
+                ChildUpdate::Keep
+            }
         } else {
-            // Root level - mark for recomposition cleanup
-            commands.entity(entity).insert(CompositionRoot);
+            let previous: HashSet<Entity> = frame.old.iter().copied().collect();
+            ChildUpdate::Append(
+                frame
+                    .new
+                    .iter()
+                    .copied()
+                    .filter(|entity| !previous.contains(entity))
+                    .collect(),
+            )
+        };
+
+        ctx.tree.emitted_children.insert(frame.parent, frame.new);
+        Some((frame.parent, removed, detached, attach))
+    });
+
+    let Some((parent, removed, detached, attach)) = finished else {
+        return;
+    };
+
+    with_commands(|commands| {
+        for entity in detached {
+            commands.entity(entity).remove::<ChildOf>();
         }
-        entity
-    })
+        for entity in removed {
+            commands.entity(entity).despawn();
+        }
+        let Some(parent) = parent else { return };
+        match attach {
+            ChildUpdate::Keep => {}
+            ChildUpdate::Replace(children) => {
+                commands.entity(parent).replace_children(&children);
+            }
+            ChildUpdate::Append(children) => {
+                commands.entity(parent).add_children(&children);
+            }
+        }
+    });
+}
+
+/// How a parent's children change when its frame finishes
+enum ChildUpdate {
+    /// Same children in the same order
+    Keep,
+    /// Replace the whole child list with the emitted order
+    Replace(Vec<Entity>),
+    /// Append newly emitted children after any existing ones
+    Append(Vec<Entity>),
 }
 
-/// Execute a closure with mutable access to commands
-#[allow(dead_code)]
-fn with_commands<R>(f: impl FnOnce(&mut Commands) -> R) -> R {
+/// Finish reconciling every open parent, innermost first
+fn finish_child_frames() {
+    while COMPOSITION_CTX.with(|ctx| !ctx.borrow().child_frames.is_empty()) {
+        finish_child_frame();
+    }
+}
+
+/// Record an entity emitted by a composable under the current scope and parent.
+///
+/// Children are attached to their parent when the parent's frame finishes, so
+/// entities can be emitted in any order relative to reused ones.
+pub fn emit_child(entity: Entity) {
+    let (scope_id, parent) = COMPOSITION_CTX.with(|ctx| {
+        let mut ctx = ctx.borrow_mut();
+        let scope_id = ctx.scope_stack.last().copied();
+        let (parent, key) = match ctx.child_frames.last_mut() {
+            Some(frame) => {
+                frame.new.push(entity);
+                let key = frame.key.clone().map(|key| {
+                    frame.key_index += 1;
+                    (key, frame.key_index - 1)
+                });
+                (Some(frame.parent), key)
+            }
+            None => (None, None),
+        };
+        match key {
+            Some(key) => ctx.tree.entity_keys.insert(entity, key),
+            None => ctx.tree.entity_keys.remove(&entity),
+        };
+        (scope_id, parent)
+    });
+
+    // Track which scope this entity belongs to
+    if let Some(scope_id) = scope_id {
+        register_entity_scope(entity, scope_id);
+    }
+
+    match parent {
+        // Attached when the parent's frame finishes
+        Some(Some(_)) => {}
+        // Root level - mark for recomposition cleanup
+        Some(None) => with_commands(|commands| {
+            commands.entity(entity).insert(CompositionRoot);
+        }),
+        // Not reconciling - attach immediately
+        None => with_commands(|commands| match get_current_parent() {
+            Some(parent) => {
+                commands.entity(parent).add_child(entity);
+            }
+            None => {
+                commands.entity(entity).insert(CompositionRoot);
+            }
+        }),
+    }
+}
+
+/// Spawn an entity and add it as a child of the current parent.
+///
+/// If the parent had a child spawned with the same bundle type during the
+/// previous pass, that entity is patched in place instead, which keeps
+/// Bevy-side state such as `Interaction` intact. Components composition
+/// inserted on it during the previous pass that are not inserted again are
+/// removed.
+pub fn spawn_child<B: Bundle>(bundle: B) -> Entity {
+    let kind = TypeId::of::<B>();
+    let existing = COMPOSITION_CTX.with(|ctx| ctx.borrow_mut().match_existing_child(kind));
+
+    let entity = with_commands(|commands| match existing {
+        Some(entity) => {
+            commands
+                .entity(entity)
+                .insert(bundle)
+                .queue(retain_emitted::<B>);
+            entity
+        }
+        None => commands.spawn(bundle).queue(retain_emitted::<B>).id(),
+    });
+
     COMPOSITION_CTX.with(|ctx| {
-        let ctx = ctx.borrow();
-        // SAFETY: We ensure commands is valid during composition
-        let commands = unsafe { &mut *ctx.commands };
-        f(commands)
-    })
+        ctx.borrow_mut().tree.entity_kinds.insert(entity, kind);
+    });
+    emit_child(entity);
+    entity
 }
 
-// ============================================================================
-// State Owner Management (generational-box)
-// ============================================================================
+/// Components composition inserted on an entity spawned with `spawn_child`:
+/// those of its bundle and those added with `insert_emitted` since
+#[derive(Component, Default)]
+pub(crate) struct EmittedComponents(Vec<ComponentId>);
+
+/// Make the components of `B` the ones composition inserted on an entity,
+/// removing any it inserted during the previous pass that `B` doesn't have
+fn retain_emitted<B: Bundle>(mut entity: EntityWorldMut) {
+    let components =
+        entity.world_scope(|world| world.register_bundle::<B>().explicit_components().to_vec());
+    let stale: Vec<ComponentId> = entity
+        .get::<EmittedComponents>()
+        .map(|emitted| {
+            emitted
+                .0
+                .iter()
+                .copied()
+                .filter(|id| !components.contains(id))
+                .collect()
+        })
+        .unwrap_or_default();
+    entity.remove_by_ids(&stale);
+    entity.insert(EmittedComponents(components));
+}
 
-/// Global owner that manages the lifetime of State values created outside any scope.
-/// States created at the app level (before any composable runs) use this owner.
-static GLOBAL_STATE_OWNER: RwLock<Option<Owner<SyncStorage>>> = RwLock::new(None);
+/// Insert a component on an entity emitted during this pass, outside of the
+/// bundle it was spawned with.
+///
+/// Like the bundle's components, it is removed again if a later pass patches
+/// the entity without inserting it.
+pub(crate) fn insert_emitted<C: Component>(entity: Entity, component: C) {
+    with_commands(|commands| {
+        commands
+            .entity(entity)
+            .insert(component)
+            .queue(|mut entity: EntityWorldMut| {
+                let id = entity.world_scope(|world| world.register_component::<C>());
+                if let Some(mut emitted) = entity.get_mut::<EmittedComponents>() {
+                    if !emitted.0.contains(&id) {
+                        emitted.0.push(id);
+                    }
+                }
+            });
+    });
+}
+
+/// World of the composition pass or event dispatch running on this thread,
+/// with the commands queued for it so far
+struct PassWorld {
+    world: World,
+    commands: CommandQueue,
+}
+
+thread_local! {
+    static PASS_WORLD: RefCell<Option<PassWorld>> = const { RefCell::new(None) };
+    /// Empty world standing in for a world while it is installed, kept to
+    /// avoid creating one for every pass
+    static SPARE_WORLD: RefCell<Option<World>> = const { RefCell::new(None) };
+}
+
+/// Install `world` on this thread while `f` runs, so composables can queue
+/// commands for it, and apply the queued commands when `f` returns.
+///
+/// Called by the framework around every composition pass and around the
+/// dispatch of event handlers. The world is moved into the thread-local for
+/// the duration of `f` and moved back afterwards, even if `f` panics, so
+/// composables only ever reach it through `with_commands` while `f` runs.
+pub fn with_composition_world<R>(world: &mut World, f: impl FnOnce() -> R) -> R {
+    let (result, mut commands) = with_installed_world(world, f);
+    commands.apply(world);
+    result
+}
 
-/// Get the global state owner for app-level states
-fn get_global_owner() -> Owner<SyncStorage> {
-    {
-        let guard = GLOBAL_STATE_OWNER.read().unwrap();
-        if let Some(ref owner) = *guard {
-            return owner.clone();
+/// Like `with_composition_world`, but hands back the queued commands
+/// instead of applying them
+pub(crate) fn with_installed_world<R>(
+    world: &mut World,
+    f: impl FnOnce() -> R,
+) -> (R, CommandQueue) {
+    /// Moves the world back into place, even on unwind
+    struct Installed<'a> {
+        world: &'a mut World,
+        previous: Option<PassWorld>,
+        uninstalled: bool,
+    }
+
+    impl Installed<'_> {
+        /// Move the world back, returning the commands queued for it
+        fn uninstall(&mut self) -> CommandQueue {
+            self.uninstalled = true;
+            let installed = PASS_WORLD.with(|current| current.replace(self.previous.take()));
+            let Some(PassWorld { world, commands }) = installed else {
+                return CommandQueue::default();
+            };
+            let spare = std::mem::replace(self.world, world);
+            SPARE_WORLD.with(|slot| *slot.borrow_mut() = Some(spare));
+            commands
         }
     }
-    let mut guard = GLOBAL_STATE_OWNER.write().unwrap();
-    if guard.is_none() {
-        *guard = Some(SyncStorage::owner());
+
+    impl Drop for Installed<'_> {
+        fn drop(&mut self) {
+            if !self.uninstalled {
+                self.uninstall();
+            }
+        }
     }
-    guard.as_ref().unwrap().clone()
+
+    let spare = SPARE_WORLD
+        .with(|slot| slot.borrow_mut().take())
+        .unwrap_or_default();
+    let pass = PassWorld {
+        world: std::mem::replace(world, spare),
+        commands: CommandQueue::default(),
+    };
+    let previous = PASS_WORLD.with(|current| current.replace(Some(pass)));
+    let mut installed = Installed {
+        world,
+        previous,
+        uninstalled: false,
+    };
+    let result = f();
+    (result, installed.uninstall())
 }
 
+/// Execute a closure with the world of the current composition pass or event
+/// dispatch, or return `None` outside of one.
+///
+/// The commands queued so far are not applied to it yet.
+pub(crate) fn try_with_world<R>(f: impl FnOnce(&mut World) -> R) -> Option<R> {
+    PASS_WORLD.with(|current| {
+        let mut current = current.try_borrow_mut().ok()?;
+        let PassWorld { world, .. } = current.as_mut()?;
+        Some(f(world))
+    })
+}
+
+/// Execute a closure with mutable access to the command buffer of the current
+/// composition pass or event dispatch, or return `None` outside of one.
+///
+/// The buffer is borrowed while `f` runs, so calling this again from inside
+/// `f` also returns `None` rather than aliasing it.
+pub fn try_with_commands<R>(f: impl FnOnce(&mut Commands) -> R) -> Option<R> {
+    PASS_WORLD.with(|current| {
+        let mut current = current.try_borrow_mut().ok()?;
+        let PassWorld { world, commands } = current.as_mut()?;
+        let mut commands = Commands::new(commands, world);
+        Some(f(&mut commands))
+    })
+}
+
+/// Execute a closure with mutable access to the command buffer of the current
+/// composition pass or event dispatch.
+///
+/// Click handlers are dispatched with the app's world installed, so the
+/// commands they queue are applied in the same frame as the click.
+///
+/// # Panics
+/// Panics if called outside of a composition pass or event dispatch, e.g.
+/// from a background task, or from inside another `with_commands` call.
+pub fn with_commands<R>(f: impl FnOnce(&mut Commands) -> R) -> R {
+    try_with_commands(f)
+        .expect("with_commands called outside of a composition pass or event dispatch")
+}
+
+// ============================================================================
+// State Owner Management (generational-box)
+// ============================================================================
+
 /// Create a new State value.
 /// - If called inside a composable scope, the state is tied to that scope's lifetime
 /// - If called outside any scope (app level), the state lives for the app's lifetime
-fn create_state_box<T: Send + Sync + 'static>(value: T) -> GenerationalBox<T, SyncStorage> {
-    // Check if we're inside a composition scope
-    let scope_id = current_scope_id();
-
-    let owner = if let Some(scope_id) = scope_id {
-        // Use the scope's owner - state will be freed when scope is destroyed
-        get_or_create_scope_owner(scope_id)
-    } else {
-        // No scope - use global owner (app-level state)
-        get_global_owner()
-    };
+/// - If called outside any app, the state lives for the process's lifetime
+pub(crate) fn create_state_box<T: Send + Sync + 'static>(
+    value: T,
+) -> GenerationalBox<T, SyncStorage> {
+    // Use the current scope's owner - state will be freed when the scope's
+    // node leaves the composition tree. With no scope, use the runtime's
+    // owner (app-level state).
+    let owner = current_scope_id()
+        .and_then(|scope_id| with_scope_node(scope_id, |node| node.state_owner()))
+        .unwrap_or_else(unscoped_state_owner);
 
     owner.insert(value)
 }
 
-// ============================================================================
-// Reactive State
-// ============================================================================
+// ============================================================================
+// Reactive State
+// ============================================================================
+
+/// Inner state data holding value and subscribers
+struct StateInner<T> {
+    value: T,
+    /// Version of the last write applied to the value
+    version: u64,
+    /// Earlier values with their versions, oldest first, kept while read-only
+    /// snapshots started before they were overwritten are open
+    history: Vec<(u64, T)>,
+    /// Scopes that have read from this state
+    subscribers: Vec<Subscriber>,
+    /// Runtime this state was created in
+    runtime: Option<RuntimeHandle>,
+}
+
+impl<T: Clone> StateInner<T> {
+    /// Value a read-only snapshot reading at `read` sees, or the current
+    /// value outside of one
+    fn value_at(&self, read: Option<u64>) -> &T {
+        match read {
+            Some(read) if self.version > read => self
+                .history
+                .iter()
+                .rev()
+                .find(|(version, _)| *version <= read)
+                .map_or(&self.value, |(_, value)| value),
+            _ => &self.value,
+        }
+    }
+
+    /// Keep the current value before it is overwritten if read-only snapshots
+    /// reading at `oldest_read` or later may read it, and drop the earlier
+    /// values no open snapshot reads anymore
+    fn keep_for_readers(&mut self, oldest_read: Option<u64>) {
+        let Some(oldest_read) = oldest_read else {
+            self.history.clear();
+            return;
+        };
+        self.history.push((self.version, self.value.clone()));
+        let oldest_seen = self
+            .history
+            .iter()
+            .rposition(|(version, _)| *version <= oldest_read)
+            .unwrap_or(0);
+        self.history.drain(..oldest_seen);
+    }
+
+    /// Lock ordering the writes to this state: that of the runtime it was
+    /// created in, or the detached lock
+    fn write_lock(&self) -> Arc<WriteLock> {
+        self.runtime
+            .as_ref()
+            .and_then(|runtime| runtime.write_lock())
+            .unwrap_or_else(WriteLock::detached)
+    }
+
+    /// Scopes to invalidate when the value changes: the subscribers, or else
+    /// the root of the runtime the state was created in
+    fn invalidations(&self) -> Vec<Subscriber> {
+        if self.subscribers.is_empty() {
+            self.runtime
+                .iter()
+                .map(|runtime| runtime.subscriber(ScopeId::root()))
+                .collect()
+        } else {
+            self.subscribers.clone()
+        }
+    }
+}
+
+/// Write to a `State` buffered by a mutable snapshot
+struct StateWrite<T: 'static> {
+    state: State<T>,
+    /// Version of the state when the snapshot first wrote it
+    base: u64,
+    value: T,
+    /// Whether the state's readers are invalidated when the write is applied
+    notify: bool,
+}
+
+impl<T: Clone + Send + Sync + 'static> SnapshotWrite for StateWrite<T> {
+    fn base(&self) -> u64 {
+        self.base
+    }
 
-/// Inner state data holding value and subscribers
-struct StateInner<T> {
-    value: T,
-    /// Scopes that have read from this state
-    subscribers: HashSet<ScopeId>,
+    fn conflicts(&self) -> bool {
+        self.state
+            .version()
+            .is_some_and(|version| version != self.base)
+    }
+
+    fn write_lock(&self) -> Arc<WriteLock> {
+        self.state.write_lock()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn apply(self: Box<Self>, version: u64, oldest_read: Option<u64>) -> Vec<Subscriber> {
+        let write = *self;
+        let Ok(inner_guard) = write.state.inner.try_read() else {
+            return Vec::new();
+        };
+        let mut inner = inner_guard.write().unwrap();
+        inner.keep_for_readers(oldest_read);
+        inner.value = write.value;
+        inner.version = version;
+        if write.notify {
+            inner.invalidations()
+        } else {
+            Vec::new()
+        }
+    }
 }
 
 /// Reactive state that automatically triggers recomposition when modified.
@@ -471,9 +1332,12 @@ struct StateInner<T> {
 /// Just pass it around freely.
 ///
 /// ## Lifetime Management
-/// - **Inside a composable**: State is tied to the composable's scope. When the scope
-///   is destroyed (e.g., during recomposition), all states created within it are freed.
-/// - **Outside composables (app level)**: State lives for the entire application lifetime.
+/// - **Inside a composable**: State is tied to the composable's scope. `State::new`
+///   creates a fresh state every time the scope recomposes; use `remember_state` to
+///   keep the same state across recompositions.
+/// - **Outside composables (app level)**: State lives as long as the App whose
+///   runtime was active when it was created (e.g. in a click handler), or for the
+///   rest of the process if it was created outside any App.
 ///
 /// ## Subscription & Recomposition
 /// State tracks which composition scope(s) read from it and only marks those
@@ -509,7 +1373,10 @@ impl<T: Clone + Send + Sync + 'static> State<T> {
     pub fn new(value: T) -> Self {
         let inner = StateInner {
             value,
-            subscribers: HashSet::new(),
+            version: 0,
+            history: Vec::new(),
+            subscribers: Vec::new(),
+            runtime: current_runtime().map(|runtime| runtime.handle()),
         };
         Self {
             inner: create_state_box(RwLock::new(inner)),
@@ -517,70 +1384,188 @@ impl<T: Clone + Send + Sync + 'static> State<T> {
     }
 
     /// Get the current value and subscribe the current scope
+    ///
+    /// Read while a derived state computes its value, the state becomes a
+    /// dependency of the derived state instead.
     pub fn get(&self) -> T {
-        // Subscribe current scope to this state
-        if let Some(scope_id) = current_scope_id() {
-            if let Ok(inner_guard) = self.inner.try_read() {
-                if let Ok(mut inner) = inner_guard.write() {
-                    inner.subscribers.insert(scope_id);
-                }
+        if !record_dependency(|| Box::new(*self)) {
+            if let Some(scope_id) = current_scope_id() {
+                self.subscribe(scope_id);
             }
         }
+        self.read_value()
+    }
+
+    /// Read the value written by the mutable snapshot open on this thread, if
+    /// it wrote one, or else the applied value, as of the read-only snapshot
+    /// open on this thread
+    fn read_value(&self) -> T {
+        self.try_read_value().expect("State was dropped")
+    }
+
+    /// Like `read_value`, but `None` if the state was dropped
+    pub(crate) fn try_read_value(&self) -> Option<T> {
+        let pending = snapshot::pending_write(self.inner.id(), |write| {
+            write
+                .as_any()
+                .downcast_ref::<StateWrite<T>>()
+                .map(|write| write.value.clone())
+        });
+        if let Some(value) = pending.flatten() {
+            return Some(value);
+        }
+        self.inner.try_read().ok().and_then(|guard| {
+            let inner = guard.read().ok()?;
+            let read = snapshot::read_version(|| inner.write_lock());
+            Some(inner.value_at(read).clone())
+        })
+    }
+
+    /// Lock ordering the writes to this state: that of the runtime it was
+    /// created in, or the detached lock
+    fn write_lock(&self) -> Arc<WriteLock> {
         self.inner
             .try_read()
             .ok()
-            .and_then(|guard| guard.read().ok().map(|inner| inner.value.clone()))
-            .expect("State was dropped")
+            .and_then(|guard| Some(guard.read().ok()?.write_lock()))
+            .unwrap_or_else(WriteLock::detached)
     }
 
-    /// Get the value without subscribing (useful for event handlers)
-    pub fn get_untracked(&self) -> T {
+    /// Version of the last write applied to this state, if it still exists
+    fn version(&self) -> Option<u64> {
+        self.inner
+            .try_read()
+            .ok()
+            .and_then(|guard| guard.read().ok().map(|inner| inner.version))
+    }
+
+    /// Subscribe a scope of the current runtime to this state.
+    ///
+    /// The subscription is removed again when the scope is disposed.
+    fn subscribe(&self, scope_id: ScopeId) {
+        let Some(subscriber) = Subscriber::new(scope_id) else {
+            return;
+        };
+        if self.add_subscriber(&subscriber) {
+            let state = *self;
+            with_scope_node(scope_id, |node| {
+                node.add_subscription(Box::new(move || state.unsubscribe(&subscriber)));
+            });
+        }
+    }
+
+    /// Add a subscriber, returning `false` if it was already subscribed or the
+    /// state was dropped
+    fn add_subscriber(&self, subscriber: &Subscriber) -> bool {
+        self.inner.try_read().is_ok_and(|inner_guard| {
+            let Ok(mut inner) = inner_guard.write() else {
+                return false;
+            };
+            let added = !inner.subscribers.contains(subscriber);
+            if added {
+                inner.subscribers.push(subscriber.clone());
+            }
+            added
+        })
+    }
+
+    /// Remove a subscriber, if this state still exists
+    fn unsubscribe(&self, subscriber: &Subscriber) {
+        if let Ok(inner_guard) = self.inner.try_read() {
+            if let Ok(mut inner) = inner_guard.write() {
+                inner.subscribers.retain(|s| s != subscriber);
+            }
+        }
+    }
+
+    /// Number of scopes subscribed to this state
+    pub fn subscriber_count(&self) -> usize {
         self.inner
             .try_read()
             .ok()
-            .and_then(|guard| guard.read().ok().map(|inner| inner.value.clone()))
-            .expect("State was dropped")
+            .and_then(|guard| guard.read().ok().map(|inner| inner.subscribers.len()))
+            .unwrap_or(0)
+    }
+
+    /// Get the value without subscribing (useful for event handlers)
+    pub fn get_untracked(&self) -> T {
+        self.read_value()
     }
 
     /// Set a new value and trigger recomposition of subscribed scopes
+    ///
+    /// Inside a mutable snapshot the value is applied, and the scopes
+    /// invalidated, when the snapshot ends.
     pub fn set(&self, value: T) {
-        let subscribers = {
-            let inner_guard = self.inner.try_read().expect("State was dropped");
-            let mut inner = inner_guard.write().unwrap();
-            inner.value = value;
-            inner.subscribers.clone()
-        };
-        Self::notify_subscribers_static(&subscribers);
+        let written = self.write(|current| *current = value, true);
+        assert!(written || polling_cancelled_effect(), "State was dropped");
     }
 
     /// Update the value using a function and trigger recomposition
     pub fn update(&self, f: impl FnOnce(&mut T)) {
-        let subscribers = {
-            let inner_guard = self.inner.try_read().expect("State was dropped");
-            let mut inner = inner_guard.write().unwrap();
-            f(&mut inner.value);
-            inner.subscribers.clone()
-        };
-        Self::notify_subscribers_static(&subscribers);
+        let written = self.write(f, true);
+        assert!(written || polling_cancelled_effect(), "State was dropped");
     }
 
     /// Modify without triggering recomposition (for batched updates)
     pub fn set_silent(&self, value: T) {
-        if let Ok(inner_guard) = self.inner.try_read() {
-            if let Ok(mut inner) = inner_guard.write() {
-                inner.value = value;
-            }
-        }
+        self.write(|current| *current = value, false);
     }
 
-    /// Notify all subscribed scopes that this state changed
-    fn notify_subscribers_static(subscribers: &HashSet<ScopeId>) {
-        if subscribers.is_empty() {
-            // No subscribers, fall back to global invalidation
-            mark_scope_dirty(ScopeId(0));
-        } else {
-            for scope_id in subscribers.iter() {
-                mark_scope_dirty(*scope_id);
+    /// Write the value, in the mutable snapshot open on this thread if there
+    /// is one. Returns `false` if the state was dropped.
+    fn write(&self, f: impl FnOnce(&mut T), notify: bool) -> bool {
+        match snapshot::write_mode() {
+            WriteMode::Buffered => {
+                let id = self.inner.id();
+                let pending = snapshot::pending_write(id, |write| {
+                    let notify = write
+                        .as_any()
+                        .downcast_ref::<StateWrite<T>>()
+                        .is_some_and(|write| write.notify);
+                    (write.base(), notify)
+                });
+                let (base, notified) = match pending {
+                    Some(pending) => pending,
+                    None => match self.version() {
+                        Some(version) => (version, false),
+                        None => return false,
+                    },
+                };
+                let mut value = self.read_value();
+                f(&mut value);
+                snapshot::buffer_write(
+                    id,
+                    Box::new(StateWrite {
+                        state: *self,
+                        base,
+                        value,
+                        notify: notify || notified,
+                    }),
+                );
+                true
+            }
+            WriteMode::Direct => {
+                let lock = self.write_lock();
+                let invalidated = snapshot::with_write_lock(lock.clone(), || {
+                    let inner_guard = self.inner.try_read().ok()?;
+                    let mut inner = inner_guard.write().unwrap();
+                    inner.keep_for_readers(lock.oldest_read());
+                    f(&mut inner.value);
+                    inner.version = lock.next_version();
+                    Some(if notify {
+                        inner.invalidations()
+                    } else {
+                        Vec::new()
+                    })
+                });
+                let Some(invalidated) = invalidated else {
+                    return false;
+                };
+                for subscriber in invalidated {
+                    subscriber.notify();
+                }
+                true
             }
         }
     }
@@ -595,6 +1580,34 @@ impl<T: Clone + Send + Sync + 'static> State<T> {
     }
 }
 
+// Writes to a state invalidate its readers, so groups it is passed to never
+// need to compare it
+impl<T: 'static> Stable for State<T> {}
+
+impl<T: Clone + Send + Sync + 'static> Dependency for State<T> {
+    fn key(&self) -> GenerationalBoxId {
+        self.inner.id()
+    }
+
+    fn observe(&self, observer: &Subscriber) {
+        self.add_subscriber(observer);
+    }
+
+    fn unobserve(&self, observer: &Subscriber) {
+        self.unsubscribe(observer);
+    }
+
+    fn is_pending(&self) -> bool {
+        snapshot::pending_write(self.inner.id(), |_| ()).is_some()
+            || self.inner.try_read().is_ok_and(|guard| {
+                guard.read().is_ok_and(|inner| {
+                    snapshot::read_version(|| inner.write_lock())
+                        .is_some_and(|read| inner.version > read)
+                })
+            })
+    }
+}
+
 impl<T: Clone + Send + Sync + Default + 'static> Default for State<T> {
     fn default() -> Self {
         Self::new(T::default())
@@ -617,62 +1630,69 @@ impl State<i32> {
 // ============================================================================
 
 /// Helper to create an implicit scope for a composable.
-/// Every composable automatically becomes a recomposition boundary.
+///
+/// The scope gets its own node in the composition tree, so values remembered
+/// inside it stay with this composable. Leaf composables don't store their
+/// content, so state read inside recomposes the nearest enclosing container.
+#[track_caller]
 pub fn with_implicit_scope<F, R>(content: F) -> R
 where
     F: FnOnce() -> R,
 {
-    let scope_id = ScopeId::new();
-    let parent_scope = current_scope_id();
-
-    // Enter this scope
-    enter_scope(scope_id);
+    composable_scope(ComposableType::Custom("Composable".to_string()), content)
+}
 
-    // Execute content
+/// Run `content` in a child group of the current scope for a composable of
+/// the given type
+#[track_caller]
+fn composable_scope<F, R>(composable_type: ComposableType, content: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let scope_id = start_group(composable_type, None);
     let result = content();
-
-    // Exit scope
-    exit_scope();
-
-    // Note: We don't register the scope here since leaf composables
-    // don't have stored content functions. For granular updates,
-    // the parent scope will be marked dirty instead.
-    let _ = parent_scope; // silence unused warning
-
+    end_group(scope_id);
     result
 }
 
 /// Helper to create a scoped container composable with stored content function.
 /// This enables granular recomposition - only this subtree rebuilds when its state changes.
-fn scoped_container<F>(container_entity: Entity, content: F)
-where
+#[track_caller]
+pub(crate) fn scoped_container<F>(
+    composable_type: ComposableType,
+    container_entity: Entity,
+    content: F,
+) where
     F: Fn() + Send + Sync + 'static,
 {
-    let scope_id = ScopeId::new();
-    let parent_scope = current_scope_id();
+    keyed_scoped_container(composable_type, None, container_entity, Arc::new(content));
+}
+
+/// Scoped container matched by `key` among its siblings, returning its scope
+#[track_caller]
+pub(crate) fn keyed_scoped_container(
+    composable_type: ComposableType,
+    key: Option<CompositionKey>,
+    container_entity: Entity,
+    content_fn: ScopedContentFn,
+) -> ScopeId {
+    let scope_id = start_group(composable_type, key);
 
     // Add scope marker to the container
-    COMPOSITION_CTX.with(|ctx| {
-        let ctx = ctx.borrow();
-        let commands = unsafe { &mut *ctx.commands };
-        commands
-            .entity(container_entity)
-            .insert(ScopeMarker(scope_id));
-    });
+    insert_emitted(container_entity, ScopeMarker(scope_id));
 
-    // Register the scope with its content function for later recomposition
-    let content_fn: ScopedContentFn = Arc::new(content);
-    register_scope(scope_id, content_fn.clone(), parent_scope);
+    // Store the content function on the scope's node for later recomposition
+    with_scope_node(scope_id, |node| node.content = Some(content_fn.clone()));
     set_scope_root_entity(scope_id, container_entity);
 
-    // Enter scope and compose content
+    // Compose content inside the container
     push_parent(container_entity);
-    enter_scope(scope_id);
 
     content_fn();
 
-    exit_scope();
     pop_parent();
+    end_group(scope_id);
+    scope_id
 }
 
 // Removed unstyled `Text` composable. Use the styled `Text(content, style: TextStyle)` instead.
@@ -686,8 +1706,9 @@ where
 /// ```ignore
 /// Text("Hello!", TextStyle::title().with_color(Color::WHITE));
 /// ```
+#[track_caller]
 pub fn Text(content: impl Into<String>, style: TextStyle) {
-    with_implicit_scope(|| {
+    composable_scope(ComposableType::Leaf(LeafType::Text), || {
         let content = content.into();
         spawn_child((
             bevy::prelude::Text::new(content),
@@ -705,18 +1726,18 @@ pub fn Text(content: impl Into<String>, style: TextStyle) {
 /// Button composable with modifier
 ///
 /// Button is automatically scoped - changes to state it reads only rebuild this button.
+/// The click handler can also take `&mut Commands` or `&mut World`; see
+/// `IntoOnClick`.
 ///
 /// # Example
 /// ```ignore
 /// Button("Submit", Modifier().background(Color::BLUE), || submit());
 /// ```
-pub fn Button<F>(label: impl Into<String>, modifier: Modifiers, on_click: F)
-where
-    F: Fn() + Send + Sync + 'static,
-{
-    with_implicit_scope(|| {
+#[track_caller]
+pub fn Button<M>(label: impl Into<String>, modifier: Modifiers, on_click: impl IntoOnClick<M>) {
+    composable_scope(ComposableType::Custom("Button".to_string()), || {
         let label = label.into();
-        let on_click = Arc::new(on_click);
+        let on_click = on_click.into_on_click();
 
         let mut node = Node {
             padding: UiRect::axes(Val::Px(16.0), Val::Px(8.0)),
@@ -762,6 +1783,7 @@ where
 ///     Text("Bottom");
 /// });
 /// ```
+#[track_caller]
 pub fn Spacer() {
     spawn_child((Node {
         flex_grow: 1.0,
@@ -770,6 +1792,7 @@ pub fn Spacer() {
 }
 
 /// Fixed-size spacer
+#[track_caller]
 pub fn FixedSpacer(size: f32) {
     spawn_child((Node {
         width: Val::Px(size),
@@ -802,6 +1825,7 @@ pub fn FixedSpacer(size: f32) {
 ///     }
 /// );
 /// ```
+#[track_caller]
 pub fn Column<F>(modifier: Modifiers, content: F)
 where
     F: Fn() + Send + Sync + 'static,
@@ -818,7 +1842,7 @@ where
 
     let column = spawn_child((node, bg));
 
-    scoped_container(column, content);
+    scoped_container(ComposableType::Layout(LayoutType::Column), column, content);
 }
 
 // Row now relies on `ModifierChain` for visual/layout properties
@@ -826,6 +1850,7 @@ where
 /// Row layout composable with automatic scoping.
 ///
 /// Row is automatically a recomposition boundary.
+#[track_caller]
 pub fn Row<F>(modifier: Modifiers, content: F)
 where
     F: Fn() + Send + Sync + 'static,
@@ -842,7 +1867,7 @@ where
 
     let row = spawn_child((node, bg));
 
-    scoped_container(row, content);
+    scoped_container(ComposableType::Layout(LayoutType::Row), row, content);
 }
 
 // Removed unstyled `Box`. Use the styled `Box(modifier, content)` instead.
@@ -850,6 +1875,7 @@ where
 /// Box layout composable with automatic scoping.
 ///
 /// Box is automatically a recomposition boundary.
+#[track_caller]
 pub fn Box<F>(modifier: Modifiers, content: F)
 where
     F: Fn() + Send + Sync + 'static,
@@ -865,7 +1891,70 @@ where
 
     let box_node = spawn_child((node, bg));
 
-    scoped_container(box_node, content);
+    scoped_container(ComposableType::Layout(LayoutType::Box), box_node, content);
+}
+
+/// Box composable whose content depends on the space the box gets
+///
+/// `content` receives the box's laid out size as `Constraints` (with a
+/// minimum of zero) and is recomposed whenever the size changes, so it can
+/// pick a different arrangement for narrow and wide boxes. Size the box
+/// independently of its content (e.g. with `fill_max_size`) so the content
+/// cannot feed back into the constraints.
+///
+/// Layout runs after composition, so a new size reaches the content one
+/// frame late. Until the box is first laid out, the content is composed with
+/// the laid out size of the box's parent, or else the size of the primary
+/// window, and with nothing if neither is known.
+///
+/// # Example
+/// ```ignore
+/// BoxWithConstraints(Modifiers::new().fill_max_size(), |constraints| {
+///     if constraints.max_width < 600.0 {
+///         Column(Modifiers::new(), || ListPane());
+///     } else {
+///         Row(Modifiers::new(), || {
+///             ListPane();
+///             DetailPane();
+///         });
+///     }
+/// });
+/// ```
+#[track_caller]
+pub fn BoxWithConstraints<F>(modifier: Modifiers, content: F)
+where
+    F: Fn(Constraints) + Send + Sync + 'static,
+{
+    composable_scope(
+        ComposableType::Custom("BoxWithConstraints".to_string()),
+        || {
+            let parent = get_current_parent();
+            let constraints = remember(|| {
+                State::new(try_with_world(|world| estimated_constraints(world, parent)).flatten())
+            });
+
+            let mut node = Node {
+                display: Display::Flex,
+                ..default()
+            };
+            modifier.apply_to_node(&mut node);
+
+            let mut bg = BackgroundColor(Color::NONE);
+            modifier.apply_to_background(&mut bg);
+
+            let box_node = spawn_child((node, bg, MeasuredConstraints { constraints }));
+
+            scoped_container(
+                ComposableType::Layout(LayoutType::Box),
+                box_node,
+                move || {
+                    if let Some(constraints) = constraints.get() {
+                        content(constraints);
+                    }
+                },
+            );
+        },
+    );
 }
 
 // ============================================================================
@@ -877,6 +1966,7 @@ where
 /// Surface composable with automatic scoping.
 ///
 /// Surface is typically the root of your UI and is automatically a recomposition boundary.
+#[track_caller]
 pub fn Surface<F>(modifier: Modifiers, content: F)
 where
     F: Fn() + Send + Sync + 'static,
@@ -895,7 +1985,11 @@ where
 
     let surface = spawn_child((node, bg));
 
-    scoped_container(surface, content);
+    scoped_container(
+        ComposableType::Custom("Surface".to_string()),
+        surface,
+        content,
+    );
 }
 
 // ============================================================================
@@ -915,17 +2009,74 @@ where
 ///     });
 /// });
 /// ```
+#[track_caller]
 pub fn ForEach<T, F>(items: &[T], content: F)
 where
     F: Fn(&T),
 {
     for item in items {
-        with_implicit_scope(|| {
+        composable_scope(ComposableType::Custom("ForEach".to_string()), || {
             content(item);
         });
     }
 }
 
+/// Iterates over items and composes content for each, identifying items by key.
+///
+/// Each item gets a stable scope keyed by `key_fn`, so when the list is
+/// reordered the item keeps its remembered state and its entities are moved
+/// rather than rebuilt. Items whose keys disappear are disposed. Keys must be
+/// unique within the list; duplicates fall back to positional identity.
+///
+/// # Example
+/// ```ignore
+/// Column(Modifiers::new(), move || {
+///     ForEachKeyed(&todos.get(), |todo| todo.id, |todo| {
+///         let editing = remember_state(false);
+///         Text(todo.title.clone(), TextStyle::body());
+///     });
+/// });
+/// ```
+#[track_caller]
+pub fn ForEachKeyed<T, K, KF, F>(items: &[T], key_fn: KF, content: F)
+where
+    K: Into<CompositionKey>,
+    KF: Fn(&T) -> K,
+    F: Fn(&T),
+{
+    let call_site = Some(CallSite::caller());
+    for item in items {
+        let key: CompositionKey = key_fn(item).into();
+        let item_type = ComposableType::Custom("ForEachKeyed".to_string());
+        let Some(scope_id) = enter_child_group(item_type.clone(), Some(key.clone()), call_site)
+        else {
+            warn!("ForEachKeyed: duplicate key {:?}", key);
+            composable_scope(item_type, || content(item));
+            continue;
+        };
+
+        let previous_key = set_frame_key(Some((key, 0)));
+
+        content(item);
+
+        end_group(scope_id);
+        set_frame_key(previous_key);
+    }
+}
+
+/// Set the list item key (and emission index) for entities emitted into the
+/// current parent, returning the key that was active before
+fn set_frame_key(key: Option<(CompositionKey, usize)>) -> Option<(CompositionKey, usize)> {
+    COMPOSITION_CTX.with(|ctx| {
+        let mut ctx = ctx.borrow_mut();
+        let frame = ctx.child_frames.last_mut()?;
+        let (key, key_index) = key.unzip();
+        let previous_index = std::mem::replace(&mut frame.key_index, key_index.unwrap_or(0));
+        let previous_key = std::mem::replace(&mut frame.key, key)?;
+        Some((previous_key, previous_index))
+    })
+}
+
 /// Conditional composition with automatic scoping.
 ///
 /// # Example
@@ -934,15 +2085,18 @@ where
 ///     Text("Hello!", TextStyle::body());
 /// });
 /// ```
+#[track_caller]
 pub fn If<F>(condition: bool, content: F)
 where
     F: FnOnce(),
 {
-    if condition {
-        with_implicit_scope(|| {
+    // The group is emitted even when the condition is false, so composables
+    // after it keep their position.
+    composable_scope(ComposableType::Custom("If".to_string()), || {
+        if condition {
             content();
-        });
-    }
+        }
+    });
 }
 
 /// Conditional composition with else branch and automatic scoping.
@@ -954,12 +2108,13 @@ where
 ///     || Text("Please log in", TextStyle::body()),
 /// );
 /// ```
+#[track_caller]
 pub fn IfElse<F1, F2>(condition: bool, if_true: F1, if_false: F2)
 where
     F1: FnOnce(),
     F2: FnOnce(),
 {
-    with_implicit_scope(|| {
+    composable_scope(ComposableType::Custom("IfElse".to_string()), || {
         if condition {
             if_true();
         } else {
@@ -1001,40 +2156,148 @@ pub struct ScopeMarker(pub ScopeId);
 ///     });
 /// });
 /// ```
+#[track_caller]
 pub fn Scope<F>(content: F)
 where
     F: Fn() + Send + Sync + 'static,
 {
-    let scope_id = ScopeId::new();
-    let parent_scope = current_scope_id();
+    // Create a minimal container node for this scope
+    let scope_container = spawn_child((Node {
+        display: Display::Flex,
+        flex_direction: FlexDirection::Column,
+        ..default()
+    },));
+
+    scoped_container(
+        ComposableType::Custom("Scope".to_string()),
+        scope_container,
+        content,
+    );
+}
 
-    // Wrap content in Arc for storage
-    let content_fn: ScopedContentFn = Arc::new(content);
+/// Reported when an `ErrorBoundary` catches an error from its content
+#[derive(Message, Debug, Clone)]
+pub struct CompositionErrorEvent {
+    /// Scope of the boundary that caught the error
+    pub scope: ScopeId,
+    pub error: CompositionError,
+}
 
-    // Register this scope with its content function
-    register_scope(scope_id, content_fn.clone(), parent_scope);
+/// Handle for clearing the error caught by an `ErrorBoundary`
+#[derive(Clone, Copy)]
+pub struct ErrorBoundaryReset(State<Option<CompositionError>>);
 
-    // Create a minimal container node for this scope
-    let scope_container = spawn_child((
-        Node {
-            display: Display::Flex,
-            flex_direction: FlexDirection::Column,
-            ..default()
+impl ErrorBoundaryReset {
+    /// Compose the boundary's content again instead of the fallback
+    pub fn reset(&self) {
+        self.0.set(None);
+    }
+}
+
+/// Error boundary composable
+///
+/// Composes `content`, catching panics and errors it returns. When content
+/// fails, whatever it emitted is discarded, `fallback` is composed in its
+/// place and the error is reported as a `CompositionErrorEvent`. The rest of
+/// the UI is composed as usual. The fallback stays until it calls `reset`.
+///
+/// # Example
+/// ```ignore
+/// ErrorBoundary(
+///     |error, retry| {
+///         Text(format!("Failed: {}", error), TextStyle::body());
+///         Button("Retry", Modifiers::new(), move || retry.reset());
+///     },
+///     move || -> Result<(), String> {
+///         let profile = load_profile()?;
+///         Text(profile.name, TextStyle::body());
+///         Ok(())
+///     },
+/// );
+/// ```
+#[track_caller]
+pub fn ErrorBoundary<FB, F, R>(fallback: FB, content: F)
+where
+    FB: Fn(&CompositionError, ErrorBoundaryReset) + Send + Sync + 'static,
+    F: Fn() -> R + Send + Sync + 'static,
+    R: IntoCompositionResult,
+{
+    let container = spawn_child((Node {
+        display: Display::Flex,
+        flex_direction: FlexDirection::Column,
+        ..default()
+    },));
+
+    scoped_container(
+        ComposableType::Custom("ErrorBoundary".to_string()),
+        container,
+        move || loop {
+            let error = remember_state(None::<CompositionError>);
+            if let Some(scope_id) = current_scope_id() {
+                let handler: ErrorHandler = Arc::new(move |caught: CompositionError| {
+                    report_composition_error(scope_id, caught.clone());
+                    error.set_silent(Some(caught));
+                });
+                with_scope_node(scope_id, |node| node.error_handler = Some(handler));
+            }
+            if let Some(caught) = error.get() {
+                fallback(&caught, ErrorBoundaryReset(error));
+                return;
+            }
+
+            let depths = stack_depths();
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                content().into_composition_result()
+            }))
+            .unwrap_or_else(|payload| Err(CompositionError::from_panic(payload)));
+            let Err(caught) = result else {
+                return;
+            };
+
+            // Drop the partial content and compose the fallback instead
+            unwind_to(depths);
+            if let Some(scope_id) = current_scope_id() {
+                report_composition_error(scope_id, caught.clone());
+            }
+            error.set_silent(Some(caught));
+            restart_current_scope();
         },
-        ScopeMarker(scope_id),
-    ));
+    );
+}
 
-    // Update scope registry with root entity
-    set_scope_root_entity(scope_id, scope_container);
+/// Recompose `scope_id` on its own with `recompose`.
+///
+/// If the scope panics and is inside an `ErrorBoundary`, the context is
+/// unwound, the error is handed to the nearest boundary and the boundary is
+/// recomposed in turn so its fallback replaces the failed content. Outside any
+/// boundary the panic continues.
+pub fn recompose_within_boundary(scope_id: ScopeId, recompose: &dyn Fn(ScopeId)) {
+    let Some(boundary) = with_tree(|tree| tree.error_boundary_of(scope_id)) else {
+        recompose(scope_id);
+        return;
+    };
 
-    // Enter this scope and compose content
-    push_parent(scope_container);
-    enter_scope(scope_id);
+    let depths = stack_depths();
+    let Err(payload) = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        recompose(scope_id);
+    })) else {
+        return;
+    };
 
-    content_fn();
+    unwind_to(depths);
+    let handler = with_tree(|tree| tree.get(boundary)?.error_handler.clone());
+    if let Some(handler) = handler {
+        handler(CompositionError::from_panic(payload));
+    }
+    recompose_within_boundary(boundary, recompose);
+}
 
-    exit_scope();
-    pop_parent();
+/// Log a caught composition error and send it as a `CompositionErrorEvent`
+fn report_composition_error(scope: ScopeId, error: CompositionError) {
+    error!("error boundary {:?} caught: {}", scope, error);
+    try_with_commands(|commands| {
+        commands.write_message(CompositionErrorEvent { scope, error });
+    });
 }
 
 /// Scoped state wrapper (legacy - prefer using State directly in any composable).
@@ -1042,6 +2305,7 @@ where
 /// Since all composables are now automatically scoped, you can simply use
 /// State<T> directly and it will automatically subscribe to the current scope.
 #[deprecated(note = "All composables are now automatically scoped. Use State<T> directly.")]
+#[track_caller]
 pub fn ScopedState<T, F>(initial: T, content: F)
 where
     T: Clone + Send + Sync + 'static,
@@ -1054,3 +2318,373 @@ where
         content(state);
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn remember_keeps_its_value_across_recompositions() {
+        let inits = Arc::new(AtomicUsize::new(0));
+        let seen = Captured::new();
+        let mut app = compose_app({
+            let inits = inits.clone();
+            let seen = seen.clone();
+            move || {
+                let value = remember(|| inits.fetch_add(1, Ordering::SeqCst));
+                seen.set(value);
+            }
+        });
+
+        recompose_root(&mut app);
+        recompose_root(&mut app);
+
+        assert_eq!(inits.load(Ordering::SeqCst), 1);
+        assert_eq!(seen.get(), 0);
+    }
+
+    #[test]
+    fn remember_is_positional_within_a_scope() {
+        let seen = Captured::new();
+        let mut app = compose_app({
+            let seen = seen.clone();
+            move || {
+                let first = remember(|| "first");
+                let second = remember(|| "second");
+                seen.set((first, second));
+            }
+        });
+        recompose_root(&mut app);
+
+        assert_eq!(seen.get(), ("first", "second"));
+    }
+
+    #[test]
+    fn remember_state_survives_scope_recomposition() {
+        let count = Captured::new();
+        let mut app = compose_app({
+            let count = count.clone();
+            move || {
+                let count = count.clone();
+                Column(Modifiers::new(), move || {
+                    let state = remember_state(0);
+                    count.set(state);
+                    Text(format!("count {}", state.get()), TextStyle::body());
+                });
+            }
+        });
+        assert_eq!(texts(&mut app), ["count 0"]);
+
+        count.get().set(1);
+        app.update();
+        count.get().update(|count| *count += 1);
+        app.update();
+
+        assert_eq!(texts(&mut app), ["count 2"]);
+    }
+
+    #[test]
+    fn recomposition_patches_entities_in_place() {
+        let label = Captured::new();
+        let mut app = compose_app({
+            let label = label.clone();
+            move || {
+                let label = label.clone();
+                Column(Modifiers::new(), move || {
+                    let state = remember_state("before");
+                    label.set(state);
+                    Text(state.get(), TextStyle::body());
+                    Button("Press", Modifiers::new(), || {});
+                });
+            }
+        });
+        let before = entities::<Node>(&mut app);
+
+        label.get().set("after");
+        app.update();
+
+        assert_eq!(texts(&mut app), ["after", "Press"]);
+        assert_eq!(entities::<Node>(&mut app), before);
+    }
+
+    #[test]
+    fn reused_entity_loses_components_it_is_no_longer_given() {
+        let scoped = Captured::new();
+        let mut app = compose_app({
+            let scoped = scoped.clone();
+            move || {
+                let scoped = scoped.clone();
+                Column(Modifiers::new(), move || {
+                    let state = remember_state(true);
+                    scoped.set(state);
+                    // Patched in place: a Column's bundle without its scope
+                    IfElse(
+                        state.get(),
+                        || Column(Modifiers::new(), || {}),
+                        || {
+                            spawn_child((Node::default(), BackgroundColor(Color::NONE)));
+                        },
+                    );
+                });
+            }
+        });
+        let before = entities::<Node>(&mut app);
+        assert_eq!(count::<ScopeMarker>(&mut app), 2);
+
+        scoped.get().set(false);
+        app.update();
+
+        assert_eq!(entities::<Node>(&mut app), before);
+        assert_eq!(count::<ScopeMarker>(&mut app), 1);
+    }
+
+    #[test]
+    fn removed_subtrees_release_their_scopes_states_and_entities() {
+        let shown = Captured::new();
+        let inner = Captured::new();
+        let mut app = compose_app({
+            let shown = shown.clone();
+            let inner = inner.clone();
+            move || {
+                let state = remember_state(false);
+                shown.set(state);
+                let inner = inner.clone();
+                If(state.get(), move || {
+                    Column(Modifiers::new(), {
+                        let inner = inner.clone();
+                        move || {
+                            let count = remember_state(1);
+                            inner.set(count);
+                            Text(count.get().to_string(), TextStyle::body());
+                        }
+                    });
+                });
+            }
+        });
+        let hidden_stats = stats(&app);
+        let hidden_entities = count::<Node>(&mut app);
+
+        shown.get().set(true);
+        app.update();
+        assert_eq!(texts(&mut app), ["1"]);
+        assert!(stats(&app).nodes > hidden_stats.nodes);
+
+        shown.get().set(false);
+        app.update();
+
+        assert_eq!(stats(&app), hidden_stats);
+        assert_eq!(count::<Node>(&mut app), hidden_entities);
+        assert!(inner.get().try_read_value().is_none());
+    }
+
+    /// Column of keyed items, each remembering a state that starts at ten
+    /// times its item
+    fn keyed_items(
+        items: Captured<State<Vec<u32>>>,
+        item_states: Captured<Vec<State<u32>>>,
+    ) -> App {
+        compose_app(move || {
+            let items = items.clone();
+            let item_states = item_states.clone();
+            Column(Modifiers::new(), move || {
+                let list = remember_state(vec![1, 2, 3]);
+                items.set(list);
+                let states = Arc::new(std::sync::Mutex::new(Vec::new()));
+                ForEachKeyed(
+                    &list.get(),
+                    |item| *item,
+                    |item| {
+                        let state = remember_state(item * 10);
+                        states.lock().unwrap().push(state);
+                        Text(format!("{item}:{}", state.get()), TextStyle::body());
+                    },
+                );
+                item_states.set(states.lock().unwrap().clone());
+            });
+        })
+    }
+
+    #[test]
+    fn keyed_items_keep_state_and_entities_across_reorders() {
+        let items = Captured::new();
+        let item_states = Captured::new();
+        let mut app = keyed_items(items.clone(), item_states.clone());
+        item_states.get()[1].set(99);
+        app.update();
+        let before = entities::<bevy::prelude::Text>(&mut app);
+
+        items.get().set(vec![3, 2, 1]);
+        app.update();
+
+        assert_eq!(texts(&mut app), ["3:30", "2:99", "1:10"]);
+        assert_eq!(entities::<bevy::prelude::Text>(&mut app), before);
+    }
+
+    #[test]
+    fn removed_keys_are_disposed() {
+        let items = Captured::new();
+        let item_states = Captured::new();
+        let mut app = keyed_items(items.clone(), item_states.clone());
+        let removed = item_states.get()[1];
+
+        items.get().set(vec![1, 3]);
+        app.update();
+
+        assert_eq!(texts(&mut app), ["1:10", "3:30"]);
+        assert_eq!(count::<bevy::prelude::Text>(&mut app), 2);
+        assert!(removed.try_read_value().is_none());
+
+        items.get().set(vec![2, 1, 3]);
+        app.update();
+
+        assert_eq!(texts(&mut app), ["2:20", "1:10", "3:30"]);
+    }
+
+    #[test]
+    fn remember_outside_composition_runs_init_every_time() {
+        let inits = AtomicUsize::new(0);
+        remember(|| inits.fetch_add(1, Ordering::SeqCst));
+        remember(|| inits.fetch_add(1, Ordering::SeqCst));
+
+        assert_eq!(inits.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn commands_are_only_reachable_during_the_pass() {
+        let mut world = World::new();
+
+        assert!(try_with_commands(|_| ()).is_none());
+        let spawned = with_composition_world(&mut world, || {
+            let nested = with_commands(|_| try_with_commands(|_| ()));
+            assert!(nested.is_none());
+            with_commands(|commands| commands.spawn_empty().id())
+        });
+        assert!(try_with_commands(|_| ()).is_none());
+
+        assert!(world.get_entity(spawned).is_ok());
+    }
+
+    #[test]
+    fn the_world_is_moved_back_when_the_pass_panics() {
+        let mut world = World::new();
+        let kept = world.spawn_empty().id();
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            with_composition_world(&mut world, || panic!("composable panicked"))
+        }));
+
+        assert!(result.is_err());
+        assert!(try_with_commands(|_| ()).is_none());
+        assert!(world.get_entity(kept).is_ok());
+    }
+
+    fn error_messages(app: &App) -> Vec<String> {
+        let messages = app.world().resource::<Messages<CompositionErrorEvent>>();
+        messages
+            .get_cursor()
+            .read(messages)
+            .map(|message| message.error.to_string())
+            .collect()
+    }
+
+    /// Boundary whose content panics while `fail` is set, followed by a
+    /// sibling text
+    fn failing_app(fail: Captured<State<bool>>, reset: Captured<ErrorBoundaryReset>) -> App {
+        compose_app(move || {
+            let fail = fail.clone();
+            let reset = reset.clone();
+            Column(Modifiers::new(), move || {
+                let failing = remember_state(true);
+                fail.set(failing);
+                let reset = reset.clone();
+                ErrorBoundary(
+                    move |error, retry| {
+                        reset.set(retry);
+                        Text(error.to_string(), TextStyle::body());
+                    },
+                    move || {
+                        if failing.get() {
+                            panic!("boom");
+                        }
+                        Text("ok", TextStyle::body());
+                    },
+                );
+                Text("after", TextStyle::body());
+            });
+        })
+    }
+
+    #[test]
+    fn error_boundary_composes_the_fallback_when_content_panics() {
+        let mut app = failing_app(Captured::new(), Captured::new());
+
+        assert_eq!(texts(&mut app), ["composition panicked: boom", "after"]);
+        assert_eq!(error_messages(&app), ["composition panicked: boom"]);
+    }
+
+    #[test]
+    fn error_boundary_composes_the_content_again_after_reset() {
+        let fail = Captured::new();
+        let reset = Captured::new();
+        let mut app = failing_app(fail.clone(), reset.clone());
+
+        fail.get().set(false);
+        reset.get().reset();
+        app.update();
+
+        assert_eq!(texts(&mut app), ["ok", "after"]);
+    }
+
+    #[test]
+    fn error_boundary_catches_returned_errors() {
+        let mut app = compose_app(|| {
+            ErrorBoundary(
+                |error, _| Text(error.to_string(), TextStyle::body()),
+                || -> Result<(), String> {
+                    Text("partial", TextStyle::body());
+                    Err("no profile".to_string())
+                },
+            );
+        });
+
+        assert_eq!(texts(&mut app), ["composition failed: no profile"]);
+    }
+
+    #[test]
+    fn error_boundary_catches_panics_while_recomposing_a_nested_scope() {
+        let count = Captured::new();
+        let mut app = compose_app({
+            let count = count.clone();
+            move || {
+                let count = count.clone();
+                Column(Modifiers::new(), move || {
+                    let count = count.clone();
+                    ErrorBoundary(
+                        |error, _| Text(error.to_string(), TextStyle::body()),
+                        move || {
+                            let count = count.clone();
+                            Scope(move || {
+                                let value = remember_state(0);
+                                count.set(value);
+                                if value.get() > 0 {
+                                    panic!("too many");
+                                }
+                                Text(value.get().to_string(), TextStyle::body());
+                            });
+                        },
+                    );
+                    Text("after", TextStyle::body());
+                });
+            }
+        });
+        assert_eq!(texts(&mut app), ["0", "after"]);
+
+        count.get().set(1);
+        app.update();
+
+        assert_eq!(texts(&mut app), ["composition panicked: too many", "after"]);
+        assert_eq!(error_messages(&app), ["composition panicked: too many"]);
+    }
+}
@@ -0,0 +1,475 @@
+//! Compose Roots
+//!
+//! Mounts reactive compositions under entities of an existing Bevy app. A
+//! `ComposeRoot` component composes its content into the children of the
+//! entity it is added to; the composition recomposes like the content of a
+//! `BecomposeApp` and is torn down when the component is removed or the
+//! entity is despawned.
+//!
+//! Each root has its own root scope, so invalidating one root recomposes only
+//! that root. A root can target a camera, or a window it then spawns a camera
+//! for; a root whose window closes is despawned with it.
+
+use bevy::camera::RenderTarget;
+use bevy::ecs::system::SystemState;
+use bevy::prelude::*;
+use bevy::ui::UiTargetCamera;
+use bevy::window::WindowRef;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::app::{compose_pass, recompose_scope};
+use super::composables::{unmount_scope, ScopeId};
+use super::{BecomposePlugin, ComposeRuntime};
+use crate::composition::{
+    CallSite, ComposableType, CompositionNode, CompositionTree, GroupKey, ScopedContentFn,
+};
+
+/// Mounts a reactive composition under the entity it is added to
+///
+/// Replacing the component recomposes the entity with the new content and
+/// moves it to the new target.
+///
+/// # Example
+/// ```ignore
+/// fn open_pause_menu(mut commands: Commands) {
+///     commands.spawn((
+///         Node { width: Val::Percent(100.0), height: Val::Percent(100.0), ..default() },
+///         ComposeRoot::new(|| PauseMenu()),
+///     ));
+/// }
+/// ```
+#[derive(Component, Clone)]
+pub struct ComposeRoot {
+    content: ScopedContentFn,
+    target: ComposeTarget,
+    call_site: CallSite,
+}
+
+/// Where a compose root is rendered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ComposeTarget {
+    /// The default UI camera
+    #[default]
+    Default,
+    /// An existing camera
+    Camera(Entity),
+    /// A window, rendered by a camera spawned for the root
+    Window(Entity),
+}
+
+impl ComposeRoot {
+    #[track_caller]
+    pub fn new<F>(content: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        Self {
+            content: Arc::new(content),
+            target: ComposeTarget::Default,
+            call_site: CallSite::caller(),
+        }
+    }
+
+    /// Render the root with `camera`
+    pub fn target_camera(mut self, camera: Entity) -> Self {
+        self.target = ComposeTarget::Camera(camera);
+        self
+    }
+
+    /// Render the root in `window`; the root is despawned when the window
+    /// closes
+    pub fn window(mut self, window: Entity) -> Self {
+        self.target = ComposeTarget::Window(window);
+        self
+    }
+
+    pub fn target(&self) -> ComposeTarget {
+        self.target
+    }
+}
+
+/// A mounted compose root
+struct MountedRoot {
+    scope: ScopeId,
+    /// Where the root is rendered
+    target: ComposeTarget,
+    /// Camera spawned for the root's window
+    camera: Option<Entity>,
+}
+
+impl MountedRoot {
+    /// Render the root with `target`, replacing the camera spawned for its
+    /// previous window and the camera its host entity targets
+    fn retarget(&mut self, commands: &mut Commands, entity: Entity, target: ComposeTarget) {
+        if self.target == target {
+            return;
+        }
+        if let Some(camera) = self.camera.take() {
+            commands.entity(camera).try_despawn();
+        }
+
+        let camera = match target {
+            ComposeTarget::Default => None,
+            ComposeTarget::Camera(camera) => Some(camera),
+            ComposeTarget::Window(window) => {
+                let camera = commands
+                    .spawn((
+                        Camera2d,
+                        Camera {
+                            target: RenderTarget::Window(WindowRef::Entity(window)),
+                            ..default()
+                        },
+                    ))
+                    .id();
+                self.camera = Some(camera);
+                Some(camera)
+            }
+        };
+        match camera {
+            Some(camera) => commands.entity(entity).insert(UiTargetCamera(camera)),
+            None => commands.entity(entity).remove::<UiTargetCamera>(),
+        };
+        self.target = target;
+    }
+}
+
+/// Scopes of the mounted compose roots, by host entity
+#[derive(Resource, Default)]
+pub struct ComposeRoots {
+    roots: HashMap<Entity, MountedRoot>,
+}
+
+impl ComposeRoots {
+    /// Get the root scope composed under `entity`
+    pub fn scope_of(&self, entity: Entity) -> Option<ScopeId> {
+        self.roots.get(&entity).map(|root| root.scope)
+    }
+
+    /// Recompose the root composed under `entity`, leaving other roots alone
+    pub fn invalidate(&self, runtime: &ComposeRuntime, entity: Entity) {
+        if let Some(scope_id) = self.scope_of(entity) {
+            runtime.mark_dirty(scope_id);
+        }
+    }
+
+    /// Number of mounted compose roots
+    pub fn len(&self) -> usize {
+        self.roots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+}
+
+/// Mount compositions in an existing app
+pub trait ComposeRootAppExt {
+    /// Spawn a full-size UI node composing `content`.
+    ///
+    /// Adds `BecomposePlugin` if it is not added yet.
+    fn add_compose_root<F>(&mut self, content: F) -> &mut Self
+    where
+        F: Fn() + Send + Sync + 'static;
+}
+
+impl ComposeRootAppExt for App {
+    fn add_compose_root<F>(&mut self, content: F) -> &mut Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        if !self.is_plugin_added::<BecomposePlugin>() {
+            self.add_plugins(BecomposePlugin);
+        }
+        self.world_mut().spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            ComposeRoot::new(content),
+        ));
+        self
+    }
+}
+
+/// System that mounts new compose roots, recomposes replaced ones and tears
+/// down those that were removed
+///
+/// Roots whose window was closed are despawned, and torn down once their
+/// removal is seen. A replaced root whose target changed drops the camera
+/// spawned for its previous window.
+#[allow(clippy::type_complexity)]
+pub(crate) fn mount_compose_roots(
+    world: &mut World,
+    params: &mut SystemState<(
+        Commands,
+        Query<(Entity, &ComposeRoot), Changed<ComposeRoot>>,
+        RemovedComponents<ComposeRoot>,
+        RemovedComponents<Window>,
+        ResMut<ComposeRoots>,
+        ResMut<CompositionTree>,
+    )>,
+) {
+    let (mut commands, roots, mut removed, mut closed_windows, mut mounted, mut tree) =
+        params.get_mut(world);
+
+    for window in closed_windows.read() {
+        for (&entity, _) in mounted
+            .roots
+            .iter()
+            .filter(|(_, root)| root.target == ComposeTarget::Window(window))
+        {
+            commands.entity(entity).try_despawn();
+        }
+    }
+
+    let unmounted: Vec<(Entity, MountedRoot)> = removed
+        .read()
+        .filter_map(|entity| Some((entity, mounted.roots.remove(&entity)?)))
+        .collect();
+    for camera in unmounted.iter().filter_map(|(_, root)| root.camera) {
+        commands.entity(camera).try_despawn();
+    }
+
+    let mut changed = Vec::new();
+    for (entity, root) in &roots {
+        let mounted_root = match mounted.roots.entry(entity) {
+            Entry::Occupied(entry) => {
+                let mounted_root = entry.into_mut();
+                if let Some(node) = tree.get_mut(mounted_root.scope) {
+                    node.content = Some(root.content.clone());
+                }
+                mounted_root
+            }
+            Entry::Vacant(entry) => {
+                let mut node =
+                    CompositionNode::new(ComposableType::Custom("ComposeRoot".to_string()));
+                node.content = Some(root.content.clone());
+                node.entity = Some(entity);
+                node.call_site = Some(root.call_site);
+                node.group_key = GroupKey::ROOT.child(Some(root.call_site), None, 0);
+                let scope_id = tree.insert(node);
+                // The host entity belongs to the app, so it is not linked to
+                // the node and outlives it
+                tree.new_nodes.retain(|id| *id != scope_id);
+
+                entry.insert(MountedRoot {
+                    scope: scope_id,
+                    target: ComposeTarget::Default,
+                    camera: None,
+                })
+            }
+        };
+        mounted_root.retarget(&mut commands, entity, root.target);
+        changed.push(mounted_root.scope);
+    }
+    params.apply(world);
+
+    if !unmounted.is_empty() {
+        compose_pass(world, || {
+            for (entity, root) in &unmounted {
+                unmount_scope(root.scope, *entity);
+            }
+        });
+    }
+    for scope_id in changed {
+        compose_pass(world, || recompose_scope(scope_id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bevy_integration::{remember_state, State, Text};
+    use crate::components::TextStyle;
+    use crate::testing::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Content showing a remembered counter
+    fn counter(captured: Captured<State<i32>>) -> impl Fn() + Send + Sync + 'static {
+        move || {
+            let value = remember_state(0);
+            captured.set(value);
+            Text(value.get().to_string(), TextStyle::body());
+        }
+    }
+
+    fn root_host(app: &mut App) -> Entity {
+        let world = app.world_mut();
+        world
+            .query_filtered::<Entity, With<ComposeRoot>>()
+            .single(world)
+            .unwrap()
+    }
+
+    /// Texts composed under `host`, in tree order
+    fn texts_under(app: &mut App, host: Entity) -> Vec<String> {
+        fn collect(world: &World, entity: Entity, out: &mut Vec<String>) {
+            if let Some(text) = world.get::<bevy::prelude::Text>(entity) {
+                out.push(text.0.clone());
+            }
+            for &child in world.get::<Children>(entity).into_iter().flatten() {
+                collect(world, child, out);
+            }
+        }
+
+        let mut out = Vec::new();
+        collect(app.world(), host, &mut out);
+        out
+    }
+
+    #[test]
+    fn compose_roots_mount_into_an_existing_app() {
+        let mut app = compose_app_with(
+            |app| {
+                app.add_compose_root(|| Text("menu", TextStyle::body()));
+            },
+            || {},
+        );
+
+        let host = root_host(&mut app);
+        assert_eq!(texts_under(&mut app, host), ["menu"]);
+        assert_eq!(app.world().resource::<ComposeRoots>().len(), 1);
+    }
+
+    #[test]
+    fn compose_roots_recompose_their_content() {
+        let counter_state = Captured::new();
+        let mut app = compose_app_with(
+            |app| {
+                app.add_compose_root(counter(counter_state.clone()));
+            },
+            || {},
+        );
+        let host = root_host(&mut app);
+
+        counter_state.get().set(1);
+        app.update();
+        assert_eq!(texts_under(&mut app, host), ["1"]);
+
+        app.world_mut()
+            .entity_mut(host)
+            .insert(ComposeRoot::new(|| Text("replaced", TextStyle::body())));
+        app.update();
+        assert_eq!(texts_under(&mut app, host), ["replaced"]);
+    }
+
+    #[test]
+    fn despawned_compose_roots_are_torn_down() {
+        let counter_state = Captured::new();
+        let mut app = compose_app(|| {});
+        let empty = stats(&app);
+        app.add_compose_root(counter(counter_state.clone()));
+        app.update();
+
+        let host = root_host(&mut app);
+        app.world_mut().entity_mut(host).despawn();
+        app.update();
+
+        assert!(app.world().resource::<ComposeRoots>().is_empty());
+        assert_eq!(stats(&app), empty);
+        assert_eq!(count::<bevy::prelude::Text>(&mut app), 0);
+        assert!(counter_state.get().try_read_value().is_none());
+    }
+
+    #[test]
+    fn removing_the_component_keeps_the_host_entity() {
+        let mut app = compose_app_with(
+            |app| {
+                app.add_compose_root(|| Text("menu", TextStyle::body()));
+            },
+            || {},
+        );
+
+        let host = root_host(&mut app);
+        app.world_mut().entity_mut(host).remove::<ComposeRoot>();
+        app.update();
+
+        assert!(app.world().get_entity(host).is_ok());
+        assert!(texts_under(&mut app, host).is_empty());
+        assert!(app.world().resource::<ComposeRoots>().is_empty());
+    }
+
+    /// Content showing a remembered counter, counting its runs
+    fn counted(
+        captured: Captured<State<i32>>,
+        runs: Arc<AtomicUsize>,
+    ) -> impl Fn() + Send + Sync + 'static {
+        let content = counter(captured);
+        move || {
+            runs.fetch_add(1, Ordering::SeqCst);
+            content();
+        }
+    }
+
+    #[test]
+    fn invalidating_one_root_leaves_the_others_alone() {
+        let (first, second) = (Captured::new(), Captured::new());
+        let first_runs = Arc::new(AtomicUsize::new(0));
+        let second_runs = Arc::new(AtomicUsize::new(0));
+        let mut app = compose_app_with(
+            |app| {
+                app.add_compose_root(counted(first.clone(), first_runs.clone()))
+                    .add_compose_root(counted(second.clone(), second_runs.clone()));
+            },
+            || {},
+        );
+
+        first.get().set(1);
+        app.update();
+
+        assert_eq!(first_runs.load(Ordering::SeqCst), 2);
+        assert_eq!(second_runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn retargeted_roots_drop_the_camera_of_their_previous_window() {
+        let mut app = compose_app(|| {});
+        let window = app.world_mut().spawn(Window::default()).id();
+        let camera = app.world_mut().spawn(Camera2d).id();
+        let host = app
+            .world_mut()
+            .spawn((Node::default(), ComposeRoot::new(|| {}).window(window)))
+            .id();
+        app.update();
+        let window_camera = app.world().get::<UiTargetCamera>(host).unwrap().0;
+        assert_ne!(window_camera, camera);
+
+        app.world_mut()
+            .entity_mut(host)
+            .insert(ComposeRoot::new(|| {}).target_camera(camera));
+        app.update();
+        assert!(app.world().get_entity(window_camera).is_err());
+        assert_eq!(app.world().get::<UiTargetCamera>(host).unwrap().0, camera);
+
+        app.world_mut()
+            .entity_mut(host)
+            .insert(ComposeRoot::new(|| {}));
+        app.update();
+        assert!(app.world().get::<UiTargetCamera>(host).is_none());
+        assert!(app.world().get_entity(camera).is_ok());
+    }
+
+    #[test]
+    fn roots_are_despawned_with_their_window() {
+        let mut app = compose_app(|| {});
+        let window = app.world_mut().spawn(Window::default()).id();
+        let host = app
+            .world_mut()
+            .spawn((Node::default(), ComposeRoot::new(|| {}).window(window)))
+            .id();
+        app.update();
+        let window_camera = app.world().get::<UiTargetCamera>(host).unwrap().0;
+
+        app.world_mut().entity_mut(window).despawn();
+        app.update();
+        app.update();
+
+        assert!(app.world().get_entity(host).is_err());
+        assert!(app.world().get_entity(window_camera).is_err());
+        assert!(app.world().resource::<ComposeRoots>().is_empty());
+    }
+}
@@ -0,0 +1,292 @@
+//! Launched Effects
+//!
+//! Run futures tied to a scope of the composition. `launched_effect` spawns
+//! its future on a Bevy task pool the first time its call composes and again
+//! whenever its key changes, cancelling the previous run. The running future
+//! is cancelled when its scope leaves the composition.
+//!
+//! Futures can write `State` directly: writes from a task are applied at once
+//! and recompose the scopes reading the state on the next frame.
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use bevy::tasks::{AsyncComputeTaskPool, IoTaskPool, Task, TaskPool};
+
+use super::composables::{current_scope_id, remember, with_scope_node};
+
+/// Task pool a launched effect runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum EffectPool {
+    Compute,
+    Io,
+}
+
+impl EffectPool {
+    fn spawn(self, future: impl Future<Output = ()> + Send + 'static) -> Task<()> {
+        match self {
+            EffectPool::Compute => {
+                AsyncComputeTaskPool::get_or_init(TaskPool::default).spawn(future)
+            }
+            EffectPool::Io => IoTaskPool::get_or_init(TaskPool::default).spawn(future),
+        }
+    }
+}
+
+/// Future of a launched effect that stops once cancelled.
+///
+/// The `cancelled` flag is checked before each poll, so the future is not
+/// polled again once its effect is cancelled. No lock is held while polling:
+/// the future may write states, which takes the runtime's write lock, and
+/// waiting on it from a poll could deadlock with the frame applying writes.
+struct Cancellable {
+    future: Pin<Box<dyn Future<Output = ()> + Send>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+thread_local! {
+    /// Cancellation flag of the effect being polled on this thread
+    static POLLING: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+/// Whether this thread is polling an effect that was cancelled during the
+/// poll. Its writes to states its scope dropped are ignored.
+pub(crate) fn polling_cancelled_effect() -> bool {
+    POLLING.with(|polling| {
+        polling
+            .borrow()
+            .as_ref()
+            .is_some_and(|cancelled| cancelled.load(Ordering::Acquire))
+    })
+}
+
+impl Future for Cancellable {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.cancelled.load(Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+        let previous = POLLING.with(|polling| polling.replace(Some(self.cancelled.clone())));
+        let poll = self.future.as_mut().poll(cx);
+        POLLING.with(|polling| *polling.borrow_mut() = previous);
+        poll
+    }
+}
+
+/// The running future of a `launched_effect` call and the key it was
+/// launched with
+struct Launched<K> {
+    key: K,
+    cancelled: Arc<AtomicBool>,
+    task: Task<()>,
+}
+
+impl<K> Launched<K> {
+    /// Stop polling the future and drop the task, which drops the future
+    /// unless a poll is in progress on another thread, in which case it is
+    /// dropped once that poll returns
+    fn cancel(self) {
+        self.cancelled.store(true, Ordering::Release);
+        drop(self.task);
+    }
+}
+
+type EffectSlot<K> = Arc<Mutex<Option<Launched<K>>>>;
+
+/// Spawn the future made by `make` on `pool` unless the current call already
+/// runs one launched with `key`
+pub(super) fn launch<K, F>(pool: EffectPool, key: K, make: impl FnOnce() -> F)
+where
+    K: PartialEq + Send + Sync + 'static,
+    F: Future<Output = ()> + Send + 'static,
+{
+    // Each call remembers its slot, which is emptied when the scope is
+    // disposed
+    let slot: EffectSlot<K> = remember(|| {
+        let slot: EffectSlot<K> = Arc::new(Mutex::new(None));
+        if let Some(scope_id) = current_scope_id() {
+            let slot = slot.clone();
+            with_scope_node(scope_id, |node| {
+                node.add_disposal(Box::new(move || {
+                    if let Some(launched) = slot.lock().unwrap().take() {
+                        launched.cancel();
+                    }
+                }));
+            });
+        }
+        slot
+    });
+
+    let mut slot = slot.lock().unwrap();
+    if slot.as_ref().is_some_and(|launched| launched.key == key) {
+        return;
+    }
+    if let Some(previous) = slot.take() {
+        previous.cancel();
+    }
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let task = pool.spawn(Cancellable {
+        future: Box::pin(make()),
+        cancelled: cancelled.clone(),
+    });
+    *slot = Some(Launched {
+        key,
+        cancelled,
+        task,
+    });
+}
+
+/// Run `future` on the `AsyncComputeTaskPool` while the current scope is in
+/// the composition.
+///
+/// The future is spawned the first time the call composes. When a
+/// recomposition passes a different `key`, the running future is cancelled
+/// and the new one is spawned; with the same key, the new future is dropped
+/// unpolled. The future is cancelled when the scope leaves the composition.
+///
+/// Cancellation takes effect at the future's next `.await`: a poll already
+/// running on another thread finishes, but the future is not polled again,
+/// and the poll's writes to states its scope dropped are ignored. The future
+/// can set states to recompose the scopes reading them.
+///
+/// # Example
+/// ```ignore
+/// let query = remember_state(String::new());
+/// let results = remember_state(Vec::new());
+/// let text = query.get();
+/// launched_effect(text.clone(), async move {
+///     // Debounce: a new query cancels this one while it sleeps
+///     sleep(Duration::from_millis(300)).await;
+///     results.set(search(&text));
+/// });
+/// ```
+pub fn launched_effect<K, F>(key: K, future: F)
+where
+    K: PartialEq + Send + Sync + 'static,
+    F: Future<Output = ()> + Send + 'static,
+{
+    launch(EffectPool::Compute, key, || future);
+}
+
+/// Like `launched_effect`, but runs `future` on the `IoTaskPool`, for effects
+/// that mostly wait on files or the network.
+///
+/// # Example
+/// ```ignore
+/// let save = remember_state(None);
+/// launched_io_effect(slot_name.clone(), async move {
+///     save.set(load_save(&slot_name).await.ok());
+/// });
+/// ```
+pub fn launched_io_effect<K, F>(key: K, future: F)
+where
+    K: PartialEq + Send + Sync + 'static,
+    F: Future<Output = ()> + Send + 'static,
+{
+    launch(EffectPool::Io, key, || future);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bevy_integration::{remember_state, Column, Scope, State, Text};
+    use crate::components::TextStyle;
+    use crate::modifier::Modifiers;
+    use crate::testing::*;
+    use bevy::prelude::*;
+
+    /// Effect that waits forever while holding a flag set when it is dropped
+    fn pending_effect(key: u32, dropped: &Arc<AtomicBool>) {
+        let flag = DropFlag(dropped.clone());
+        launched_effect(key, async move {
+            let _flag = flag;
+            std::future::pending::<()>().await;
+        });
+    }
+
+    #[test]
+    fn effects_write_states_that_recompose_their_readers() {
+        let mut app = compose_app(|| {
+            let loaded = remember_state(String::from("loading"));
+            launched_effect((), async move { loaded.set("done".to_string()) });
+            Scope(move || Text(loaded.get(), TextStyle::body()));
+        });
+
+        update_until(&mut app, |app| texts(app) == ["done"]);
+    }
+
+    #[test]
+    fn a_new_key_cancels_the_running_effect() {
+        let (first, second) = (
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(false)),
+        );
+        let key = Captured::<State<u32>>::new();
+        let mut app = compose_app({
+            let (first, second, key) = (first.clone(), second.clone(), key.clone());
+            move || {
+                let current = remember_state(0);
+                key.set(current);
+                let dropped = if current.get() == 0 { &first } else { &second };
+                pending_effect(current.get(), dropped);
+            }
+        });
+        app.update();
+        assert!(!first.load(Ordering::Acquire));
+
+        key.get().set(1);
+        update_until(&mut app, |_| first.load(Ordering::Acquire));
+        assert!(!second.load(Ordering::Acquire));
+    }
+
+    #[test]
+    fn effects_are_cancelled_when_their_scope_leaves() {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let shown = Captured::<State<bool>>::new();
+        let mut app = compose_app({
+            let (dropped, shown) = (dropped.clone(), shown.clone());
+            move || {
+                let visible = remember_state(true);
+                shown.set(visible);
+                let dropped = dropped.clone();
+                Column(Modifiers::new(), move || {
+                    if visible.get() {
+                        let dropped = dropped.clone();
+                        Scope(move || pending_effect(0, &dropped));
+                    }
+                });
+            }
+        });
+        app.update();
+        assert!(!dropped.load(Ordering::Acquire));
+
+        shown.get().set(false);
+        update_until(&mut app, |_| dropped.load(Ordering::Acquire));
+    }
+
+    #[test]
+    fn cancelled_effects_are_not_polled_again() {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let polls = Arc::new(AtomicBool::new(false));
+        let mut future = Box::pin(Cancellable {
+            future: Box::pin({
+                let polls = polls.clone();
+                async move { polls.store(true, Ordering::Release) }
+            }),
+            cancelled: cancelled.clone(),
+        });
+        cancelled.store(true, Ordering::Release);
+
+        let poll = future
+            .as_mut()
+            .poll(&mut Context::from_waker(std::task::Waker::noop()));
+
+        assert!(poll.is_ready());
+        assert!(!polls.load(Ordering::Acquire));
+    }
+}
@@ -13,34 +13,88 @@ pub struct CompositionBridge {
 }
 
 /// Syncs composition tree changes to Bevy entities
-pub fn sync_composition_to_entities(
-    mut commands: Commands,
-    mut tree: ResMut<CompositionTree>,
-    query: Query<(Entity, &CompositionBridge)>,
-) {
+///
+/// Composables materialize their own entities while composing, so new nodes
+/// only need to be linked to the entity they were materialized as. Entities
+/// linked to removed nodes are despawned unless composition still uses them.
+pub fn sync_composition_to_entities(mut commands: Commands, mut tree: ResMut<CompositionTree>) {
     // Collect new node IDs first
     let new_node_ids: Vec<_> = tree.new_nodes.drain(..).collect();
 
-    // Handle new nodes - spawn entities
+    // Handle new nodes - link them to their entities
     for node_id in new_node_ids {
-        commands.spawn((
-            CompositionBridge {
+        if let Some(entity) = tree.get_entity(node_id) {
+            commands.entity(entity).try_insert(CompositionBridge {
                 composition_id: node_id,
-            },
-            Node::default(),
-        ));
+            });
+            tree.bridged.insert(node_id, entity);
+        }
+    }
+
+    // Run cleanup of nodes removed outside of composition
+    for dispose in tree.take_disposals() {
+        dispose();
     }
 
     // Collect removed node IDs
     let removed_node_ids: Vec<_> = tree.removed_nodes.drain(..).collect();
 
-    // Handle removed nodes - despawn entities
+    // Handle removed nodes - despawn entities that are no longer composed
     for node_id in removed_node_ids {
-        for (entity, bridge) in query.iter() {
-            if bridge.composition_id == node_id {
-                commands.entity(entity).despawn();
-                break;
-            }
+        let Some(entity) = tree.bridged.remove(&node_id) else {
+            continue;
+        };
+        if tree.node_for_entity(entity).is_none() {
+            commands.entity(entity).try_despawn();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bevy_integration::{remember_state, Column, If};
+    use crate::modifier::Modifiers;
+    use crate::testing::*;
+
+    #[test]
+    fn removed_nodes_despawn_their_bridged_entities() {
+        let shown = Captured::new();
+        let mut app = compose_app({
+            let shown = shown.clone();
+            move || {
+                let state = remember_state(true);
+                shown.set(state);
+                If(state.get(), || Column(Modifiers::new(), || {}));
+            }
+        });
+        app.update();
+        assert_eq!(count::<CompositionBridge>(&mut app), 1);
+
+        shown.get().set(false);
+        app.update();
+        app.update();
+
+        assert_eq!(count::<CompositionBridge>(&mut app), 0);
+        assert!(app.world().resource::<CompositionTree>().bridged.is_empty());
+    }
+
+    #[test]
+    fn recomposed_nodes_are_bridged_in_the_same_frame() {
+        let shown = Captured::new();
+        let mut app = compose_app({
+            let shown = shown.clone();
+            move || {
+                let state = remember_state(false);
+                shown.set(state);
+                If(state.get(), || Column(Modifiers::new(), || {}));
+            }
+        });
+        assert_eq!(count::<CompositionBridge>(&mut app), 0);
+
+        shown.get().set(true);
+        app.update();
+
+        assert_eq!(count::<CompositionBridge>(&mut app), 1);
+    }
+}
@@ -2,28 +2,195 @@
 //!
 //! Handles input events and dispatches them to composables.
 
-use crate::components::Clickable;
+use super::{with_composition_world, with_installed_world, with_runtime, ComposeRuntime};
+use crate::components::{Clickable, OnClick};
+use crate::state::with_mutable_snapshot;
 use bevy::prelude::*;
+use std::collections::HashSet;
 
 /// Handles button click interactions
+///
+/// Handlers run with the app's runtime active, so state they create or
+/// invalidate belongs to this app, and in a mutable snapshot, so the states
+/// they update change together. The commands they queue, including handlers
+/// taking `&mut Commands` or `&mut World`, are applied with those updates
+/// right after each handler, in the same frame.
 #[allow(clippy::type_complexity)]
 pub fn handle_button_interactions(
-    interaction_query: Query<(&Interaction, &Clickable), (Changed<Interaction>, With<Button>)>,
+    world: &mut World,
+    interactions: &mut QueryState<(&Interaction, &Clickable), (Changed<Interaction>, With<Button>)>,
 ) {
-    for (interaction, clickable) in interaction_query.iter() {
-        if *interaction == Interaction::Pressed {
-            (clickable.on_click)();
-        }
+    for on_click in pressed(world, interactions) {
+        dispatch(world, || on_click());
     }
 }
 
 /// Handles general node interactions for clickable elements
 pub fn handle_node_interactions(
-    interaction_query: Query<(&Interaction, &Clickable), Changed<Interaction>>,
+    world: &mut World,
+    interactions: &mut QueryState<(&Interaction, &Clickable), Changed<Interaction>>,
 ) {
-    for (interaction, clickable) in interaction_query.iter() {
-        if *interaction == Interaction::Pressed {
-            (clickable.on_click)();
+    for on_click in pressed(world, interactions) {
+        dispatch(world, || on_click());
+    }
+}
+
+/// Click handlers of the pressed elements
+fn pressed<F: bevy::ecs::query::QueryFilter>(
+    world: &World,
+    interactions: &mut QueryState<(&Interaction, &Clickable), F>,
+) -> Vec<OnClick> {
+    interactions
+        .iter(world)
+        .filter(|(interaction, _)| **interaction == Interaction::Pressed)
+        .map(|(_, clickable)| clickable.on_click.clone())
+        .collect()
+}
+
+/// Times an event handler is run in a mutable snapshot before it is run
+/// without one
+const SNAPSHOT_ATTEMPTS: usize = 8;
+
+/// Run an event handler with the app's runtime and world installed
+///
+/// The handler runs in a mutable snapshot, and the commands it queues are
+/// applied together with its state updates. If another thread wrote one of
+/// the states it updated meanwhile, neither is applied and the handler runs
+/// again, so a handler may run more than once for one event. A handler that
+/// keeps conflicting runs once more without a snapshot, so the event is never
+/// dropped.
+pub(crate) fn dispatch(world: &mut World, handler: impl Fn()) {
+    let runtime = world.resource::<ComposeRuntime>().clone();
+    with_runtime(&runtime, || {
+        for _ in 0..SNAPSHOT_ATTEMPTS {
+            world.flush();
+            let existing: HashSet<Entity> = empty_entities(world).collect();
+            let (applied, mut commands) =
+                with_installed_world(world, || with_mutable_snapshot(&handler));
+            if applied.is_ok() {
+                commands.apply(world);
+                return;
+            }
+            drop(commands);
+            // Entities the discarded commands reserved are left empty
+            world.flush();
+            let reserved: Vec<Entity> = empty_entities(world)
+                .filter(|entity| !existing.contains(entity))
+                .collect();
+            for entity in reserved {
+                world.despawn(entity);
+            }
+        }
+        warn!("event handler kept conflicting with writes from other threads; running it without a snapshot");
+        with_composition_world(world, handler);
+    });
+}
+
+/// Entities without components
+fn empty_entities(world: &World) -> impl Iterator<Item = Entity> + '_ {
+    world
+        .archetypes()
+        .empty()
+        .entities()
+        .iter()
+        .map(|entity| entity.id())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bevy_integration::{remember_state, with_commands, Button, Column, State, Text};
+    use crate::components::{IntoOnChange, TextStyle};
+    use crate::modifier::Modifiers;
+    use crate::testing::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[derive(Component)]
+    struct Spawned;
+
+    #[derive(Resource, Default)]
+    struct Volume(f32);
+
+    fn press_all(app: &mut App) {
+        for button in entities::<Clickable>(app) {
+            app.world_mut()
+                .entity_mut(button)
+                .insert(Interaction::Pressed);
         }
+        app.update();
+    }
+
+    #[test]
+    fn handlers_taking_commands_apply_them_in_the_frame_of_the_click() {
+        let mut app = compose_app(|| {
+            Button("Spawn", Modifiers::new(), |commands: &mut Commands| {
+                commands.spawn(Spawned);
+            });
+        });
+
+        press_all(&mut app);
+
+        assert_eq!(count::<Spawned>(&mut app), 1);
+    }
+
+    #[test]
+    fn handlers_update_state_and_the_world_together() {
+        let mut app = compose_app(|| {
+            let clicks = remember_state(0);
+            Column(Modifiers::new(), move || {
+                Text(format!("{}", clicks.get()), TextStyle::body());
+                Button("Louder", Modifiers::new(), move || {
+                    clicks.update(|n| *n += 1)
+                });
+                Button("Max", Modifiers::new(), |world: &mut World| {
+                    world.insert_resource(Volume(1.0));
+                });
+            });
+        });
+
+        press_all(&mut app);
+        app.update();
+
+        assert_eq!(app.world().resource::<Volume>().0, 1.0);
+        assert_eq!(texts(&mut app)[0], "1");
+    }
+
+    #[test]
+    fn change_handlers_receive_the_value_first() {
+        let mut app = compose_app(|| {});
+        let on_change = (|value: f32, world: &mut World| {
+            world.insert_resource(Volume(value));
+        })
+        .into_on_change();
+
+        dispatch(app.world_mut(), || on_change(0.25));
+
+        assert_eq!(app.world().resource::<Volume>().0, 0.25);
+    }
+
+    #[test]
+    fn handlers_conflicting_with_other_threads_run_again() {
+        let mut app = compose_app(|| {});
+        let total = State::new(0);
+        let attempts = AtomicUsize::new(0);
+        let empty_before = app.world().archetypes().empty().len();
+
+        dispatch(app.world_mut(), || {
+            total.update(|n| *n += 1);
+            with_commands(|commands| {
+                commands.spawn(Spawned);
+            });
+            if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                thread::spawn(move || total.update(|n| *n += 10))
+                    .join()
+                    .unwrap();
+            }
+        });
+
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert_eq!(total.get_untracked(), 11);
+        assert_eq!(count::<Spawned>(&mut app), 1);
+        assert_eq!(app.world().archetypes().empty().len(), empty_before);
     }
 }
@@ -0,0 +1,173 @@
+//! Layout Bridge
+//!
+//! Feeds sizes resolved by Bevy's UI layout back into composition.
+
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use super::State;
+use crate::layout::Constraints;
+
+/// Container whose laid out size is made available to its content as
+/// `Constraints`, as used by `BoxWithConstraints`
+#[derive(Component, Clone, Copy)]
+pub struct MeasuredConstraints {
+    /// Constraints from the last layout, `None` until the first layout
+    pub constraints: State<Option<Constraints>>,
+}
+
+/// Update measured constraints from the laid out size of their containers
+///
+/// Sizes are compared in whole logical pixels, so sub-pixel jitter does not
+/// recompose the content.
+pub fn update_measured_constraints(
+    containers: Query<(&ComputedNode, &MeasuredConstraints), Changed<ComputedNode>>,
+) {
+    for (node, measured) in &containers {
+        let constraints = constraints_of(node.size() * node.inverse_scale_factor());
+        if measured.constraints.get_untracked() != Some(constraints) {
+            measured.constraints.set(Some(constraints));
+        }
+    }
+}
+
+/// Constraints to compose a box with before it is first laid out: the laid
+/// out size of `parent`, or else the logical size of the primary window
+pub(crate) fn estimated_constraints(
+    world: &mut World,
+    parent: Option<Entity>,
+) -> Option<Constraints> {
+    let parent_size = parent
+        .and_then(|parent| world.get::<ComputedNode>(parent))
+        .map(|node| node.size() * node.inverse_scale_factor())
+        .filter(|size| *size != Vec2::ZERO);
+    let size = parent_size.or_else(|| {
+        let mut windows = world.query_filtered::<&Window, With<PrimaryWindow>>();
+        windows.single(world).ok().map(|window| window.size())
+    })?;
+    Some(constraints_of(size))
+}
+
+/// Constraints for a logical size, in whole pixels
+fn constraints_of(size: Vec2) -> Constraints {
+    let size = size.round();
+    Constraints::new(0.0, size.x, 0.0, size.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bevy_integration::{remember_state, BoxWithConstraints, Column, Text};
+    use crate::components::TextStyle;
+    use crate::modifier::Modifiers;
+    use crate::testing::*;
+    use bevy::window::WindowResolution;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn lay_out(app: &mut App, width: f32) {
+        let world = app.world_mut();
+        let boxes: Vec<Entity> = world
+            .query_filtered::<Entity, With<MeasuredConstraints>>()
+            .iter(world)
+            .collect();
+        for entity in boxes {
+            world.entity_mut(entity).insert(ComputedNode {
+                size: Vec2::new(width, 300.0),
+                inverse_scale_factor: 1.0,
+                ..default()
+            });
+        }
+        // One frame to measure the boxes, one to recompose their content
+        app.update();
+        app.update();
+    }
+
+    #[test]
+    fn content_is_composed_with_the_laid_out_size() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let mut app = compose_app({
+            let runs = runs.clone();
+            move || {
+                let runs = runs.clone();
+                BoxWithConstraints(Modifiers::new().fill_max_size(), move |constraints| {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                    let layout = if constraints.max_width < 600.0 {
+                        "one column"
+                    } else {
+                        "two columns"
+                    };
+                    Text(layout, TextStyle::body());
+                });
+            }
+        });
+        assert!(texts(&mut app).is_empty());
+
+        lay_out(&mut app, 400.0);
+        assert_eq!(texts(&mut app), ["one column"]);
+        let runs_after_layout = runs.load(Ordering::SeqCst);
+
+        lay_out(&mut app, 400.0);
+        assert_eq!(runs.load(Ordering::SeqCst), runs_after_layout);
+
+        lay_out(&mut app, 800.0);
+        assert_eq!(texts(&mut app), ["two columns"]);
+        assert_eq!(runs.load(Ordering::SeqCst), runs_after_layout + 1);
+    }
+
+    /// Content showing whether it got room for two columns
+    fn responsive_content(constraints: Constraints) {
+        let layout = if constraints.max_width < 600.0 {
+            "one column"
+        } else {
+            "two columns"
+        };
+        Text(layout, TextStyle::body());
+    }
+
+    #[test]
+    fn content_is_first_composed_with_the_window_size() {
+        let app = &mut compose_app_with(
+            |app| {
+                app.world_mut().spawn((
+                    Window {
+                        resolution: WindowResolution::new(1000, 600),
+                        ..default()
+                    },
+                    PrimaryWindow,
+                ));
+            },
+            || BoxWithConstraints(Modifiers::new().fill_max_size(), responsive_content),
+        );
+
+        assert_eq!(texts(app), ["two columns"]);
+    }
+
+    #[test]
+    fn boxes_added_later_are_composed_with_their_parents_size() {
+        let show = Captured::new();
+        let mut app = compose_app({
+            let show = show.clone();
+            move || {
+                let visible = remember_state(false);
+                show.set(visible);
+                Column(Modifiers::new().fill_max_size(), move || {
+                    if visible.get() {
+                        BoxWithConstraints(Modifiers::new().fill_max_size(), responsive_content);
+                    }
+                });
+            }
+        });
+        let column = entities::<Node>(&mut app)[0];
+        app.world_mut().entity_mut(column).insert(ComputedNode {
+            size: Vec2::new(400.0, 300.0),
+            inverse_scale_factor: 1.0,
+            ..default()
+        });
+
+        show.get().set(true);
+        app.update();
+
+        assert_eq!(texts(&mut app), ["one column"]);
+    }
+}
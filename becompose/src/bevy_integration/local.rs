@@ -0,0 +1,214 @@
+//! Composition Locals
+//!
+//! Values passed implicitly down the composition tree. A `ProvideLocal`
+//! provides a value to everything composed inside it; `local.current()` reads
+//! the value of the nearest enclosing provider, or the local's default.
+//!
+//! Providers are nodes of the `CompositionTree`, so a scope that recomposes on
+//! its own still sees the providers above it.
+
+// Allow PascalCase function names to match Jetpack Compose conventions
+#![allow(non_snake_case)]
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use super::composables::{
+    current_scope_id, end_group, remember, start_group, with_scope_node, with_tree, State,
+};
+use crate::composition::{ComposableType, CompositionId, CompositionTree};
+
+/// A value provided implicitly to a subtree of the composition
+///
+/// # Example
+/// ```ignore
+/// static LOCAL_ACCENT: LazyLock<CompositionLocal<Color>> =
+///     LazyLock::new(|| composition_local_of(Color::WHITE));
+///
+/// ProvideLocal(&LOCAL_ACCENT, Color::srgb(0.9, 0.3, 0.3), || {
+///     let accent = LOCAL_ACCENT.current();
+///     Text("Warning", TextStyle::body().with_color(accent));
+/// });
+/// ```
+pub struct CompositionLocal<T> {
+    id: u64,
+    default: Arc<T>,
+}
+
+impl<T> Clone for CompositionLocal<T> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            default: self.default.clone(),
+        }
+    }
+}
+
+/// Create a composition local with the value used where no provider encloses
+/// the reader
+pub fn composition_local_of<T>(default: T) -> CompositionLocal<T>
+where
+    T: Clone + PartialEq + Send + Sync + 'static,
+{
+    static NEXT_LOCAL_ID: AtomicU64 = AtomicU64::new(0);
+
+    CompositionLocal {
+        id: NEXT_LOCAL_ID.fetch_add(1, Ordering::Relaxed),
+        default: Arc::new(default),
+    }
+}
+
+impl<T> CompositionLocal<T>
+where
+    T: Clone + PartialEq + Send + Sync + 'static,
+{
+    /// Read the value provided by the nearest enclosing `ProvideLocal`.
+    ///
+    /// The current scope is recomposed when that provider's value changes.
+    /// Outside any provider the default is returned.
+    pub fn current(&self) -> T {
+        match current_scope_id().and_then(|scope_id| self.provider_state(scope_id)) {
+            Some(state) => state.get(),
+            None => (*self.default).clone(),
+        }
+    }
+
+    /// The value of the nearest provider at or above `scope_id`
+    fn provider_state(&self, scope_id: CompositionId) -> Option<State<T>> {
+        with_tree(|tree| {
+            let provider = self.nearest_provider(tree, scope_id)?;
+            let (_, value) = tree.get(provider)?.provided_local.as_ref()?;
+            value.downcast_ref::<State<T>>().copied()
+        })
+    }
+
+    fn nearest_provider(&self, tree: &CompositionTree, id: CompositionId) -> Option<CompositionId> {
+        let mut current = id;
+        loop {
+            let node = tree.get(current)?;
+            if matches!(node.provided_local, Some((local_id, _)) if local_id == self.id) {
+                return Some(current);
+            }
+            current = node.parent?;
+        }
+    }
+}
+
+/// Provide `value` for `local` to everything composed in `content`.
+///
+/// Nested providers of the same local override this one for their subtree.
+/// When `value` changes between recompositions, scopes that read the local
+/// are recomposed.
+#[track_caller]
+pub fn ProvideLocal<T, F>(local: &CompositionLocal<T>, value: T, content: F)
+where
+    T: Clone + PartialEq + Send + Sync + 'static,
+    F: FnOnce(),
+{
+    let scope_id = start_group(ComposableType::Custom("ProvideLocal".to_string()), None);
+
+    let state = remember(|| State::new(value.clone()));
+    if state.get_untracked() != value {
+        state.set(value);
+    }
+    with_scope_node(scope_id, |node| {
+        node.provided_local = Some((local.id, Box::new(state)));
+    });
+
+    content();
+
+    end_group(scope_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bevy_integration::{remember_state, Column, Scope, Text};
+    use crate::components::TextStyle;
+    use crate::modifier::Modifiers;
+    use crate::testing::*;
+
+    fn show(local: &CompositionLocal<&'static str>) {
+        Text(local.current(), TextStyle::body());
+    }
+
+    #[test]
+    fn readers_see_the_nearest_provider_or_the_default() {
+        let local = composition_local_of("default");
+        let mut app = compose_app(move || {
+            let local = local.clone();
+            Column(Modifiers::new(), move || {
+                show(&local);
+                ProvideLocal(&local, "outer", || {
+                    show(&local);
+                    ProvideLocal(&local, "inner", || show(&local));
+                    show(&local);
+                });
+                show(&local);
+            });
+        });
+
+        assert_eq!(
+            texts(&mut app),
+            ["default", "outer", "inner", "outer", "default"]
+        );
+    }
+
+    #[test]
+    fn scopes_recomposed_on_their_own_see_the_provided_value() {
+        let local = composition_local_of("default");
+        let counter = Captured::new();
+        let mut app = compose_app({
+            let counter = counter.clone();
+            move || {
+                let reader = local.clone();
+                let counter = counter.clone();
+                ProvideLocal(&local, "provided", move || {
+                    let local = reader.clone();
+                    let counter = counter.clone();
+                    Scope(move || {
+                        let count = remember_state(0);
+                        counter.set(count);
+                        Text(
+                            format!("{} {}", local.current(), count.get()),
+                            TextStyle::body(),
+                        );
+                    });
+                });
+            }
+        });
+
+        counter.get().set(1);
+        app.update();
+
+        assert_eq!(texts(&mut app), ["provided 1"]);
+    }
+
+    #[test]
+    fn readers_are_recomposed_when_the_provided_value_changes() {
+        let local = composition_local_of("default");
+        let provided = Captured::new();
+        let mut app = compose_app({
+            let provided = provided.clone();
+            move || {
+                let local = local.clone();
+                let provided = provided.clone();
+                Column(Modifiers::new(), move || {
+                    let value = remember_state("first");
+                    provided.set(value);
+                    let reader = local.clone();
+                    ProvideLocal(&local, value.get(), move || {
+                        let local = reader.clone();
+                        Scope(move || show(&local));
+                    });
+                });
+            }
+        });
+        assert_eq!(texts(&mut app), ["first"]);
+
+        provided.get().set("second");
+        app.update();
+
+        assert_eq!(texts(&mut app), ["second"]);
+    }
+}
@@ -4,10 +4,10 @@
 
 use bevy::prelude::*;
 use bevy_material_ui::prelude::*;
-use std::sync::Arc;
 
 use crate::bevy_integration::composables::with_implicit_scope;
 use crate::bevy_integration::material_ui::spawn_material_child;
+use crate::components::{IntoOnClick, OnClick};
 
 /// Design filled button composable
 ///
@@ -15,10 +15,8 @@ use crate::bevy_integration::material_ui::spawn_material_child;
 /// ```ignore
 /// FilledButton("Submit", || submit_form());
 /// ```
-pub fn FilledButton<F>(label: impl Into<String>, on_click: F)
-where
-    F: Fn() + Send + Sync + 'static,
-{
+#[track_caller]
+pub fn FilledButton<M>(label: impl Into<String>, on_click: impl IntoOnClick<M>) {
     Button(label, ButtonVariant::Filled, on_click);
 }
 
@@ -28,10 +26,8 @@ where
 /// ```ignore
 /// OutlinedButton("Cancel", || cancel());
 /// ```
-pub fn OutlinedButton<F>(label: impl Into<String>, on_click: F)
-where
-    F: Fn() + Send + Sync + 'static,
-{
+#[track_caller]
+pub fn OutlinedButton<M>(label: impl Into<String>, on_click: impl IntoOnClick<M>) {
     Button(label, ButtonVariant::Outlined, on_click);
 }
 
@@ -41,10 +37,8 @@ where
 /// ```ignore
 /// TextButton("Learn More", || show_info());
 /// ```
-pub fn TextButton<F>(label: impl Into<String>, on_click: F)
-where
-    F: Fn() + Send + Sync + 'static,
-{
+#[track_caller]
+pub fn TextButton<M>(label: impl Into<String>, on_click: impl IntoOnClick<M>) {
     Button(label, ButtonVariant::Text, on_click);
 }
 
@@ -54,10 +48,8 @@ where
 /// ```ignore
 /// ElevatedButton("Save", || save_data());
 /// ```
-pub fn ElevatedButton<F>(label: impl Into<String>, on_click: F)
-where
-    F: Fn() + Send + Sync + 'static,
-{
+#[track_caller]
+pub fn ElevatedButton<M>(label: impl Into<String>, on_click: impl IntoOnClick<M>) {
     Button(label, ButtonVariant::Elevated, on_click);
 }
 
@@ -67,10 +59,8 @@ where
 /// ```ignore
 /// TonalButton("Add", || add_item());
 /// ```
-pub fn TonalButton<F>(label: impl Into<String>, on_click: F)
-where
-    F: Fn() + Send + Sync + 'static,
-{
+#[track_caller]
+pub fn TonalButton<M>(label: impl Into<String>, on_click: impl IntoOnClick<M>) {
     Button(label, ButtonVariant::FilledTonal, on_click);
 }
 
@@ -80,13 +70,11 @@ where
 /// ```ignore
 /// Button("Click Me", ButtonVariant::Filled, || handle_click());
 /// ```
-pub fn Button<F>(label: impl Into<String>, variant: ButtonVariant, on_click: F)
-where
-    F: Fn() + Send + Sync + 'static,
-{
+#[track_caller]
+pub fn Button<M>(label: impl Into<String>, variant: ButtonVariant, on_click: impl IntoOnClick<M>) {
     with_implicit_scope(|| {
         let label = label.into();
-        let on_click = Arc::new(on_click);
+        let on_click = on_click.into_on_click();
 
         spawn_material_child(move |commands, theme| {
             let button_bundle = MaterialButtonBuilder::new(&label)
@@ -115,12 +103,10 @@ where
 ///     || save_data()
 /// );
 /// ```
-pub fn ButtonConfigured<F>(config: ButtonConfig, on_click: F)
-where
-    F: Fn() + Send + Sync + 'static,
-{
+#[track_caller]
+pub fn ButtonConfigured<M>(config: ButtonConfig, on_click: impl IntoOnClick<M>) {
     with_implicit_scope(|| {
-        let on_click = Arc::new(on_click);
+        let on_click = on_click.into_on_click();
 
         spawn_material_child(move |commands, theme| {
             let mut builder = MaterialButtonBuilder::new(&config.label);
@@ -195,5 +181,5 @@ impl ButtonConfig {
 /// Component to handle button click events and call the user's callback
 #[derive(Component)]
 pub struct ButtonClickHandler {
-    pub on_click: Arc<dyn Fn() + Send + Sync>,
+    pub on_click: OnClick,
 }
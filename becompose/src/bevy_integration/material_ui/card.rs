@@ -4,10 +4,10 @@
 
 use bevy::prelude::*;
 use bevy_material_ui::prelude::*;
-use std::sync::Arc;
 
 use crate::bevy_integration::composables::with_implicit_scope;
 use crate::bevy_integration::material_ui::spawn_material_child_with_children;
+use crate::components::{IntoOnClick, OnClick};
 
 /// Design elevated card composable
 ///
@@ -17,6 +17,7 @@ use crate::bevy_integration::material_ui::spawn_material_child_with_children;
 ///     Text("Card content", TextStyle::body());
 /// });
 /// ```
+#[track_caller]
 pub fn ElevatedCard<F>(content: F)
 where
     F: FnOnce(),
@@ -32,6 +33,7 @@ where
 ///     Text("Card content", TextStyle::body());
 /// });
 /// ```
+#[track_caller]
 pub fn FilledCard<F>(content: F)
 where
     F: FnOnce(),
@@ -47,6 +49,7 @@ where
 ///     Text("Card content", TextStyle::body());
 /// });
 /// ```
+#[track_caller]
 pub fn OutlinedCard<F>(content: F)
 where
     F: FnOnce(),
@@ -65,6 +68,7 @@ where
 ///     });
 /// });
 /// ```
+#[track_caller]
 pub fn Card<F>(variant: CardVariant, content: F)
 where
     F: FnOnce(),
@@ -91,13 +95,13 @@ where
 ///     Text("Click me!", TextStyle::body());
 /// });
 /// ```
-pub fn ClickableCard<F, C>(variant: CardVariant, on_click: F, content: C)
+#[track_caller]
+pub fn ClickableCard<M, C>(variant: CardVariant, on_click: impl IntoOnClick<M>, content: C)
 where
-    F: Fn() + Send + Sync + 'static,
     C: FnOnce(),
 {
     with_implicit_scope(|| {
-        let on_click = Arc::new(on_click);
+        let on_click = on_click.into_on_click();
 
         spawn_material_child_with_children(
             move |commands, theme| {
@@ -116,6 +120,7 @@ where
 }
 
 /// Design card composable with full configuration
+#[track_caller]
 pub fn CardConfigured<C>(config: CardConfig, content: C)
 where
     C: FnOnce(),
@@ -156,7 +161,7 @@ pub struct CardConfig {
     pub variant: CardVariant,
     pub clickable: bool,
     pub draggable: bool,
-    pub on_click: Option<Arc<dyn Fn() + Send + Sync>>,
+    pub on_click: Option<OnClick>,
 }
 
 impl CardConfig {
@@ -199,8 +204,8 @@ impl CardConfig {
         self
     }
 
-    pub fn on_click<F: Fn() + Send + Sync + 'static>(mut self, on_click: F) -> Self {
-        self.on_click = Some(Arc::new(on_click));
+    pub fn on_click<M>(mut self, on_click: impl IntoOnClick<M>) -> Self {
+        self.on_click = Some(on_click.into_on_click());
         self.clickable = true;
         self
     }
@@ -215,5 +220,5 @@ impl Default for CardConfig {
 /// Component to handle card click events
 #[derive(Component)]
 pub struct CardClickHandler {
-    pub on_click: Arc<dyn Fn() + Send + Sync>,
+    pub on_click: OnClick,
 }
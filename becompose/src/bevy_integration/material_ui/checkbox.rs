@@ -17,6 +17,7 @@ use crate::bevy_integration::material_ui::spawn_material_child;
 ///     println!("Checkbox state changed to: {:?}", new_state);
 /// });
 /// ```
+#[track_caller]
 pub fn Checkbox<F>(label: impl Into<String>, initial_state: CheckboxState, on_change: F)
 where
     F: Fn(CheckboxState) + Send + Sync + 'static,
@@ -67,6 +68,7 @@ where
 }
 
 /// Design checkbox composable with configuration
+#[track_caller]
 pub fn CheckboxConfigured<F>(config: CheckboxConfig, on_change: F)
 where
     F: Fn(CheckboxState) + Send + Sync + 'static,
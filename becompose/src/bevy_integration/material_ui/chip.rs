@@ -17,6 +17,7 @@ use crate::bevy_integration::material_ui::spawn_material_child;
 ///     println!("Assist chip clicked!");
 /// });
 /// ```
+#[track_caller]
 pub fn AssistChip<F>(label: impl Into<String>, on_click: F)
 where
     F: Fn() + Send + Sync + 'static,
@@ -32,6 +33,7 @@ where
 ///     println!("Filter chip selected: {}", selected);
 /// });
 /// ```
+#[track_caller]
 pub fn FilterChip<F>(label: impl Into<String>, selected: bool, on_select: F)
 where
     F: Fn(bool) + Send + Sync + 'static,
@@ -72,6 +74,7 @@ where
 ///     println!("Input chip deleted!");
 /// });
 /// ```
+#[track_caller]
 pub fn InputChip<F>(label: impl Into<String>, on_delete: F)
 where
     F: Fn() + Send + Sync + 'static,
@@ -112,6 +115,7 @@ where
 ///     println!("Suggestion chip clicked!");
 /// });
 /// ```
+#[track_caller]
 pub fn SuggestionChip<F>(label: impl Into<String>, on_click: F)
 where
     F: Fn() + Send + Sync + 'static,
@@ -120,6 +124,7 @@ where
 }
 
 /// Design chip composable with variant
+#[track_caller]
 pub fn Chip<F>(label: impl Into<String>, variant: ChipVariant, on_click: F)
 where
     F: Fn() + Send + Sync + 'static,
@@ -158,6 +163,7 @@ where
 }
 
 /// Design chip composable with full configuration
+#[track_caller]
 pub fn ChipConfigured<F>(config: ChipConfig, on_click: F)
 where
     F: Fn() + Send + Sync + 'static,
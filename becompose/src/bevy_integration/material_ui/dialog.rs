@@ -20,6 +20,7 @@ use crate::bevy_integration::material_ui::spawn_material_child_with_children;
 ///     || println!("Cancelled"),
 /// );
 /// ```
+#[track_caller]
 pub fn Dialog<F1, F2>(
     title: impl Into<String>,
     content: impl Into<String>,
@@ -84,6 +85,7 @@ pub fn Dialog<F1, F2>(
 ///     }
 /// );
 /// ```
+#[track_caller]
 pub fn DialogWithContent<C>(config: DialogConfig, content: C)
 where
     C: FnOnce(),
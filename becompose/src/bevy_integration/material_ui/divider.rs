@@ -17,6 +17,7 @@ use crate::bevy_integration::material_ui::spawn_material_child;
 ///     Text("Below divider", TextStyle::body());
 /// });
 /// ```
+#[track_caller]
 pub fn Divider() {
     with_implicit_scope(|| {
         spawn_material_child(move |commands, theme| {
@@ -44,6 +45,7 @@ pub fn Divider() {
 ///     Text("Right", TextStyle::body());
 /// });
 /// ```
+#[track_caller]
 pub fn VerticalDivider() {
     with_implicit_scope(|| {
         spawn_material_child(move |commands, theme| {
@@ -67,6 +69,7 @@ pub fn VerticalDivider() {
 /// ```ignore
 /// DividerWithInset(16.0); // 16px inset on both sides
 /// ```
+#[track_caller]
 pub fn DividerWithInset(inset: f32) {
     with_implicit_scope(|| {
         spawn_material_child(move |commands, theme| {
@@ -86,6 +89,7 @@ pub fn DividerWithInset(inset: f32) {
 }
 
 /// Design divider composable with configuration
+#[track_caller]
 pub fn DividerConfigured(config: DividerConfig) {
     with_implicit_scope(|| {
         spawn_material_child(move |commands, theme| {
@@ -17,6 +17,7 @@ use crate::bevy_integration::material_ui::spawn_material_child;
 ///     println!("FAB clicked!");
 /// });
 /// ```
+#[track_caller]
 pub fn Fab<F>(icon: impl Into<String>, on_click: F)
 where
     F: Fn() + Send + Sync + 'static,
@@ -46,6 +47,7 @@ where
 ///     println!("Small FAB clicked!");
 /// });
 /// ```
+#[track_caller]
 pub fn SmallFab<F>(icon: impl Into<String>, on_click: F)
 where
     F: Fn() + Send + Sync + 'static,
@@ -75,6 +77,7 @@ where
 ///     println!("Large FAB clicked!");
 /// });
 /// ```
+#[track_caller]
 pub fn LargeFab<F>(icon: impl Into<String>, on_click: F)
 where
     F: Fn() + Send + Sync + 'static,
@@ -104,6 +107,7 @@ where
 ///     println!("Extended FAB clicked!");
 /// });
 /// ```
+#[track_caller]
 pub fn ExtendedFab<F>(icon: impl Into<String>, label: impl Into<String>, on_click: F)
 where
     F: Fn() + Send + Sync + 'static,
@@ -127,6 +131,7 @@ where
 }
 
 /// Design FAB composable with full configuration
+#[track_caller]
 pub fn FabConfigured<F>(config: FabConfig, on_click: F)
 where
     F: Fn() + Send + Sync + 'static,
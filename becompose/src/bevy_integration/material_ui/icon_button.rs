@@ -4,10 +4,10 @@
 
 use bevy::prelude::*;
 use bevy_material_ui::prelude::*;
-use std::sync::Arc;
 
 use crate::bevy_integration::composables::with_implicit_scope;
 use crate::bevy_integration::material_ui::spawn_material_child;
+use crate::components::{IntoOnClick, OnClick};
 
 /// Design standard icon button composable
 ///
@@ -17,10 +17,8 @@ use crate::bevy_integration::material_ui::spawn_material_child;
 ///     println!("Icon button clicked!");
 /// });
 /// ```
-pub fn IconButton<F>(icon: impl Into<String>, on_click: F)
-where
-    F: Fn() + Send + Sync + 'static,
-{
+#[track_caller]
+pub fn IconButton<M>(icon: impl Into<String>, on_click: impl IntoOnClick<M>) {
     IconButtonWithVariant(icon, IconButtonVariant::Standard, on_click);
 }
 
@@ -32,10 +30,8 @@ where
 ///     println!("Filled icon button clicked!");
 /// });
 /// ```
-pub fn FilledIconButton<F>(icon: impl Into<String>, on_click: F)
-where
-    F: Fn() + Send + Sync + 'static,
-{
+#[track_caller]
+pub fn FilledIconButton<M>(icon: impl Into<String>, on_click: impl IntoOnClick<M>) {
     IconButtonWithVariant(icon, IconButtonVariant::Filled, on_click);
 }
 
@@ -47,10 +43,8 @@ where
 ///     println!("Tonal icon button clicked!");
 /// });
 /// ```
-pub fn TonalIconButton<F>(icon: impl Into<String>, on_click: F)
-where
-    F: Fn() + Send + Sync + 'static,
-{
+#[track_caller]
+pub fn TonalIconButton<M>(icon: impl Into<String>, on_click: impl IntoOnClick<M>) {
     IconButtonWithVariant(icon, IconButtonVariant::FilledTonal, on_click);
 }
 
@@ -62,21 +56,21 @@ where
 ///     println!("Outlined icon button clicked!");
 /// });
 /// ```
-pub fn OutlinedIconButton<F>(icon: impl Into<String>, on_click: F)
-where
-    F: Fn() + Send + Sync + 'static,
-{
+#[track_caller]
+pub fn OutlinedIconButton<M>(icon: impl Into<String>, on_click: impl IntoOnClick<M>) {
     IconButtonWithVariant(icon, IconButtonVariant::Outlined, on_click);
 }
 
 /// Design icon button composable with variant
-pub fn IconButtonWithVariant<F>(icon: impl Into<String>, variant: IconButtonVariant, on_click: F)
-where
-    F: Fn() + Send + Sync + 'static,
-{
+#[track_caller]
+pub fn IconButtonWithVariant<M>(
+    icon: impl Into<String>,
+    variant: IconButtonVariant,
+    on_click: impl IntoOnClick<M>,
+) {
     with_implicit_scope(|| {
         let icon = icon.into();
-        let on_click = Arc::new(on_click);
+        let on_click = on_click.into_on_click();
 
         spawn_material_child(move |commands, theme| {
             let icon_button_bundle = IconButtonBuilder::new(&icon).variant(variant).build(theme);
@@ -92,12 +86,10 @@ where
 }
 
 /// Design icon button composable with full configuration
-pub fn IconButtonConfigured<F>(config: IconButtonConfig, on_click: F)
-where
-    F: Fn() + Send + Sync + 'static,
-{
+#[track_caller]
+pub fn IconButtonConfigured<M>(config: IconButtonConfig, on_click: impl IntoOnClick<M>) {
     with_implicit_scope(|| {
-        let on_click = Arc::new(on_click);
+        let on_click = on_click.into_on_click();
 
         spawn_material_child(move |commands, theme| {
             let mut builder = IconButtonBuilder::new(&config.icon).variant(config.variant);
@@ -180,5 +172,5 @@ impl IconButtonConfig {
 /// Component to handle icon button click events
 #[derive(Component)]
 pub struct IconButtonClickHandler {
-    pub on_click: Arc<dyn Fn() + Send + Sync>,
+    pub on_click: OnClick,
 }
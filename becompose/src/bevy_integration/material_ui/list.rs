@@ -4,12 +4,12 @@
 
 use bevy::prelude::*;
 use bevy_material_ui::prelude::*;
-use std::sync::Arc;
 
 use crate::bevy_integration::composables::with_implicit_scope;
 use crate::bevy_integration::material_ui::{
     spawn_material_child, spawn_material_child_with_children,
 };
+use crate::components::{IntoOnClick, OnClick};
 
 /// Design list composable
 ///
@@ -20,6 +20,7 @@ use crate::bevy_integration::material_ui::{
 ///     ListItem("Item 2", || println!("Item 2 clicked"));
 /// });
 /// ```
+#[track_caller]
 pub fn List<F>(content: F)
 where
     F: FnOnce(),
@@ -50,13 +51,11 @@ where
 /// ```ignore
 /// ListItem("Settings", || open_settings());
 /// ```
-pub fn ListItem<F>(headline: impl Into<String>, on_click: F)
-where
-    F: Fn() + Send + Sync + 'static,
-{
+#[track_caller]
+pub fn ListItem<M>(headline: impl Into<String>, on_click: impl IntoOnClick<M>) {
     with_implicit_scope(|| {
         let headline = headline.into();
-        let on_click = Arc::new(on_click);
+        let on_click = on_click.into_on_click();
 
         spawn_material_child(move |commands, theme| {
             let list_item = ListItemBuilder::new(&headline).build(theme);
@@ -77,17 +76,16 @@ where
 /// ```ignore
 /// ListItemWithSupporting("Wi-Fi", "Connected to Home Network", || open_wifi_settings());
 /// ```
-pub fn ListItemWithSupporting<F>(
+#[track_caller]
+pub fn ListItemWithSupporting<M>(
     headline: impl Into<String>,
     supporting: impl Into<String>,
-    on_click: F,
-) where
-    F: Fn() + Send + Sync + 'static,
-{
+    on_click: impl IntoOnClick<M>,
+) {
     with_implicit_scope(|| {
         let headline = headline.into();
         let supporting = supporting.into();
-        let on_click = Arc::new(on_click);
+        let on_click = on_click.into_on_click();
 
         spawn_material_child(move |commands, theme| {
             let list_item = ListItemBuilder::new(&headline)
@@ -105,12 +103,10 @@ pub fn ListItemWithSupporting<F>(
 }
 
 /// Design list item composable with configuration
-pub fn ListItemConfigured<F>(config: ListItemConfig, on_click: F)
-where
-    F: Fn() + Send + Sync + 'static,
-{
+#[track_caller]
+pub fn ListItemConfigured<M>(config: ListItemConfig, on_click: impl IntoOnClick<M>) {
     with_implicit_scope(|| {
-        let on_click = Arc::new(on_click);
+        let on_click = on_click.into_on_click();
 
         spawn_material_child(move |commands, theme| {
             let mut builder = ListItemBuilder::new(&config.headline);
@@ -199,5 +195,5 @@ impl ListItemConfig {
 /// Component to handle list item click events
 #[derive(Component)]
 pub struct ListItemClickHandler {
-    pub on_click: Arc<dyn Fn() + Send + Sync>,
+    pub on_click: OnClick,
 }
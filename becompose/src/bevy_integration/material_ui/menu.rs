@@ -21,6 +21,7 @@ use crate::bevy_integration::material_ui::{
 ///     MenuItem("Paste", || paste());
 /// });
 /// ```
+#[track_caller]
 pub fn Menu<F>(content: F)
 where
     F: FnOnce(),
@@ -55,6 +56,7 @@ where
 /// ```ignore
 /// MenuItem("Settings", || open_settings());
 /// ```
+#[track_caller]
 pub fn MenuItem<F>(label: impl Into<String>, on_select: F)
 where
     F: Fn() + Send + Sync + 'static,
@@ -82,6 +84,7 @@ where
 /// ```ignore
 /// MenuItemWithIcon("settings", "Settings", || open_settings());
 /// ```
+#[track_caller]
 pub fn MenuItemWithIcon<F>(icon: impl Into<String>, label: impl Into<String>, on_select: F)
 where
     F: Fn() + Send + Sync + 'static,
@@ -107,6 +110,7 @@ where
 }
 
 /// Design menu item composable with configuration
+#[track_caller]
 pub fn MenuItemConfigured<F>(config: MenuItemConfig, on_select: F)
 where
     F: Fn() + Send + Sync + 'static,
@@ -142,6 +146,7 @@ where
 }
 
 /// Design menu divider composable
+#[track_caller]
 pub fn MenuDivider() {
     with_implicit_scope(|| {
         spawn_material_child(move |commands, theme| {
@@ -23,6 +23,10 @@
 
 use bevy::prelude::*;
 use std::cell::RefCell;
+use std::sync::Arc;
+
+use super::input_bridge::dispatch;
+use crate::components::{OnChange, OnClick};
 
 mod button;
 mod card;
@@ -36,6 +40,7 @@ mod list;
 mod menu;
 mod progress;
 mod radio;
+mod scaffold;
 mod select;
 mod slider;
 mod snackbar;
@@ -56,6 +61,7 @@ pub use list::*;
 pub use menu::*;
 pub use progress::*;
 pub use radio::*;
+pub use scaffold::*;
 pub use select::*;
 pub use slider::*;
 pub use snackbar::*;
@@ -111,48 +117,20 @@ pub fn spawn_material_child<F>(f: F) -> Entity
 where
     F: FnOnce(&mut Commands, &MaterialTheme) -> Entity,
 {
-    use crate::bevy_integration::composables::{
-        register_entity_scope, CompositionRoot, COMPOSITION_CTX,
-    };
-    use std::cell::RefCell;
-
-    COMPOSITION_CTX.with(
-        |ctx: &RefCell<crate::bevy_integration::composables::CompositionContext>| {
-            let ctx_ref = ctx.borrow();
-            let commands = unsafe { &mut *ctx_ref.commands };
-
-            // Get the theme - use default if not set
-            let theme = get_material_theme().unwrap_or_default();
-
-            // Spawn the entity using the provided function
-            let entity = f(commands, &theme);
-
-            // Track which scope this entity belongs to
-            let scope_id = ctx_ref.scope_stack.last().copied();
-            let parent = ctx_ref.parent_stack.last().copied();
-
-            drop(ctx_ref);
-
-            if let Some(scope_id) = scope_id {
-                register_entity_scope(entity, scope_id);
-            }
-
-            COMPOSITION_CTX.with(
-                |ctx: &RefCell<crate::bevy_integration::composables::CompositionContext>| {
-                    let ctx_ref = ctx.borrow();
-                    let commands = unsafe { &mut *ctx_ref.commands };
-
-                    if let Some(parent) = parent {
-                        commands.entity(parent).add_child(entity);
-                    } else {
-                        commands.entity(entity).insert(CompositionRoot);
-                    }
-                },
-            );
-
-            entity
-        },
-    )
+    use crate::bevy_integration::composables::{emit_child, with_commands};
+
+    // Get the theme - use default if not set
+    let theme = get_material_theme().unwrap_or_default();
+
+    // Spawn the entity using the provided function
+    let entity = with_commands(|commands| f(commands, &theme));
+
+    // Track the entity under the current scope and parent. Material widgets
+    // build their own entity trees, so they are respawned on recomposition
+    // rather than patched in place.
+    emit_child(entity);
+
+    entity
 }
 
 /// Spawn a material UI child with children content
@@ -161,21 +139,84 @@ where
     F: FnOnce(&mut Commands, &MaterialTheme) -> Entity,
     C: FnOnce(),
 {
-    use crate::bevy_integration::composables::{
-        enter_scope, exit_scope, pop_parent, push_parent, ScopeId,
-    };
+    use crate::bevy_integration::composables::{end_group, pop_parent, push_parent, start_group};
+    use crate::composition::ComposableType;
 
     let entity = spawn_material_child(spawn_fn);
 
     // Create a scope for children
-    let scope_id = ScopeId::new();
+    let scope_id = start_group(ComposableType::Custom("MaterialContent".to_string()), None);
     push_parent(entity);
-    enter_scope(scope_id);
 
     content();
 
-    exit_scope();
     pop_parent();
+    end_group(scope_id);
 
     entity
 }
+
+// ============================================================================
+// Event Handlers
+// ============================================================================
+
+/// Handlers of a pressed material widget
+type ClickHandlers<'a> = AnyOf<(
+    &'a ButtonClickHandler,
+    &'a CardClickHandler,
+    &'a IconButtonClickHandler,
+    &'a ListItemClickHandler,
+    &'a RadioSelectHandler,
+    &'a RadioGroupItemHandler,
+)>;
+
+/// Runs the click handlers of pressed material widgets
+///
+/// Handlers are dispatched like `handle_button_interactions` does: with the
+/// app's runtime active, in a mutable snapshot, and with the commands they
+/// queue applied in the same frame.
+pub fn handle_material_clicks(
+    world: &mut World,
+    pressed: &mut QueryState<(&Interaction, ClickHandlers), Changed<Interaction>>,
+) {
+    let mut handlers: Vec<OnClick> = Vec::new();
+    for (interaction, (button, card, icon_button, list_item, radio, radio_item)) in
+        pressed.iter(world)
+    {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let on_click = button
+            .map(|handler| &handler.on_click)
+            .or(card.map(|handler| &handler.on_click))
+            .or(icon_button.map(|handler| &handler.on_click))
+            .or(list_item.map(|handler| &handler.on_click))
+            .or(radio.map(|handler| &handler.on_select));
+        handlers.extend(on_click.cloned());
+        if let Some(item) = radio_item {
+            let (on_select, index) = (item.on_select.clone(), item.index);
+            handlers.push(Arc::new(move || on_select(index)));
+        }
+    }
+    for on_click in handlers {
+        dispatch(world, || on_click());
+    }
+}
+
+/// Runs the change handlers of sliders the user moved
+///
+/// Sliders spawned by a recomposition are skipped, since their value comes
+/// from the composition rather than the user.
+pub fn handle_material_slider_changes(
+    world: &mut World,
+    sliders: &mut QueryState<(Ref<MaterialSlider>, &SliderChangeHandler), Changed<MaterialSlider>>,
+) {
+    let changes: Vec<(OnChange<f32>, f32)> = sliders
+        .iter(world)
+        .filter(|(slider, _)| !slider.is_added())
+        .map(|(slider, handler)| (handler.on_change.clone(), slider.value))
+        .collect();
+    for (on_change, value) in changes {
+        dispatch(world, || on_change(value));
+    }
+}
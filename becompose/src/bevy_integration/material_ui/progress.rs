@@ -13,6 +13,7 @@ use crate::bevy_integration::material_ui::spawn_material_child;
 /// ```ignore
 /// LinearProgress(0.5); // 50% progress
 /// ```
+#[track_caller]
 pub fn LinearProgress(progress: f32) {
     with_implicit_scope(|| {
         spawn_material_child(move |commands, theme| {
@@ -29,6 +30,7 @@ pub fn LinearProgress(progress: f32) {
 /// ```ignore
 /// LinearProgressIndeterminate();
 /// ```
+#[track_caller]
 pub fn LinearProgressIndeterminate() {
     with_implicit_scope(|| {
         spawn_material_child(move |commands, theme| {
@@ -45,6 +47,7 @@ pub fn LinearProgressIndeterminate() {
 /// ```ignore
 /// CircularProgress(0.75); // 75% progress
 /// ```
+#[track_caller]
 pub fn CircularProgress(progress: f32) {
     with_implicit_scope(|| {
         spawn_material_child(move |commands, theme| {
@@ -63,6 +66,7 @@ pub fn CircularProgress(progress: f32) {
 /// ```ignore
 /// CircularProgressIndeterminate();
 /// ```
+#[track_caller]
 pub fn CircularProgressIndeterminate() {
     with_implicit_scope(|| {
         spawn_material_child(move |commands, theme| {
@@ -74,6 +78,7 @@ pub fn CircularProgressIndeterminate() {
 }
 
 /// Design linear progress composable with configuration
+#[track_caller]
 pub fn LinearProgressConfigured(config: ProgressConfig) {
     with_implicit_scope(|| {
         spawn_material_child(move |commands, theme| {
@@ -93,6 +98,7 @@ pub fn LinearProgressConfigured(config: ProgressConfig) {
 }
 
 /// Design circular progress composable with configuration
+#[track_caller]
 pub fn CircularProgressConfigured(config: ProgressConfig) {
     with_implicit_scope(|| {
         spawn_material_child(move |commands, theme| {
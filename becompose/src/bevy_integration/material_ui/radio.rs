@@ -4,10 +4,10 @@
 
 use bevy::prelude::*;
 use bevy_material_ui::prelude::*;
-use std::sync::Arc;
 
 use crate::bevy_integration::composables::with_implicit_scope;
 use crate::bevy_integration::material_ui::spawn_material_child;
+use crate::components::{IntoOnChange, IntoOnClick, OnChange, OnClick};
 
 /// Design radio button composable
 ///
@@ -17,13 +17,11 @@ use crate::bevy_integration::material_ui::spawn_material_child;
 ///     println!("Option A selected");
 /// });
 /// ```
-pub fn Radio<F>(label: impl Into<String>, selected: bool, on_select: F)
-where
-    F: Fn() + Send + Sync + 'static,
-{
+#[track_caller]
+pub fn Radio<M>(label: impl Into<String>, selected: bool, on_select: impl IntoOnClick<M>) {
     with_implicit_scope(|| {
         let label = label.into();
-        let on_select = Arc::new(on_select);
+        let on_select = on_select.into_on_click();
 
         spawn_material_child(move |commands, theme| {
             let row = commands
@@ -77,13 +75,15 @@ where
 ///     println!("Selected option index: {}", index);
 /// });
 /// ```
-pub fn RadioGroup<F>(options: &[impl AsRef<str>], selected_index: usize, on_select: F)
-where
-    F: Fn(usize) + Send + Sync + 'static,
-{
+#[track_caller]
+pub fn RadioGroup<M>(
+    options: &[impl AsRef<str>],
+    selected_index: usize,
+    on_select: impl IntoOnChange<usize, M>,
+) {
     with_implicit_scope(|| {
         let options: Vec<String> = options.iter().map(|s| s.as_ref().to_string()).collect();
-        let on_select = Arc::new(on_select);
+        let on_select = on_select.into_on_change();
 
         spawn_material_child(move |commands, theme| {
             let column = commands
@@ -142,12 +142,10 @@ where
 }
 
 /// Design radio button composable with configuration
-pub fn RadioConfigured<F>(config: RadioConfig, on_select: F)
-where
-    F: Fn() + Send + Sync + 'static,
-{
+#[track_caller]
+pub fn RadioConfigured<M>(config: RadioConfig, on_select: impl IntoOnClick<M>) {
     with_implicit_scope(|| {
-        let on_select = Arc::new(on_select);
+        let on_select = on_select.into_on_click();
 
         spawn_material_child(move |commands, theme| {
             let row = commands
@@ -243,12 +241,12 @@ impl Default for RadioConfig {
 /// Component to handle radio selection events
 #[derive(Component)]
 pub struct RadioSelectHandler {
-    pub on_select: Arc<dyn Fn() + Send + Sync>,
+    pub on_select: OnClick,
 }
 
 /// Component to handle radio group item selection events
 #[derive(Component)]
 pub struct RadioGroupItemHandler {
     pub index: usize,
-    pub on_select: Arc<dyn Fn(usize) + Send + Sync>,
+    pub on_select: OnChange<usize>,
 }
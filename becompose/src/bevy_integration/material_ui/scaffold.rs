@@ -0,0 +1,427 @@
+//! Scaffold Composable
+//!
+//! Arranges a screen following the Material 3 scaffold layout: a top bar and
+//! a bottom bar over the main content, a floating action button and a
+//! snackbar host above the bottom bar, and a modal navigation drawer on top.
+
+use bevy::prelude::*;
+use std::sync::Arc;
+
+use crate::bevy_integration::composables::{
+    end_group, pop_parent, push_parent, remember_state, scoped_container, spawn_child, start_group,
+    with_implicit_scope, State,
+};
+use crate::bevy_integration::material_ui::get_material_theme;
+use crate::components::Clickable;
+use crate::composition::ComposableType;
+use crate::modifier::Modifiers;
+
+/// Content of one scaffold slot
+pub type ScaffoldSlot = Arc<dyn Fn() + Send + Sync>;
+
+/// Space the main content must leave free for the bars laid over it
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ScaffoldInsets {
+    /// Height of the top bar
+    pub top: f32,
+    /// Height of the bottom bar
+    pub bottom: f32,
+}
+
+impl ScaffoldInsets {
+    /// Padding that keeps content clear of the bars
+    pub fn padding(&self) -> Modifiers {
+        Modifiers::new().padding_values(self.top, 0.0, self.bottom, 0.0)
+    }
+}
+
+/// Horizontal position of the floating action button
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScaffoldFabPosition {
+    /// At the end edge of the screen
+    #[default]
+    End,
+    /// Centered horizontally
+    Center,
+}
+
+/// Slots of a scaffold
+#[derive(Clone, Default)]
+pub struct ScaffoldConfig {
+    pub top_bar: Option<ScaffoldSlot>,
+    pub bottom_bar: Option<ScaffoldSlot>,
+    pub floating_action_button: Option<ScaffoldSlot>,
+    pub fab_position: ScaffoldFabPosition,
+    pub snackbar_host: Option<ScaffoldSlot>,
+    pub drawer: Option<ScaffoldSlot>,
+    pub drawer_open: bool,
+    pub on_drawer_dismiss: Option<ScaffoldSlot>,
+}
+
+impl ScaffoldConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn top_bar(mut self, top_bar: impl Fn() + Send + Sync + 'static) -> Self {
+        self.top_bar = Some(Arc::new(top_bar));
+        self
+    }
+
+    pub fn bottom_bar(mut self, bottom_bar: impl Fn() + Send + Sync + 'static) -> Self {
+        self.bottom_bar = Some(Arc::new(bottom_bar));
+        self
+    }
+
+    pub fn floating_action_button(mut self, fab: impl Fn() + Send + Sync + 'static) -> Self {
+        self.floating_action_button = Some(Arc::new(fab));
+        self
+    }
+
+    pub fn fab_position(mut self, position: ScaffoldFabPosition) -> Self {
+        self.fab_position = position;
+        self
+    }
+
+    pub fn snackbar_host(mut self, snackbar_host: impl Fn() + Send + Sync + 'static) -> Self {
+        self.snackbar_host = Some(Arc::new(snackbar_host));
+        self
+    }
+
+    /// Modal navigation drawer, shown over the screen while `open`
+    pub fn drawer(mut self, open: bool, drawer: impl Fn() + Send + Sync + 'static) -> Self {
+        self.drawer = Some(Arc::new(drawer));
+        self.drawer_open = open;
+        self
+    }
+
+    /// Called when the scrim behind an open drawer is clicked
+    pub fn on_drawer_dismiss(mut self, on_dismiss: impl Fn() + Send + Sync + 'static) -> Self {
+        self.on_drawer_dismiss = Some(Arc::new(on_dismiss));
+        self
+    }
+}
+
+/// Edge of the scaffold a measured bar sits on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaffoldEdge {
+    Top,
+    Bottom,
+}
+
+/// Bar container whose laid out height becomes the scaffold's inset on `edge`
+#[derive(Component, Clone, Copy)]
+pub struct ScaffoldBar {
+    pub edge: ScaffoldEdge,
+    pub insets: State<ScaffoldInsets>,
+}
+
+/// Margin between the FAB and the screen edges (Material 3)
+const FAB_MARGIN: f32 = 16.0;
+/// Margin around the snackbar host (Material 3)
+const SNACKBAR_MARGIN: f32 = 8.0;
+/// Maximum width of a modal navigation drawer (Material 3)
+const DRAWER_MAX_WIDTH: f32 = 360.0;
+/// Width of the edge left uncovered by a modal drawer on narrow screens
+const DRAWER_END_GAP: f32 = 56.0;
+
+/// Design scaffold composable
+///
+/// Lays out the slots of `config` around the main content. The top and bottom
+/// bars are drawn over the content and `content` receives the insets they
+/// occupy, so it can pad itself or scroll underneath them. The insets follow
+/// the bars' laid out size and update when the bars resize.
+///
+/// # Example
+/// ```ignore
+/// Scaffold(
+///     ScaffoldConfig::new()
+///         .top_bar(|| Text("Inbox", TextStyle::title()))
+///         .floating_action_button(|| Fab("edit", || compose_mail()))
+///         .snackbar_host(|| SnackbarHost()),
+///     |insets| {
+///         Column(insets.padding().fill_max_size(), || {
+///             Text("No new mail", TextStyle::body());
+///         });
+///     },
+/// );
+/// ```
+#[track_caller]
+pub fn Scaffold<F>(config: ScaffoldConfig, content: F)
+where
+    F: Fn(ScaffoldInsets) + Send + Sync + 'static,
+{
+    with_implicit_scope(|| {
+        let insets = remember_state(ScaffoldInsets::default());
+
+        let root = spawn_child((Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            position_type: PositionType::Relative,
+            ..default()
+        },));
+
+        scoped_container(
+            ComposableType::Custom("Scaffold".to_string()),
+            root,
+            move || compose_scaffold(&config, insets, &content),
+        );
+    });
+}
+
+/// Compose the slots of a scaffold inside its root container
+fn compose_scaffold<F>(config: &ScaffoldConfig, insets: State<ScaffoldInsets>, content: &F)
+where
+    F: Fn(ScaffoldInsets),
+{
+    // Bars that are gone no longer take any space
+    let mut current = insets.get();
+    if config.top_bar.is_none() {
+        current.top = 0.0;
+    }
+    if config.bottom_bar.is_none() {
+        current.bottom = 0.0;
+    }
+    if current != insets.get_untracked() {
+        insets.set_silent(current);
+    }
+
+    // Main content fills the scaffold, underneath everything else
+    slot(
+        "ScaffoldContent",
+        (Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            ..default()
+        },),
+        || content(current),
+    );
+
+    if let Some(top_bar) = &config.top_bar {
+        slot(
+            "ScaffoldTopBar",
+            (
+                Node {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(0.0),
+                    left: Val::Px(0.0),
+                    right: Val::Px(0.0),
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                ScaffoldBar {
+                    edge: ScaffoldEdge::Top,
+                    insets,
+                },
+            ),
+            || top_bar(),
+        );
+    }
+
+    if let Some(bottom_bar) = &config.bottom_bar {
+        slot(
+            "ScaffoldBottomBar",
+            (
+                Node {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(0.0),
+                    left: Val::Px(0.0),
+                    right: Val::Px(0.0),
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                ScaffoldBar {
+                    edge: ScaffoldEdge::Bottom,
+                    insets,
+                },
+            ),
+            || bottom_bar(),
+        );
+    }
+
+    // The snackbar host sits above the bottom bar, the FAB above the bottom
+    // bar at the end edge or centered
+    if let Some(snackbar_host) = &config.snackbar_host {
+        slot(
+            "ScaffoldSnackbarHost",
+            (Node {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(current.bottom + SNACKBAR_MARGIN),
+                left: Val::Px(SNACKBAR_MARGIN),
+                right: Val::Px(SNACKBAR_MARGIN),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },),
+            || snackbar_host(),
+        );
+    }
+
+    if let Some(fab) = &config.floating_action_button {
+        let (left, right, justify_content) = match config.fab_position {
+            ScaffoldFabPosition::End => (Val::Auto, Val::Px(FAB_MARGIN), JustifyContent::End),
+            ScaffoldFabPosition::Center => (Val::Px(0.0), Val::Px(0.0), JustifyContent::Center),
+        };
+        slot(
+            "ScaffoldFab",
+            (Node {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(current.bottom + FAB_MARGIN),
+                left,
+                right,
+                justify_content,
+                ..default()
+            },),
+            || fab(),
+        );
+    }
+
+    if let Some(drawer) = config.drawer.as_ref().filter(|_| config.drawer_open) {
+        let on_dismiss = config.on_drawer_dismiss.clone();
+        let theme = get_material_theme().unwrap_or_default();
+
+        // Scrim over the rest of the screen; clicking it dismisses the drawer
+        slot(
+            "ScaffoldDrawerScrim",
+            (
+                bevy::prelude::Button,
+                Node {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    ..default()
+                },
+                BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.32)),
+                Clickable::new(move || {
+                    if let Some(on_dismiss) = &on_dismiss {
+                        on_dismiss();
+                    }
+                }),
+            ),
+            || {},
+        );
+
+        slot(
+            "ScaffoldDrawer",
+            (
+                Node {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(0.0),
+                    bottom: Val::Px(0.0),
+                    left: Val::Px(0.0),
+                    width: Val::Percent(100.0),
+                    max_width: Val::Px(DRAWER_MAX_WIDTH),
+                    margin: UiRect::right(Val::Px(DRAWER_END_GAP)),
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                BackgroundColor(theme.surface_container),
+                BorderRadius::right(Val::Px(16.0)),
+            ),
+            || drawer(),
+        );
+    }
+}
+
+/// Compose a slot into its own container entity
+#[track_caller]
+fn slot<B: Bundle>(name: &str, container: B, content: impl FnOnce()) {
+    let scope_id = start_group(ComposableType::Custom(name.to_string()), None);
+    let entity = spawn_child(container);
+    push_parent(entity);
+
+    content();
+
+    pop_parent();
+    end_group(scope_id);
+}
+
+/// Update scaffold insets from the laid out size of their bars
+pub fn update_scaffold_insets(bars: Query<(&ComputedNode, &ScaffoldBar), Changed<ComputedNode>>) {
+    for (node, bar) in &bars {
+        let height = node.size().y * node.inverse_scale_factor();
+        let mut insets = bar.insets.get_untracked();
+        let inset = match bar.edge {
+            ScaffoldEdge::Top => &mut insets.top,
+            ScaffoldEdge::Bottom => &mut insets.bottom,
+        };
+        if *inset != height {
+            *inset = height;
+            bar.insets.set(insets);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bevy_integration::Text;
+    use crate::components::TextStyle;
+    use crate::testing::*;
+
+    fn label(text: &'static str) -> impl Fn() + Send + Sync + 'static {
+        move || Text(text, TextStyle::body())
+    }
+
+    #[test]
+    fn scaffold_composes_its_slots_over_the_content() {
+        let mut app = compose_app(|| {
+            Scaffold(
+                ScaffoldConfig::new()
+                    .top_bar(label("top"))
+                    .bottom_bar(label("bottom"))
+                    .snackbar_host(label("snackbar"))
+                    .floating_action_button(label("fab"))
+                    .drawer(false, label("drawer")),
+                |_| Text("content", TextStyle::body()),
+            );
+        });
+
+        assert_eq!(
+            texts(&mut app),
+            ["content", "top", "bottom", "snackbar", "fab"]
+        );
+        assert_eq!(count::<ScaffoldBar>(&mut app), 2);
+    }
+
+    #[test]
+    fn content_receives_the_laid_out_bar_heights() {
+        let mut app = compose_app(|| {
+            Scaffold(
+                ScaffoldConfig::new()
+                    .top_bar(label("top"))
+                    .bottom_bar(label("bottom")),
+                |insets| {
+                    Text(
+                        format!("{} {}", insets.top, insets.bottom),
+                        TextStyle::body(),
+                    )
+                },
+            );
+        });
+        assert_eq!(texts(&mut app)[0], "0 0");
+
+        let world = app.world_mut();
+        let bars: Vec<(Entity, ScaffoldEdge)> = world
+            .query::<(Entity, &ScaffoldBar)>()
+            .iter(world)
+            .map(|(entity, bar)| (entity, bar.edge))
+            .collect();
+        for (entity, edge) in bars {
+            let height = match edge {
+                ScaffoldEdge::Top => 64.0,
+                ScaffoldEdge::Bottom => 80.0,
+            };
+            world.entity_mut(entity).insert(ComputedNode {
+                size: Vec2::new(400.0, height),
+                inverse_scale_factor: 1.0,
+                ..default()
+            });
+        }
+        app.update();
+        app.update();
+
+        assert_eq!(texts(&mut app)[0], "64 80");
+    }
+}
@@ -18,6 +18,7 @@ use crate::bevy_integration::material_ui::spawn_material_child;
 ///     println!("Selected index: {}", index);
 /// });
 /// ```
+#[track_caller]
 pub fn Select<F>(
     label: impl Into<String>,
     options: &[impl AsRef<str>],
@@ -56,6 +57,7 @@ pub fn Select<F>(
 }
 
 /// Design select composable with configuration
+#[track_caller]
 pub fn SelectConfigured<F>(config: SelectConfig, on_select: F)
 where
     F: Fn(usize) + Send + Sync + 'static,
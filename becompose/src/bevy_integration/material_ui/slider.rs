@@ -4,10 +4,10 @@
 
 use bevy::prelude::*;
 use bevy_material_ui::prelude::*;
-use std::sync::Arc;
 
 use crate::bevy_integration::composables::with_implicit_scope;
 use crate::bevy_integration::material_ui::spawn_material_child;
+use crate::components::{IntoOnChange, OnChange};
 
 /// Design slider composable
 ///
@@ -17,12 +17,10 @@ use crate::bevy_integration::material_ui::spawn_material_child;
 ///     println!("Slider value: {}", value);
 /// });
 /// ```
-pub fn Slider<F>(value: f32, min: f32, max: f32, on_change: F)
-where
-    F: Fn(f32) + Send + Sync + 'static,
-{
+#[track_caller]
+pub fn Slider<M>(value: f32, min: f32, max: f32, on_change: impl IntoOnChange<f32, M>) {
     with_implicit_scope(|| {
-        let on_change = Arc::new(on_change);
+        let on_change = on_change.into_on_change();
 
         spawn_material_child(move |commands, theme| {
             let slider_bundle = SliderBuilder::new(min, max).value(value).build(theme);
@@ -45,13 +43,17 @@ where
 ///     println!("Volume: {}", value);
 /// });
 /// ```
-pub fn SliderWithLabel<F>(label: impl Into<String>, value: f32, min: f32, max: f32, on_change: F)
-where
-    F: Fn(f32) + Send + Sync + 'static,
-{
+#[track_caller]
+pub fn SliderWithLabel<M>(
+    label: impl Into<String>,
+    value: f32,
+    min: f32,
+    max: f32,
+    on_change: impl IntoOnChange<f32, M>,
+) {
     with_implicit_scope(|| {
         let label = label.into();
-        let on_change = Arc::new(on_change);
+        let on_change = on_change.into_on_change();
 
         spawn_material_child(move |commands, theme| {
             let column = commands
@@ -96,12 +98,10 @@ where
 }
 
 /// Design slider composable with configuration
-pub fn SliderConfigured<F>(config: SliderConfig, on_change: F)
-where
-    F: Fn(f32) + Send + Sync + 'static,
-{
+#[track_caller]
+pub fn SliderConfigured<M>(config: SliderConfig, on_change: impl IntoOnChange<f32, M>) {
     with_implicit_scope(|| {
-        let on_change = Arc::new(on_change);
+        let on_change = on_change.into_on_change();
 
         spawn_material_child(move |commands, theme| {
             let column = commands
@@ -212,5 +212,5 @@ impl SliderConfig {
 /// Component to handle slider change events
 #[derive(Component)]
 pub struct SliderChangeHandler {
-    pub on_change: Arc<dyn Fn(f32) + Send + Sync>,
+    pub on_change: OnChange<f32>,
 }
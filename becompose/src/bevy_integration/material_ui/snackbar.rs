@@ -24,6 +24,7 @@ use crate::bevy_integration::material_ui::spawn_material_child;
 ///     writer.send(ShowSnackbar::new("Message saved"));
 /// }
 /// ```
+#[track_caller]
 pub fn SnackbarHost() {
     with_implicit_scope(|| {
         spawn_material_child(move |commands, _theme| {
@@ -42,6 +43,7 @@ pub fn SnackbarHost() {
 }
 
 /// Design snackbar composable with position
+#[track_caller]
 pub fn SnackbarHostPositioned(position: SnackbarPosition) {
     with_implicit_scope(|| {
         spawn_material_child(move |commands, _theme| {
@@ -17,6 +17,7 @@ use crate::bevy_integration::material_ui::spawn_material_child;
 ///     println!("Switch is now: {}", if selected { "ON" } else { "OFF" });
 /// });
 /// ```
+#[track_caller]
 pub fn Switch<F>(label: impl Into<String>, initial_selected: bool, on_change: F)
 where
     F: Fn(bool) + Send + Sync + 'static,
@@ -69,6 +70,7 @@ where
 }
 
 /// Design switch composable with configuration
+#[track_caller]
 pub fn SwitchConfigured<F>(config: SwitchConfig, on_change: F)
 where
     F: Fn(bool) + Send + Sync + 'static,
@@ -18,6 +18,7 @@ use crate::bevy_integration::material_ui::spawn_material_child;
 ///     println!("Selected tab: {}", index);
 /// });
 /// ```
+#[track_caller]
 pub fn Tabs<F>(tabs: &[impl AsRef<str>], selected_index: usize, on_select: F)
 where
     F: Fn(usize) + Send + Sync + 'static,
@@ -109,6 +110,7 @@ where
 ///     println!("Selected tab: {}", index);
 /// });
 /// ```
+#[track_caller]
 pub fn TabsWithIcons<F>(
     tabs: &[(impl AsRef<str>, impl AsRef<str>)],
     selected_index: usize,
@@ -196,6 +198,7 @@ pub fn TabsWithIcons<F>(
 }
 
 /// Design tabs composable with configuration
+#[track_caller]
 pub fn TabsConfigured<F>(config: TabsConfig, on_select: F)
 where
     F: Fn(usize) + Send + Sync + 'static,
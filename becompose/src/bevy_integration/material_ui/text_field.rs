@@ -17,6 +17,7 @@ use crate::bevy_integration::material_ui::spawn_material_child;
 ///     println!("Text changed: {}", value);
 /// });
 /// ```
+#[track_caller]
 pub fn FilledTextField<F>(label: impl Into<String>, initial_value: impl Into<String>, on_change: F)
 where
     F: Fn(String) + Send + Sync + 'static,
@@ -32,6 +33,7 @@ where
 ///     println!("Email changed: {}", value);
 /// });
 /// ```
+#[track_caller]
 pub fn OutlinedTextField<F>(
     label: impl Into<String>,
     initial_value: impl Into<String>,
@@ -50,6 +52,7 @@ pub fn OutlinedTextField<F>(
 ///     println!("Name: {}", value);
 /// });
 /// ```
+#[track_caller]
 pub fn TextField<F>(
     label: impl Into<String>,
     initial_value: impl Into<String>,
@@ -81,6 +84,7 @@ pub fn TextField<F>(
 }
 
 /// Design text field composable with full configuration
+#[track_caller]
 pub fn TextFieldConfigured<F, S>(config: TextFieldConfig, on_change: F, on_submit: S)
 where
     F: Fn(String) + Send + Sync + 'static,
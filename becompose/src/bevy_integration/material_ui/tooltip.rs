@@ -17,6 +17,7 @@ use crate::bevy_integration::material_ui::spawn_material_child_with_children;
 ///     });
 /// });
 /// ```
+#[track_caller]
 pub fn Tooltip<C>(text: impl AsRef<str>, content: C)
 where
     C: FnOnce() + Send + Sync + 'static,
@@ -49,6 +50,7 @@ where
 }
 
 /// Design tooltip with position configuration
+#[track_caller]
 pub fn TooltipPositioned<C>(text: impl AsRef<str>, position: TooltipPosition, content: C)
 where
     C: FnOnce() + Send + Sync + 'static,
@@ -81,6 +83,7 @@ where
 }
 
 /// Design rich tooltip with title
+#[track_caller]
 pub fn RichTooltip<C>(title: impl AsRef<str>, text: impl AsRef<str>, content: C)
 where
     C: FnOnce() + Send + Sync + 'static,
@@ -119,6 +122,7 @@ where
 }
 
 /// Design tooltip with full configuration
+#[track_caller]
 pub fn TooltipConfigured<C>(config: TooltipComposableConfig, content: C)
 where
     C: FnOnce() + Send + Sync + 'static,
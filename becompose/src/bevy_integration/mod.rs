@@ -4,16 +4,36 @@
 
 mod app;
 mod composables;
+mod compose_root;
+mod effects;
 mod entity_bridge;
 mod input_bridge;
+mod layout_bridge;
+mod local;
 pub mod material_ui;
+mod movable;
 mod plugin;
+mod producer;
+mod query;
+mod resource;
+mod runtime;
+mod saveable;
 mod ui_builder;
 
 pub use app::*;
 pub use composables::*;
+pub use compose_root::*;
+pub use effects::*;
 pub use entity_bridge::*;
 pub use input_bridge::*;
+pub use layout_bridge::*;
+pub use local::*;
 pub use material_ui::*;
+pub use movable::*;
 pub use plugin::*;
+pub use producer::*;
+pub use query::*;
+pub use resource::*;
+pub use runtime::*;
+pub use saveable::*;
 pub use ui_builder::*;
@@ -0,0 +1,281 @@
+//! Movable Content
+//!
+//! Content that keeps its state and entities when it is emitted from a
+//! different place in the composition. `movable_content_of` wraps content in a
+//! handle; when a parent stops emitting the handle and another parent emits it
+//! during the same frame, the composed subtree is moved to the new parent
+//! instead of being recomposed from scratch.
+//!
+//! Content that is not emitted again by the end of the frame is disposed of
+//! like any other removed scope.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use bevy::prelude::*;
+
+use super::composables::{
+    current_scope_id, emit_child, keyed_scoped_container, spawn_child, with_tree,
+};
+use crate::composition::{ComposableType, CompositionKey, ScopedContentFn};
+
+/// Marks the container entity of movable content
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovableContentRoot(pub u64);
+
+/// Content that can be moved between parents without losing its state
+///
+/// # Example
+/// ```ignore
+/// let wide = remember_state(false);
+/// let player = remember(|| movable_content_of(|| VideoPlayer()));
+///
+/// IfElse(
+///     wide.get(),
+///     || Row(Modifiers::new(), || {
+///         player.emit();
+///         Sidebar();
+///     }),
+///     || Column(Modifiers::new(), || player.emit()),
+/// );
+/// ```
+#[derive(Clone)]
+pub struct MovableContent {
+    id: u64,
+    content: ScopedContentFn,
+}
+
+/// Wrap `content` so it can be emitted from different places while keeping
+/// its state and entities
+pub fn movable_content_of<F>(content: F) -> MovableContent
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    static NEXT_MOVABLE_ID: AtomicU64 = AtomicU64::new(0);
+
+    MovableContent {
+        id: NEXT_MOVABLE_ID.fetch_add(1, Ordering::Relaxed),
+        content: Arc::new(content),
+    }
+}
+
+impl MovableContent {
+    /// Emit the content at the current position.
+    ///
+    /// The first time, the content is composed into its own container. After
+    /// that the existing container and scope are moved here, reparenting their
+    /// entities without recomposing them. Content can only be emitted once per
+    /// pass of its parent; later emissions while that pass runs are ignored.
+    pub fn emit(&self) {
+        let Some(parent) = current_scope_id() else {
+            (self.content)();
+            return;
+        };
+        if !with_tree(|tree| tree.mark_movable_emitted(self.id, parent)) {
+            warn!("movable content {} emitted more than once", self.id);
+            return;
+        }
+
+        let moved = with_tree(|tree| {
+            let scope_id = tree.claim_movable(self.id, parent)?;
+            tree.get_entity(scope_id)
+        });
+        if let Some(container) = moved {
+            emit_child(container);
+            return;
+        }
+
+        let container = spawn_child((
+            Node {
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            MovableContentRoot(self.id),
+        ));
+        let scope_id = keyed_scoped_container(
+            ComposableType::Custom("MovableContent".to_string()),
+            Some(CompositionKey::from(format!("movable:{}", self.id))),
+            container,
+            self.content.clone(),
+        );
+        with_tree(|tree| tree.register_movable(self.id, scope_id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bevy_integration::{
+        remember_state, Column, ComposeRoot, IfElse, Row, Scope, State, Text,
+    };
+    use crate::components::TextStyle;
+    use crate::modifier::Modifiers;
+    use crate::testing::*;
+
+    /// Movable content showing a remembered counter
+    fn counter(captured: Captured<State<i32>>) -> MovableContent {
+        movable_content_of(move || {
+            let value = remember_state(0);
+            captured.set(value);
+            Text(value.get().to_string(), TextStyle::body());
+        })
+    }
+
+    /// Host entity of the compose root the movable container is composed in
+    fn host_of_container(app: &mut App) -> Entity {
+        let world = app.world_mut();
+        let container = world
+            .query_filtered::<Entity, With<MovableContentRoot>>()
+            .single(world)
+            .unwrap();
+        let mut host = container;
+        while let Some(parent) = world.get::<ChildOf>(host) {
+            host = parent.parent();
+        }
+        host
+    }
+
+    #[test]
+    fn moved_content_keeps_its_state_and_entities() {
+        let value = Captured::new();
+        let wide = Captured::new();
+        let content = counter(value.clone());
+        let mut app = compose_app({
+            let wide = wide.clone();
+            move || {
+                let is_wide = remember_state(false);
+                wide.set(is_wide);
+                let (narrow, side) = (content.clone(), content.clone());
+                IfElse(
+                    is_wide.get(),
+                    move || Row(Modifiers::new(), move || side.emit()),
+                    move || Column(Modifiers::new(), move || narrow.emit()),
+                );
+            }
+        });
+        value.get().set(5);
+        app.update();
+        let containers = entities::<MovableContentRoot>(&mut app);
+
+        wide.get().set(true);
+        app.update();
+
+        assert_eq!(texts(&mut app), ["5"]);
+        assert_eq!(entities::<MovableContentRoot>(&mut app), containers);
+        assert_eq!(value.get().get_untracked(), 5);
+    }
+
+    #[test]
+    fn content_that_is_not_emitted_again_is_disposed() {
+        let value = Captured::new();
+        let shown = Captured::new();
+        let content = counter(value.clone());
+        let mut app = compose_app({
+            let shown = shown.clone();
+            move || {
+                let is_shown = remember_state(true);
+                shown.set(is_shown);
+                let content = content.clone();
+                Column(Modifiers::new(), move || {
+                    if is_shown.get() {
+                        content.emit();
+                    }
+                });
+            }
+        });
+        let before = stats(&app);
+
+        shown.get().set(false);
+        app.update();
+
+        assert_eq!(count::<MovableContentRoot>(&mut app), 0);
+        assert!(value.get().try_read_value().is_none());
+        assert!(stats(&app).nodes < before.nodes);
+        assert!(texts(&mut app).is_empty());
+    }
+
+    #[test]
+    fn content_is_kept_when_its_parent_recomposes_twice_in_a_frame() {
+        let value = Captured::new();
+        let step = Captured::new();
+        let content = counter(value.clone());
+        let mut app = compose_app({
+            let step = step.clone();
+            move || {
+                let step = step.clone();
+                let content = content.clone();
+                Scope(move || {
+                    let current = remember_state(0);
+                    step.set(current);
+                    // Written while composing, so the scope recomposes again
+                    // in the same frame
+                    if current.get() == 1 {
+                        current.set(2);
+                    }
+                    content.emit();
+                });
+            }
+        });
+        value.get().set(5);
+        app.update();
+        let containers = entities::<MovableContentRoot>(&mut app);
+
+        step.get().set(1);
+        app.update();
+
+        assert_eq!(step.get().get_untracked(), 2);
+        assert_eq!(entities::<MovableContentRoot>(&mut app), containers);
+        assert_eq!(texts(&mut app), ["5"]);
+        assert_eq!(value.get().get_untracked(), 5);
+    }
+
+    #[test]
+    fn content_moves_between_passes_of_the_same_frame() {
+        let value = Captured::new();
+        let content = counter(value.clone());
+        let mut app = compose_app(|| {});
+        let first = app
+            .world_mut()
+            .spawn((
+                Node::default(),
+                ComposeRoot::new({
+                    let content = content.clone();
+                    move || content.emit()
+                }),
+            ))
+            .id();
+        let second_shows = Captured::new();
+        let second = app
+            .world_mut()
+            .spawn((
+                Node::default(),
+                ComposeRoot::new({
+                    let content = content.clone();
+                    let second_shows = second_shows.clone();
+                    move || {
+                        let shows = remember_state(false);
+                        second_shows.set(shows);
+                        if shows.get() {
+                            content.emit();
+                        }
+                    }
+                }),
+            ))
+            .id();
+        app.update();
+        value.get().set(5);
+        app.update();
+        assert_eq!(host_of_container(&mut app), first);
+
+        // The first root drops the content while it is mounted again, the
+        // second emits it when it is recomposed afterwards
+        app.world_mut()
+            .entity_mut(first)
+            .insert(ComposeRoot::new(|| {}));
+        second_shows.get().set(true);
+        app.update();
+
+        assert_eq!(host_of_container(&mut app), second);
+        assert_eq!(value.get().get_untracked(), 5);
+    }
+}
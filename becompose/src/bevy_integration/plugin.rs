@@ -3,28 +3,86 @@
 //! Main Bevy plugin for BECOMPOSE.
 
 use bevy::prelude::*;
+use bevy::ui::UiSystems;
 
-use super::{handle_button_interactions, sync_composition_to_entities};
-use crate::composition::{CompositionTree, DirtyFlags};
+use super::app::{
+    incremental_recompose_ui, initial_composition, release_dropped_movables, ScopeRegistry,
+};
+use super::compose_root::mount_compose_roots;
+use super::material_ui::{
+    handle_material_clicks, handle_material_slider_changes, update_scaffold_insets,
+};
+use super::producer::apply_produced_states;
+use super::query::{poll_new_watched_queries, poll_watched_queries};
+use super::saveable::save_saveable_states;
+use super::{
+    handle_button_interactions, sync_composition_to_entities, update_measured_constraints,
+    ComposeRoots, ComposeRuntime, CompositionErrorEvent,
+};
+use crate::composition::{CompositionTree, DirtyFlags, RecomposeScheduler};
 
 /// Main plugin for BECOMPOSE
+///
+/// Runs the reactive loop: the content of a `BecomposeApp` is composed on
+/// startup, `ComposeRoot`s are mounted as they are added, and invalidated
+/// scopes are recomposed every frame. Saveable states are saved when the app
+/// exits.
 pub struct BecomposePlugin;
 
 impl Plugin for BecomposePlugin {
     fn build(&self, app: &mut App) {
         app
             // Resources
+            .init_resource::<ComposeRuntime>()
             .init_resource::<CompositionTree>()
             .init_resource::<DirtyFlags>()
+            .init_resource::<RecomposeScheduler>()
             .init_resource::<UiRoot>()
+            .init_resource::<ScopeRegistry>()
+            .init_resource::<ComposeRoots>()
+            // Messages
+            .add_message::<CompositionErrorEvent>()
             // Systems
+            .add_systems(Startup, initial_composition)
             .add_systems(
                 Update,
-                (sync_composition_to_entities, handle_button_interactions).chain(),
-            );
+                (
+                    poll_watched_queries,
+                    apply_produced_states,
+                    mount_compose_roots,
+                    incremental_recompose_ui,
+                    // Recompose the scopes of queries read for the first time
+                    poll_new_watched_queries,
+                    incremental_recompose_ui.run_if(has_dirty_scopes),
+                    release_dropped_movables,
+                )
+                    .chain(),
+            )
+            // Bridge and handle input on the entities composed this frame
+            .add_systems(
+                Update,
+                (sync_composition_to_entities, handle_button_interactions)
+                    .chain()
+                    .after(release_dropped_movables),
+            )
+            .add_systems(
+                Update,
+                (handle_material_clicks, handle_material_slider_changes)
+                    .after(release_dropped_movables),
+            )
+            .add_systems(
+                PostUpdate,
+                update_measured_constraints.after(UiSystems::Layout),
+            )
+            .add_systems(PostUpdate, update_scaffold_insets.after(UiSystems::Layout))
+            .add_systems(Last, save_saveable_states);
     }
 }
 
+fn has_dirty_scopes(runtime: Res<ComposeRuntime>) -> bool {
+    runtime.has_dirty_scopes()
+}
+
 /// Resource holding the root UI entity
 #[derive(Resource, Default)]
 pub struct UiRoot {
@@ -0,0 +1,343 @@
+//! Produced State
+//!
+//! States fed by long-lived producers. `produce_state` runs an async producer
+//! on a task pool for as long as its scope is in the composition, and
+//! `collect_as_state` drains a channel every frame.
+//!
+//! Values produced between two frames are not written to the state as they
+//! arrive. The runtime applies the latest one before recomposing, so a
+//! producer faster than the frame rate causes one recomposition per frame.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+
+use bevy::prelude::*;
+
+use super::composables::{current_scope_id, remember, with_scope_node, State};
+use super::effects::{launch, EffectPool};
+use super::runtime::{current_runtime, ComposeRuntime};
+
+/// Applies the values produced for one state since the last frame
+type ApplyProduced = Box<dyn FnMut() + Send>;
+
+/// Produced states of a `ComposeRuntime`, by id
+#[derive(Default)]
+pub(crate) struct ProducedStates {
+    appliers: Mutex<HashMap<u64, ApplyProduced>>,
+}
+
+impl ProducedStates {
+    fn apply(&self) {
+        for apply in self.appliers.lock().unwrap().values_mut() {
+            apply();
+        }
+    }
+}
+
+/// System that writes the values produced since the last frame to their
+/// states
+pub(crate) fn apply_produced_states(runtime: Res<ComposeRuntime>) {
+    runtime.produced_states().apply();
+}
+
+/// Register `apply` to run every frame until the current scope is disposed.
+///
+/// Outside a runtime nothing would run it, so the state is never written.
+fn register_applier(apply: ApplyProduced) {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+    let Some(runtime) = current_runtime() else {
+        warn!("produced state created outside a compose runtime is never updated");
+        return;
+    };
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    runtime
+        .produced_states()
+        .appliers
+        .lock()
+        .unwrap()
+        .insert(id, apply);
+    if let Some(scope_id) = current_scope_id() {
+        with_scope_node(scope_id, |node| {
+            node.add_disposal(Box::new(move || {
+                runtime
+                    .produced_states()
+                    .appliers
+                    .lock()
+                    .unwrap()
+                    .remove(&id);
+            }));
+        });
+    }
+}
+
+/// Handle a `produce_state` producer sends values through.
+///
+/// Sent values are written to the state before the next recomposition. Only
+/// the last value sent before then is written.
+pub struct StateSink<T: 'static> {
+    state: State<T>,
+    /// Value sent since the last frame
+    pending: Arc<Mutex<Option<T>>>,
+}
+
+impl<T: 'static> Clone for StateSink<T> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state,
+            pending: self.pending.clone(),
+        }
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> StateSink<T> {
+    /// Replace the value of the state
+    pub fn send(&self, value: T) {
+        *self.pending.lock().unwrap() = Some(value);
+    }
+
+    /// Update the last value sent, or the value of the state if nothing was
+    /// sent since the last frame
+    pub fn update(&self, f: impl FnOnce(&mut T)) {
+        let mut pending = self.pending.lock().unwrap();
+        let Some(mut value) = pending.take().or_else(|| self.state.try_read_value()) else {
+            return;
+        };
+        f(&mut value);
+        *pending = Some(value);
+    }
+
+    /// The last value sent, or the value of the state if nothing was sent
+    /// since the last frame
+    pub fn get(&self) -> Option<T> {
+        let pending = self.pending.lock().unwrap();
+        pending.clone().or_else(|| self.state.try_read_value())
+    }
+}
+
+/// Remember a `State` that starts at `initial` and is fed by `producer`.
+///
+/// The future returned by `producer` is spawned on the `AsyncComputeTaskPool`
+/// the first time the call composes, and is cancelled when the scope leaves
+/// the composition. It sends values through the `StateSink` it is given.
+/// Values sent between two frames are coalesced: the last one is written
+/// before the next recomposition.
+///
+/// # Example
+/// ```ignore
+/// let progress = produce_state(0.0, |sink| async move {
+///     while let Some(update) = downloads.next().await {
+///         sink.send(update.fraction());
+///     }
+/// });
+/// Text(format!("{:.0}%", progress.get() * 100.0), TextStyle::body());
+/// ```
+pub fn produce_state<T, P, Fut>(initial: T, producer: P) -> State<T>
+where
+    T: Clone + Send + Sync + 'static,
+    P: FnOnce(StateSink<T>) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let (state, pending) = remember(|| {
+        let state = State::new(initial);
+        let pending: Arc<Mutex<Option<T>>> = Arc::new(Mutex::new(None));
+        let applied = pending.clone();
+        register_applier(Box::new(move || {
+            if let Some(value) = applied.lock().unwrap().take() {
+                state.set(value);
+            }
+        }));
+        (state, pending)
+    });
+    launch(EffectPool::Compute, (), || {
+        producer(StateSink { state, pending })
+    });
+    state
+}
+
+/// Channel receivers `collect_as_state` can drain
+pub trait StateReceiver<T>: Send + 'static {
+    /// The next value received, if one is ready
+    fn try_next(&mut self) -> Option<T>;
+}
+
+impl<T: Send + 'static> StateReceiver<T> for mpsc::Receiver<T> {
+    fn try_next(&mut self) -> Option<T> {
+        self.try_recv().ok()
+    }
+}
+
+impl<T: Send + 'static> StateReceiver<T> for crossbeam_channel::Receiver<T> {
+    fn try_next(&mut self) -> Option<T> {
+        self.try_recv().ok()
+    }
+}
+
+/// Remember a `State` that starts at `T::default()` and takes every value
+/// received from `receiver`.
+///
+/// The receiver is drained before every recomposition, and the state is set
+/// to the last value received, so a sender faster than the frame rate causes
+/// one recomposition per frame. The receiver passed the first time the call
+/// composes is kept until a recomposition passes a different `key`, which
+/// swaps in the receiver passed with it; with the same key, the new receiver
+/// is dropped. The state keeps its value across the swap, and the receiver is
+/// dropped when the scope leaves the composition.
+///
+/// # Example
+/// ```ignore
+/// // `SimulationFeed` holds the receiving end of a crossbeam channel fed by
+/// // a simulation thread, and the id of the running simulation
+/// let feed = use_resource::<SimulationFeed>();
+/// let stats = collect_as_state(feed.simulation, feed.receiver.clone());
+/// Text(format!("Tick {}", stats.get().tick), TextStyle::body());
+/// ```
+pub fn collect_as_state<K, T, R>(key: K, receiver: R) -> State<T>
+where
+    K: PartialEq + Send + Sync + 'static,
+    T: Clone + Default + Send + Sync + 'static,
+    R: StateReceiver<T>,
+{
+    let (state, source) = remember(|| {
+        let state = State::new(T::default());
+        let source: Arc<Mutex<Option<(K, R)>>> = Arc::new(Mutex::new(None));
+        let drained = source.clone();
+        register_applier(Box::new(move || {
+            let mut source = drained.lock().unwrap();
+            let Some((_, receiver)) = source.as_mut() else {
+                return;
+            };
+            if let Some(value) = std::iter::from_fn(|| receiver.try_next()).last() {
+                state.set(value);
+            }
+        }));
+        (state, source)
+    });
+    let mut source = source.lock().unwrap();
+    if source.as_ref().is_none_or(|(current, _)| *current != key) {
+        *source = Some((key, receiver));
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bevy_integration::{remember_state, Column, Scope, Text};
+    use crate::components::TextStyle;
+    use crate::modifier::Modifiers;
+    use crate::testing::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize};
+
+    fn appliers(app: &App) -> usize {
+        let runtime = app.world().resource::<ComposeRuntime>();
+        runtime.produced_states().appliers.lock().unwrap().len()
+    }
+
+    #[test]
+    fn values_received_between_frames_cost_one_recomposition() {
+        let (sender, receiver) = crossbeam_channel::unbounded::<u32>();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let mut app = compose_app({
+            let runs = runs.clone();
+            move || {
+                let (receiver, runs) = (receiver.clone(), runs.clone());
+                Scope(move || {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                    let latest = collect_as_state((), receiver.clone());
+                    Text(format!("{}", latest.get()), TextStyle::body());
+                });
+            }
+        });
+        assert_eq!(texts(&mut app), ["0"]);
+        let before = runs.load(Ordering::SeqCst);
+
+        for value in 1..=3 {
+            sender.send(value).unwrap();
+        }
+        app.update();
+
+        assert_eq!(texts(&mut app), ["3"]);
+        assert_eq!(runs.load(Ordering::SeqCst), before + 1);
+    }
+
+    #[test]
+    fn a_new_key_swaps_in_the_new_receiver() {
+        let (first, first_receiver) = crossbeam_channel::unbounded::<u32>();
+        let (second, second_receiver) = crossbeam_channel::unbounded::<u32>();
+        let receivers = [first_receiver, second_receiver];
+        let feed = Captured::new();
+        let mut app = compose_app({
+            let feed = feed.clone();
+            move || {
+                let current = remember_state(0);
+                feed.set(current);
+                let receivers = receivers.clone();
+                Scope(move || {
+                    let index = current.get();
+                    let latest = collect_as_state(index, receivers[index].clone());
+                    Text(format!("{}", latest.get()), TextStyle::body());
+                });
+            }
+        });
+        first.send(1).unwrap();
+        second.send(10).unwrap();
+        app.update();
+        assert_eq!(texts(&mut app), ["1"]);
+
+        feed.get().set(1);
+        app.update();
+        first.send(2).unwrap();
+        second.send(20).unwrap();
+        app.update();
+
+        assert_eq!(texts(&mut app), ["20"]);
+    }
+
+    #[test]
+    fn producers_feed_their_state_through_the_sink() {
+        let mut app = compose_app(|| {
+            let log = produce_state(Vec::new(), |sink| async move {
+                sink.update(|log| log.push("started"));
+                sink.update(|log| log.push("ready"));
+                std::future::pending::<()>().await;
+            });
+            Scope(move || Text(log.get().join(", "), TextStyle::body()));
+        });
+
+        update_until(&mut app, |app| texts(app) == ["started, ready"]);
+    }
+
+    #[test]
+    fn producers_stop_when_their_scope_leaves() {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let shown = Captured::<State<bool>>::new();
+        let mut app = compose_app({
+            let (dropped, shown) = (dropped.clone(), shown.clone());
+            move || {
+                let visible = remember_state(true);
+                shown.set(visible);
+                let dropped = dropped.clone();
+                Column(Modifiers::new(), move || {
+                    if visible.get() {
+                        let dropped = dropped.clone();
+                        Scope(move || {
+                            let flag = DropFlag(dropped.clone());
+                            produce_state(0, |_| async move {
+                                let _flag = flag;
+                                std::future::pending::<()>().await;
+                            });
+                        });
+                    }
+                });
+            }
+        });
+        assert_eq!(appliers(&app), 1);
+
+        shown.get().set(false);
+        update_until(&mut app, |_| dropped.load(Ordering::Acquire));
+        assert_eq!(appliers(&app), 0);
+    }
+}
@@ -0,0 +1,400 @@
+//! Query Hooks
+//!
+//! Read ECS query results from composables. `use_query` registers its query
+//! with the runtime, which checks it against the world every frame before
+//! recomposing. The scope reading the query is invalidated when entities
+//! start or stop matching it, or when a component it reads changes, and the
+//! mapped results are different.
+//!
+//! Queries registered while recomposing are checked right after the pass,
+//! and their scopes recomposed again in the same frame, so a call reads
+//! results from the frame it first composes in.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use bevy::ecs::component::{ComponentId, Tick};
+use bevy::ecs::query::{ComponentAccessKind, QueryData, QueryFilter, QueryState, ROQueryItem};
+use bevy::prelude::*;
+
+use super::composables::{current_scope_id, remember, with_scope_node};
+use super::runtime::{current_runtime, ComposeRuntime, Subscriber};
+
+/// Maps the items of a watched query to the values a composable reads
+type QueryMap<D, T> = Box<dyn for<'w, 's> Fn(ROQueryItem<'w, 's, D>) -> T + Send + Sync>;
+
+/// A query registered by a `use_query` call
+trait QueryReader: Send {
+    /// Check the query against `world`, returning whether its results changed
+    fn poll(&mut self, world: &World) -> bool;
+
+    /// Whether the query was not checked yet
+    fn is_new(&self) -> bool;
+
+    fn subscriber(&self) -> &Subscriber;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+struct MappedQuery<D: QueryData + 'static, F: QueryFilter + 'static, T> {
+    subscriber: Subscriber,
+    /// Created once the components of the query are registered
+    state: Option<QueryState<(Entity, D), F>>,
+    /// Entities matching the query with their change ticks, to check the
+    /// results without mapping them
+    ticks: Option<QueryState<(Entity, EntityRef<'static>), F>>,
+    /// Components the query reads, whose changes invalidate the results
+    components: Vec<ComponentId>,
+    map: QueryMap<D, T>,
+    /// Whether `map` was replaced since the results were mapped
+    remap: bool,
+    /// Entities matched by the last poll, in order
+    entities: Vec<Entity>,
+    /// Tick of the last poll
+    last_run: Option<Tick>,
+    results: Arc<Mutex<Vec<T>>>,
+}
+
+impl<D, F, T> QueryReader for MappedQuery<D, F, T>
+where
+    D: QueryData + 'static,
+    F: QueryFilter + 'static,
+    T: Clone + PartialEq + Send + Sync + 'static,
+{
+    fn poll(&mut self, world: &World) -> bool {
+        if self.state.is_none() {
+            self.state = world.try_query_filtered::<(Entity, D), F>();
+            self.ticks = world.try_query_filtered::<(Entity, EntityRef), F>();
+            if let Some(state) = &self.state {
+                self.components = state
+                    .component_access()
+                    .access()
+                    .try_iter_component_access()
+                    .map(|access| {
+                        access
+                            .filter(|kind| !matches!(kind, ComponentAccessKind::Archetypal(_)))
+                            .map(|kind| *kind.index())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+            }
+        }
+        let (Some(state), Some(ticks)) = (&mut self.state, &mut self.ticks) else {
+            return false;
+        };
+        state.update_archetypes(world);
+        ticks.update_archetypes(world);
+
+        let this_run = world.read_change_tick();
+        let last_run = self.last_run.replace(this_run);
+        let changed = match last_run {
+            None => true,
+            Some(_) if self.remap => true,
+            Some(last_run) => changed_since(
+                ticks,
+                world,
+                &self.entities,
+                &self.components,
+                last_run,
+                this_run,
+            ),
+        };
+        if !changed {
+            return false;
+        }
+
+        self.entities = ticks.iter_manual(world).map(|(entity, _)| entity).collect();
+        self.remap = false;
+        let results: Vec<T> = state
+            .iter_manual(world)
+            .map(|(_, item)| (self.map)(item))
+            .collect();
+        let mut previous = self.results.lock().unwrap();
+        if *previous == results {
+            return false;
+        }
+        *previous = results;
+        true
+    }
+
+    fn is_new(&self) -> bool {
+        self.last_run.is_none()
+    }
+
+    fn subscriber(&self) -> &Subscriber {
+        &self.subscriber
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Whether the entities matching `ticks` differ from `entities`, or one of
+/// them has one of `components` changed between the two ticks.
+///
+/// Walks the matching archetypes once, comparing change ticks the way a
+/// `Changed` filter does.
+fn changed_since<F: QueryFilter>(
+    ticks: &QueryState<(Entity, EntityRef<'static>), F>,
+    world: &World,
+    entities: &[Entity],
+    components: &[ComponentId],
+    last_run: Tick,
+    this_run: Tick,
+) -> bool {
+    let mut previous = entities.iter();
+    for (entity, entity_ref) in ticks.iter_manual(world) {
+        if previous.next() != Some(&entity) {
+            return true;
+        }
+        let component_changed = components.iter().any(|&component| {
+            entity_ref
+                .get_change_ticks_by_id(component)
+                .is_some_and(|ticks| ticks.is_changed(last_run, this_run))
+        });
+        if component_changed {
+            return true;
+        }
+    }
+    previous.next().is_some()
+}
+
+/// Queries registered by the `use_query` calls of a `ComposeRuntime`
+#[derive(Default)]
+pub(crate) struct WatchedQueries {
+    readers: Mutex<HashMap<u64, Box<dyn QueryReader>>>,
+}
+
+impl WatchedQueries {
+    /// Check the queries selected by `filter` against `world` and invalidate
+    /// the scopes whose results changed
+    fn poll(&self, world: &World, filter: impl Fn(&dyn QueryReader) -> bool) {
+        let invalidated: Vec<Subscriber> = self
+            .readers
+            .lock()
+            .unwrap()
+            .values_mut()
+            .filter(|reader| filter(reader.as_ref()))
+            .filter_map(|reader| reader.poll(world).then(|| reader.subscriber().clone()))
+            .collect();
+        for subscriber in invalidated {
+            subscriber.notify();
+        }
+    }
+
+    fn remove(&self, id: u64) {
+        self.readers.lock().unwrap().remove(&id);
+    }
+}
+
+/// System that checks the queries read by composables against the world
+pub(crate) fn poll_watched_queries(world: &World) {
+    if let Some(runtime) = world.get_resource::<ComposeRuntime>() {
+        runtime.watched_queries().poll(world, |_| true);
+    }
+}
+
+/// System that checks the queries registered by the last recomposition, so
+/// their scopes are recomposed with the results in the same frame
+pub(crate) fn poll_new_watched_queries(world: &World) {
+    if let Some(runtime) = world.get_resource::<ComposeRuntime>() {
+        runtime
+            .watched_queries()
+            .poll(world, |reader| reader.is_new());
+    }
+}
+
+/// Read the results of an ECS query, mapped by `map`, and recompose the
+/// current scope when they change.
+///
+/// The query is checked every frame before recomposition. The scope is
+/// invalidated when entities start or stop matching `D` and `F`, or a
+/// component read by the query changes, and the mapped results differ from
+/// the ones read last. Results are mapped in the world's iteration order.
+///
+/// The first composition of a call reads no results: its query is checked
+/// right after the composition pass, and the scope is recomposed with the
+/// results in the same frame. Each recomposition replaces `map`, and the
+/// results are mapped again with it on the next check.
+///
+/// # Example
+/// ```ignore
+/// Column(Modifiers::new(), || {
+///     let party = use_query::<(&Name, &Health), With<PartyMember>, _>(|(name, health)| {
+///         format!("{name}: {}", health.0)
+///     });
+///     ForEach(&party, |line| Text(line.clone(), TextStyle::body()));
+/// });
+/// ```
+pub fn use_query<D, F, T>(
+    map: impl for<'w, 's> Fn(ROQueryItem<'w, 's, D>) -> T + Send + Sync + 'static,
+) -> Vec<T>
+where
+    D: QueryData + 'static,
+    F: QueryFilter + 'static,
+    T: Clone + PartialEq + Send + Sync + 'static,
+{
+    static NEXT_READER_ID: AtomicU64 = AtomicU64::new(0);
+
+    let (Some(runtime), Some(scope_id)) = (current_runtime(), current_scope_id()) else {
+        return Vec::new();
+    };
+    let Some(subscriber) = Subscriber::new(scope_id) else {
+        return Vec::new();
+    };
+
+    // Each call remembers its reader, so recompositions replace its map
+    let (id, results) = remember(|| {
+        let id = NEXT_READER_ID.fetch_add(1, Ordering::Relaxed);
+        let runtime = runtime.clone();
+        with_scope_node(scope_id, |node| {
+            node.add_disposal(Box::new(move || runtime.watched_queries().remove(id)));
+        });
+        (id, Arc::new(Mutex::new(Vec::<T>::new())))
+    });
+
+    let mut readers = runtime.watched_queries().readers.lock().unwrap();
+    let existing = readers
+        .get_mut(&id)
+        .and_then(|reader| reader.as_any_mut().downcast_mut::<MappedQuery<D, F, T>>());
+    match existing {
+        Some(reader) => {
+            reader.map = Box::new(map);
+            reader.remap = true;
+        }
+        None => {
+            readers.insert(
+                id,
+                Box::new(MappedQuery::<D, F, T> {
+                    subscriber,
+                    state: None,
+                    ticks: None,
+                    components: Vec::new(),
+                    map: Box::new(map),
+                    remap: false,
+                    entities: Vec::new(),
+                    last_run: None,
+                    results: results.clone(),
+                }),
+            );
+        }
+    }
+    drop(readers);
+
+    let results = results.lock().unwrap().clone();
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bevy_integration::{remember_state, Column, Scope, State, Text};
+    use crate::components::TextStyle;
+    use crate::modifier::Modifiers;
+    use crate::testing::*;
+
+    #[derive(Component)]
+    struct Health(u32);
+
+    #[derive(Component)]
+    struct Mana(u32);
+
+    /// Text listing the health of every entity, sorted
+    fn health_summary() {
+        let mut health = use_query::<&Health, (), _>(|health| health.0);
+        health.sort();
+        Text(format!("{health:?}"), TextStyle::body());
+    }
+
+    #[test]
+    fn calls_read_results_in_the_frame_they_first_compose() {
+        let shown = Captured::<State<bool>>::new();
+        let mut app = compose_app({
+            let shown = shown.clone();
+            move || {
+                let visible = remember_state(false);
+                shown.set(visible);
+                Column(Modifiers::new(), move || {
+                    if visible.get() {
+                        Scope(health_summary);
+                    }
+                });
+            }
+        });
+        app.world_mut().spawn(Health(3));
+        app.world_mut().spawn(Health(5));
+
+        shown.get().set(true);
+        app.update();
+
+        assert_eq!(texts(&mut app), ["[3, 5]"]);
+    }
+
+    #[test]
+    fn readers_recompose_when_matching_entities_or_their_components_change() {
+        let runs = Arc::new(AtomicU64::new(0));
+        let mut app = compose_app({
+            let runs = runs.clone();
+            move || {
+                let runs = runs.clone();
+                Scope(move || {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                    health_summary();
+                });
+            }
+        });
+        let hero = app.world_mut().spawn((Health(3), Mana(1))).id();
+        app.update();
+        assert_eq!(texts(&mut app), ["[3]"]);
+        let baseline = runs.load(Ordering::SeqCst);
+
+        app.world_mut().get_mut::<Mana>(hero).unwrap().0 = 2;
+        app.update();
+        app.world_mut().get_mut::<Health>(hero).unwrap().0 = 3;
+        app.update();
+        assert_eq!(runs.load(Ordering::SeqCst), baseline);
+
+        app.world_mut().get_mut::<Health>(hero).unwrap().0 = 4;
+        app.update();
+        assert_eq!(texts(&mut app), ["[4]"]);
+
+        app.world_mut().spawn(Health(1));
+        app.update();
+        assert_eq!(texts(&mut app), ["[1, 4]"]);
+
+        app.world_mut().despawn(hero);
+        app.update();
+        assert_eq!(texts(&mut app), ["[1]"]);
+        assert_eq!(runs.load(Ordering::SeqCst), baseline + 3);
+    }
+
+    #[test]
+    fn readers_are_removed_with_their_scope() {
+        let shown = Captured::<State<bool>>::new();
+        let mut app = compose_app({
+            let shown = shown.clone();
+            move || {
+                let visible = remember_state(true);
+                shown.set(visible);
+                Column(Modifiers::new(), move || {
+                    if visible.get() {
+                        Scope(health_summary);
+                    }
+                });
+            }
+        });
+        let readers = |app: &App| {
+            let runtime = app.world().resource::<ComposeRuntime>();
+            runtime.watched_queries().readers.lock().unwrap().len()
+        };
+        assert_eq!(readers(&app), 1);
+
+        shown.get().set(false);
+        app.update();
+
+        assert_eq!(readers(&app), 0);
+    }
+}
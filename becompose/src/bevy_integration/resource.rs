@@ -0,0 +1,403 @@
+//! Resource Hooks
+//!
+//! Read Bevy resources from composables. A watched resource is copied into
+//! the runtime whenever it changes, so composables can read it while
+//! composing, and the scopes that read it are invalidated.
+//!
+//! Resources are watched per type with `App::watch_resource`. `use_resource`
+//! returns a clone of the resource; `use_resource_map` returns a projection of
+//! it and only invalidates its scope when the projection changes. Their `_opt`
+//! variants return `None` while the resource doesn't exist, and invalidate
+//! their scope when it is inserted or removed.
+
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use bevy::ecs::component::Tick;
+use bevy::prelude::*;
+
+use super::app::initial_composition;
+use super::composables::{current_scope_id, remember, with_scope_node, ScopeId};
+use super::compose_root::mount_compose_roots;
+use super::runtime::{current_runtime, ComposeRuntime, Subscriber};
+use super::BecomposePlugin;
+
+/// Decides whether a change of the resource invalidates a reader. `None`
+/// means the resource was removed.
+type ReaderCheck<R> = Box<dyn FnMut(Option<&R>) -> bool + Send>;
+
+/// A scope reading a watched resource
+struct ResourceReader<R> {
+    /// Identifies the `use_resource` call the reader was registered by
+    id: u64,
+    subscriber: Subscriber,
+    changed: ReaderCheck<R>,
+}
+
+/// The last value of a watched resource and the scopes reading it
+struct Watched<R> {
+    value: Option<R>,
+    /// Change tick of `value`
+    changed_at: Option<Tick>,
+    readers: Vec<ResourceReader<R>>,
+}
+
+/// Resources watched by a `ComposeRuntime`, by type
+#[derive(Default)]
+pub(crate) struct WatchedResources {
+    resources: Mutex<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>,
+}
+
+impl WatchedResources {
+    /// Start watching `R`, returning `false` if it is watched already
+    fn watch<R: Resource + Clone>(&self) -> bool {
+        let mut resources = self.resources.lock().unwrap();
+        if resources.contains_key(&TypeId::of::<R>()) {
+            return false;
+        }
+        let watched = Mutex::new(Watched::<R> {
+            value: None,
+            changed_at: None,
+            readers: Vec::new(),
+        });
+        resources.insert(TypeId::of::<R>(), Arc::new(watched));
+        true
+    }
+
+    fn get<R: Resource + Clone>(&self) -> Option<Arc<Mutex<Watched<R>>>> {
+        let watched = self
+            .resources
+            .lock()
+            .unwrap()
+            .get(&TypeId::of::<R>())?
+            .clone();
+        watched.downcast().ok()
+    }
+
+    /// Store the value of `R` changed at `tick`, or its removal, and
+    /// invalidate the readers it changes
+    fn changed<R: Resource + Clone>(&self, resource: Option<&R>, tick: Option<Tick>) {
+        let Some(watched) = self.get::<R>() else {
+            return;
+        };
+        let invalidated: Vec<Subscriber> = {
+            let mut watched = watched.lock().unwrap();
+            // Already copied, e.g. on startup
+            if watched.changed_at == tick {
+                return;
+            }
+            watched.value = resource.cloned();
+            watched.changed_at = tick;
+            watched
+                .readers
+                .iter_mut()
+                .filter_map(|reader| (reader.changed)(resource).then(|| reader.subscriber.clone()))
+                .collect()
+        };
+        for subscriber in invalidated {
+            subscriber.notify();
+        }
+    }
+}
+
+/// Watch Bevy resources from composables
+pub trait WatchResourceAppExt {
+    /// Let composables read `R` with `use_resource` and `use_resource_map`.
+    ///
+    /// Adds `BecomposePlugin` if it is not added yet.
+    fn watch_resource<R: Resource + Clone>(&mut self) -> &mut Self;
+}
+
+impl WatchResourceAppExt for App {
+    fn watch_resource<R: Resource + Clone>(&mut self) -> &mut Self {
+        if !self.is_plugin_added::<BecomposePlugin>() {
+            self.add_plugins(BecomposePlugin);
+        }
+        let runtime = self.world().resource::<ComposeRuntime>();
+        if runtime.watched_resources().watch::<R>() {
+            self.add_systems(
+                Startup,
+                sync_watched_resource::<R>.before(initial_composition),
+            )
+            .add_systems(
+                Update,
+                sync_watched_resource::<R>.before(mount_compose_roots),
+            );
+        }
+        self
+    }
+}
+
+/// System that copies a watched resource into the runtime when it is
+/// inserted or changes, and clears the copy when it is removed
+fn sync_watched_resource<R: Resource + Clone>(
+    resource: Option<Res<R>>,
+    runtime: Res<ComposeRuntime>,
+) {
+    match resource {
+        Some(resource) if resource.is_changed() => runtime
+            .watched_resources()
+            .changed(Some(&*resource), Some(resource.last_changed())),
+        Some(_) => {}
+        None => runtime.watched_resources().changed::<R>(None, None),
+    }
+}
+
+/// Read a watched resource with `read`, which returns the value read and
+/// the check for whether a later change of the resource invalidates the
+/// current scope.
+///
+/// Returns `None` if `R` is not watched, after a debug assertion.
+fn read_resource<R, T>(read: impl FnOnce(Option<&R>) -> (T, ReaderCheck<R>)) -> Option<T>
+where
+    R: Resource + Clone,
+{
+    let watched = current_runtime().and_then(|runtime| runtime.watched_resources().get::<R>());
+    debug_assert!(
+        watched.is_some(),
+        "resource `{}` is not watched; add it with `App::watch_resource`",
+        type_name::<R>()
+    );
+    let watched = watched?;
+
+    let (value, changed) = read(watched.lock().unwrap().value.as_ref());
+    if let Some(scope_id) = current_scope_id() {
+        subscribe(&watched, scope_id, changed);
+    }
+    Some(value)
+}
+
+/// Register or replace the reader of the current `use_resource` call
+fn subscribe<R: Resource + Clone>(
+    watched: &Arc<Mutex<Watched<R>>>,
+    scope_id: ScopeId,
+    changed: ReaderCheck<R>,
+) {
+    static NEXT_READER_ID: AtomicU64 = AtomicU64::new(0);
+
+    let Some(subscriber) = Subscriber::new(scope_id) else {
+        return;
+    };
+    // Each call remembers its reader, so recompositions replace it
+    let id = remember(|| {
+        let id = NEXT_READER_ID.fetch_add(1, Ordering::Relaxed);
+        let watched = watched.clone();
+        with_scope_node(scope_id, |node| {
+            node.add_disposal(Box::new(move || {
+                watched
+                    .lock()
+                    .unwrap()
+                    .readers
+                    .retain(|reader| reader.id != id);
+            }));
+        });
+        id
+    });
+
+    let reader = ResourceReader {
+        id,
+        subscriber,
+        changed,
+    };
+    let mut watched = watched.lock().unwrap();
+    match watched.readers.iter_mut().find(|reader| reader.id == id) {
+        Some(existing) => *existing = reader,
+        None => watched.readers.push(reader),
+    }
+}
+
+/// Read a clone of resource `R` and recompose the current scope when it
+/// changes.
+///
+/// `R` must be watched with `App::watch_resource`.
+///
+/// # Panics
+/// If `R` doesn't exist. Use `use_resource_opt` for resources that may be
+/// inserted later.
+///
+/// # Example
+/// ```ignore
+/// app.watch_resource::<Settings>();
+///
+/// Column(Modifiers::new(), || {
+///     let settings = use_resource::<Settings>();
+///     Text(format!("Volume: {}", settings.volume), TextStyle::body());
+/// });
+/// ```
+pub fn use_resource<R: Resource + Clone>() -> R {
+    use_resource_opt().unwrap_or_else(|| missing::<R>())
+}
+
+/// Read a clone of resource `R`, or `None` if it doesn't exist, and recompose
+/// the current scope when it is inserted, changes or is removed.
+///
+/// `R` must be watched with `App::watch_resource`.
+pub fn use_resource_opt<R: Resource + Clone>() -> Option<R> {
+    read_resource(|resource: Option<&R>| {
+        let always: ReaderCheck<R> = Box::new(|_| true);
+        (resource.cloned(), always)
+    })
+    .flatten()
+}
+
+/// Read a projection of resource `R` and recompose the current scope only when
+/// the projected value changes.
+///
+/// `R` must be watched with `App::watch_resource`.
+///
+/// # Panics
+/// If `R` doesn't exist. Use `use_resource_map_opt` for resources that may be
+/// inserted later.
+///
+/// # Example
+/// ```ignore
+/// // Not recomposed when other fields of `Game` change
+/// let score = use_resource_map(|game: &Game| game.score);
+/// Text(format!("Score: {score}"), TextStyle::headline());
+/// ```
+pub fn use_resource_map<R, T, F>(project: F) -> T
+where
+    R: Resource + Clone,
+    T: Clone + PartialEq + Send + 'static,
+    F: Fn(&R) -> T + Send + 'static,
+{
+    use_resource_map_opt(project).unwrap_or_else(|| missing::<R>())
+}
+
+/// Read a projection of resource `R`, or `None` if it doesn't exist, and
+/// recompose the current scope only when the projected value changes or the
+/// resource is inserted or removed.
+///
+/// `R` must be watched with `App::watch_resource`.
+pub fn use_resource_map_opt<R, T, F>(project: F) -> Option<T>
+where
+    R: Resource + Clone,
+    T: Clone + PartialEq + Send + 'static,
+    F: Fn(&R) -> T + Send + 'static,
+{
+    read_resource(move |resource: Option<&R>| {
+        let value = resource.map(&project);
+        let mut last = value.clone();
+        let changed: ReaderCheck<R> = Box::new(move |resource| {
+            let projected = resource.map(&project);
+            let changed = projected != last;
+            last = projected;
+            changed
+        });
+        (value, changed)
+    })
+    .flatten()
+}
+
+fn missing<R>() -> ! {
+    panic!(
+        "resource `{}` does not exist; use the `_opt` variant to read it before it is inserted",
+        type_name::<R>()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bevy_integration::{Column, Scope, Text};
+    use crate::components::TextStyle;
+    use crate::modifier::Modifiers;
+    use crate::testing::*;
+
+    #[derive(Resource, Clone)]
+    struct Game {
+        score: u32,
+        ticks: u32,
+    }
+
+    /// App watching `Game`, composing `content` for one frame
+    fn game_app(content: impl Fn() + Send + Sync + 'static) -> App {
+        compose_app_with(
+            |app| {
+                app.watch_resource::<Game>();
+            },
+            content,
+        )
+    }
+
+    fn counted(runs: &Arc<AtomicU64>, content: impl Fn() + Send + Sync + 'static) {
+        let runs = runs.clone();
+        Scope(move || {
+            runs.fetch_add(1, Ordering::SeqCst);
+            content();
+        });
+    }
+
+    #[test]
+    fn optional_readers_follow_the_resource_being_inserted_and_removed() {
+        let mut app = game_app(|| {
+            Scope(|| {
+                let score = use_resource_opt::<Game>().map(|game| game.score);
+                Text(format!("{score:?}"), TextStyle::body());
+            });
+        });
+        assert_eq!(texts(&mut app), ["None"]);
+
+        app.insert_resource(Game { score: 1, ticks: 0 });
+        app.update();
+        assert_eq!(texts(&mut app), ["Some(1)"]);
+
+        app.world_mut().remove_resource::<Game>();
+        app.update();
+        assert_eq!(texts(&mut app), ["None"]);
+    }
+
+    #[test]
+    fn map_readers_only_recompose_when_their_projection_changes() {
+        let (reads, maps) = (Arc::new(AtomicU64::new(0)), Arc::new(AtomicU64::new(0)));
+        let mut app = game_app({
+            let (reads, maps) = (reads.clone(), maps.clone());
+            move || {
+                let (reads, maps) = (reads.clone(), maps.clone());
+                Column(Modifiers::new(), move || {
+                    counted(&reads, || {
+                        let ticks = use_resource_opt::<Game>().map(|game| game.ticks);
+                        Text(format!("{ticks:?} ticks"), TextStyle::body());
+                    });
+                    counted(&maps, || {
+                        let score = use_resource_map_opt(|game: &Game| game.score);
+                        Text(format!("{score:?} points"), TextStyle::body());
+                    });
+                });
+            }
+        });
+        app.insert_resource(Game { score: 0, ticks: 0 });
+        app.update();
+
+        app.world_mut().resource_mut::<Game>().ticks += 1;
+        app.update();
+        assert_eq!(texts(&mut app), ["Some(1) ticks", "Some(0) points"]);
+        assert_eq!(
+            (reads.load(Ordering::SeqCst), maps.load(Ordering::SeqCst)),
+            (3, 2)
+        );
+
+        app.world_mut().resource_mut::<Game>().score += 1;
+        app.update();
+        assert_eq!(texts(&mut app), ["Some(1) ticks", "Some(1) points"]);
+        assert_eq!(maps.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn resources_inserted_before_startup_are_read_by_the_first_composition() {
+        let mut app = compose_app_with(
+            |app| {
+                app.watch_resource::<Game>()
+                    .insert_resource(Game { score: 4, ticks: 0 });
+            },
+            || {
+                let score = use_resource_map(|game: &Game| game.score);
+                Text(format!("{score}"), TextStyle::body());
+            },
+        );
+
+        assert_eq!(texts(&mut app), ["4"]);
+    }
+}
@@ -0,0 +1,341 @@
+//! Composition Runtime
+//!
+//! Per-App runtime state for the composable runtime. Every `App` that adds
+//! `BecomposePlugin` gets its own `ComposeRuntime`, so several apps in one
+//! process (parallel tests, an embedded preview) never see each other's
+//! scopes or invalidations.
+
+use bevy::prelude::*;
+use generational_box::{AnyStorage, Owner, SyncStorage};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+
+use super::composables::ScopeId;
+use super::producer::ProducedStates;
+use super::query::WatchedQueries;
+use super::resource::WatchedResources;
+use super::saveable::{SaveableStateRegistry, SaveableStates};
+use crate::state::snapshot::WriteLock;
+
+/// State shared between a `ComposeRuntime` and the states bound to it
+struct RuntimeShared {
+    /// Scopes invalidated since the last recomposition
+    dirty: Mutex<HashSet<ScopeId>>,
+    /// Owner of states created outside any scope while this runtime is active
+    owner: Owner<SyncStorage>,
+    /// States created by `remember_saveable`
+    saveable: SaveableStates,
+    /// Resources composables read with `use_resource`
+    resources: WatchedResources,
+    /// Queries composables read with `use_query`
+    queries: WatchedQueries,
+    /// States fed by `produce_state` and `collect_as_state`
+    produced: ProducedStates,
+    /// Orders the writes to states created in this runtime
+    write_lock: Arc<WriteLock>,
+}
+
+/// The composition runtime of one App.
+///
+/// Collects the scopes invalidated by state changes and owns app-level states
+/// created while the App runs (for example inside click handlers). Added by
+/// `BecomposePlugin`.
+#[derive(Resource, Clone)]
+pub struct ComposeRuntime {
+    shared: Arc<RuntimeShared>,
+}
+
+impl Default for ComposeRuntime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ComposeRuntime {
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(RuntimeShared {
+                dirty: Mutex::new(HashSet::new()),
+                owner: SyncStorage::owner(),
+                saveable: SaveableStates::default(),
+                resources: WatchedResources::default(),
+                queries: WatchedQueries::default(),
+                produced: ProducedStates::default(),
+                write_lock: Arc::default(),
+            }),
+        }
+    }
+
+    /// Mark a scope of this runtime as needing recomposition
+    pub fn mark_dirty(&self, scope_id: ScopeId) {
+        self.shared.dirty.lock().unwrap().insert(scope_id);
+    }
+
+    /// Mark the root scope dirty for full recomposition
+    pub fn invalidate(&self) {
+        self.mark_dirty(ScopeId::root());
+    }
+
+    /// Check if any scopes are dirty
+    pub fn has_dirty_scopes(&self) -> bool {
+        !self.shared.dirty.lock().unwrap().is_empty()
+    }
+
+    /// Take all dirty scopes (clears the set)
+    pub fn take_dirty_scopes(&self) -> HashSet<ScopeId> {
+        std::mem::take(&mut *self.shared.dirty.lock().unwrap())
+    }
+
+    /// The saveable states of this runtime
+    pub fn saveable_states(&self) -> &SaveableStates {
+        &self.shared.saveable
+    }
+
+    /// Save and restore saveable states with `registry` instead of keeping
+    /// them in memory
+    pub fn set_saveable_registry(&self, registry: impl SaveableStateRegistry) {
+        self.shared.saveable.set_registry(registry);
+    }
+
+    pub(crate) fn watched_resources(&self) -> &WatchedResources {
+        &self.shared.resources
+    }
+
+    pub(crate) fn watched_queries(&self) -> &WatchedQueries {
+        &self.shared.queries
+    }
+
+    pub(crate) fn produced_states(&self) -> &ProducedStates {
+        &self.shared.produced
+    }
+
+    pub(crate) fn write_lock(&self) -> Arc<WriteLock> {
+        self.shared.write_lock.clone()
+    }
+
+    /// Weak handle for states bound to this runtime
+    pub(crate) fn handle(&self) -> RuntimeHandle {
+        RuntimeHandle(Arc::downgrade(&self.shared))
+    }
+}
+
+/// Weak reference to a runtime, held by states so they don't keep an App's
+/// runtime alive
+#[derive(Clone)]
+pub(crate) struct RuntimeHandle(Weak<RuntimeShared>);
+
+impl RuntimeHandle {
+    /// Subscriber for a scope of this runtime
+    pub(crate) fn subscriber(&self, scope_id: ScopeId) -> Subscriber {
+        Subscriber {
+            target: SubscriberTarget::Scope {
+                runtime: self.clone(),
+                scope_id,
+            },
+        }
+    }
+
+    /// Lock ordering the writes to the runtime's states, if the runtime
+    /// still exists
+    pub(crate) fn write_lock(&self) -> Option<Arc<WriteLock>> {
+        Some(self.0.upgrade()?.write_lock.clone())
+    }
+
+    /// Mark a scope dirty, if the runtime still exists
+    pub(crate) fn mark_dirty(&self, scope_id: ScopeId) {
+        if let Some(shared) = self.0.upgrade() {
+            shared.dirty.lock().unwrap().insert(scope_id);
+        }
+    }
+}
+
+/// Something that read a piece of state: a scope, together with the runtime
+/// that composed it, or a derived state
+#[derive(Clone)]
+pub struct Subscriber {
+    target: SubscriberTarget,
+}
+
+#[derive(Clone)]
+enum SubscriberTarget {
+    Scope {
+        runtime: RuntimeHandle,
+        scope_id: ScopeId,
+    },
+    Derived {
+        id: u64,
+        on_change: Arc<dyn Fn() + Send + Sync>,
+    },
+}
+
+impl Subscriber {
+    /// Subscriber for a scope of the runtime active on this thread
+    pub fn new(scope_id: ScopeId) -> Option<Self> {
+        Some(current_runtime()?.handle().subscriber(scope_id))
+    }
+
+    /// Subscriber calling `on_change` when a dependency of a derived state
+    /// changes
+    pub(crate) fn derived(on_change: Arc<dyn Fn() + Send + Sync>) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Self {
+            target: SubscriberTarget::Derived {
+                id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+                on_change,
+            },
+        }
+    }
+
+    /// The subscribed scope, if a scope subscribed
+    pub fn scope_id(&self) -> Option<ScopeId> {
+        match &self.target {
+            SubscriberTarget::Scope { scope_id, .. } => Some(*scope_id),
+            SubscriberTarget::Derived { .. } => None,
+        }
+    }
+
+    /// Mark the subscribed scope for recomposition, or let the subscribed
+    /// derived state know its dependency changed
+    pub fn notify(&self) {
+        match &self.target {
+            SubscriberTarget::Scope { runtime, scope_id } => runtime.mark_dirty(*scope_id),
+            SubscriberTarget::Derived { on_change, .. } => on_change(),
+        }
+    }
+}
+
+impl PartialEq for Subscriber {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.target, &other.target) {
+            (
+                SubscriberTarget::Scope { runtime, scope_id },
+                SubscriberTarget::Scope {
+                    runtime: other_runtime,
+                    scope_id: other_scope_id,
+                },
+            ) => scope_id == other_scope_id && Weak::ptr_eq(&runtime.0, &other_runtime.0),
+            (SubscriberTarget::Derived { id, .. }, SubscriberTarget::Derived { id: other, .. }) => {
+                id == other
+            }
+            _ => false,
+        }
+    }
+}
+
+thread_local! {
+    static CURRENT_RUNTIME: RefCell<Option<ComposeRuntime>> = const { RefCell::new(None) };
+}
+
+/// Make `runtime` the runtime used on this thread until `f` returns.
+///
+/// Called by the framework around composition and event dispatch, so states
+/// created and invalidated there are bound to the right App.
+pub fn with_runtime<R>(runtime: &ComposeRuntime, f: impl FnOnce() -> R) -> R {
+    /// Restores the previous runtime even if `f` panics
+    struct Restore(Option<ComposeRuntime>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            CURRENT_RUNTIME.with(|current| *current.borrow_mut() = previous);
+        }
+    }
+
+    let previous = CURRENT_RUNTIME.with(|current| current.replace(Some(runtime.clone())));
+    let _restore = Restore(previous);
+    f()
+}
+
+/// Get the runtime active on this thread
+pub fn current_runtime() -> Option<ComposeRuntime> {
+    CURRENT_RUNTIME.with(|current| current.borrow().clone())
+}
+
+/// Owner for states created outside any scope.
+///
+/// States created while a runtime is active live as long as that runtime.
+/// States created outside any App (e.g. in `main` before the app runs) are
+/// detached and live for the rest of the process.
+pub(crate) fn unscoped_state_owner() -> Owner<SyncStorage> {
+    static DETACHED_OWNER: OnceLock<Owner<SyncStorage>> = OnceLock::new();
+
+    match current_runtime() {
+        Some(runtime) => runtime.shared.owner.clone(),
+        None => DETACHED_OWNER.get_or_init(SyncStorage::owner).clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bevy_integration::{remember_state, Column, State, Text};
+    use crate::components::TextStyle;
+    use crate::modifier::Modifiers;
+    use crate::testing::*;
+
+    fn counter_app(counter: Captured<State<i32>>) -> App {
+        compose_app(move || {
+            let counter = counter.clone();
+            Column(Modifiers::new(), move || {
+                let state = remember_state(0);
+                counter.set(state);
+                Text(state.get().to_string(), TextStyle::body());
+            });
+        })
+    }
+
+    #[test]
+    fn apps_only_recompose_for_their_own_states() {
+        let first = Captured::new();
+        let second = Captured::new();
+        let mut first_app = counter_app(first.clone());
+        let mut second_app = counter_app(second.clone());
+
+        first.get().set(1);
+
+        assert!(first_app
+            .world()
+            .resource::<ComposeRuntime>()
+            .has_dirty_scopes());
+        assert!(!second_app
+            .world()
+            .resource::<ComposeRuntime>()
+            .has_dirty_scopes());
+        first_app.update();
+        second_app.update();
+        assert_eq!(texts(&mut first_app), ["1"]);
+        assert_eq!(texts(&mut second_app), ["0"]);
+    }
+
+    #[test]
+    fn unscoped_states_live_as_long_as_their_runtime() {
+        let runtime = ComposeRuntime::new();
+        let state = with_runtime(&runtime, || State::new(1));
+        assert_eq!(state.try_read_value(), Some(1));
+
+        drop(runtime);
+
+        assert_eq!(state.try_read_value(), None);
+    }
+
+    #[test]
+    fn with_runtime_restores_the_previous_runtime() {
+        let outer = ComposeRuntime::new();
+        let inner = ComposeRuntime::new();
+        with_runtime(&outer, || {
+            with_runtime(&inner, || {
+                assert!(Arc::ptr_eq(
+                    &current_runtime().unwrap().shared,
+                    &inner.shared
+                ));
+            });
+            assert!(Arc::ptr_eq(
+                &current_runtime().unwrap().shared,
+                &outer.shared
+            ));
+        });
+        assert!(current_runtime().is_none());
+    }
+}
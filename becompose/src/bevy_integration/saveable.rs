@@ -0,0 +1,548 @@
+//! Saveable State
+//!
+//! State that outlives the scope and the process that created it. A state
+//! created by `remember_saveable` is saved under its key when its scope leaves
+//! the composition and when the app exits, and is restored from the saved
+//! value the next time a scope remembers the same key.
+//!
+//! Values are serialized with serde into a `SaveableStateRegistry`. The
+//! default registry keeps them in memory, so they survive leaving and
+//! re-entering a screen; `FileSaveableRegistry` also writes them to disk, so
+//! they survive restarts.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Write as _};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::composables::{current_scope_id, remember, with_scope_node, State};
+use super::runtime::{current_runtime, ComposeRuntime};
+
+/// Text format saved values are encoded in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SaveFormat {
+    #[default]
+    Json,
+    Ron,
+}
+
+impl SaveFormat {
+    /// File extension for values in this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            SaveFormat::Json => "json",
+            SaveFormat::Ron => "ron",
+        }
+    }
+
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<String, SaveableStateError> {
+        match self {
+            SaveFormat::Json => serde_json::to_string_pretty(value)
+                .map_err(|error| SaveableStateError::Encode(error.to_string())),
+            SaveFormat::Ron => ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())
+                .map_err(|error| SaveableStateError::Encode(error.to_string())),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, text: &str) -> Result<T, SaveableStateError> {
+        match self {
+            SaveFormat::Json => serde_json::from_str(text)
+                .map_err(|error| SaveableStateError::Decode(error.to_string())),
+            SaveFormat::Ron => {
+                ron::from_str(text).map_err(|error| SaveableStateError::Decode(error.to_string()))
+            }
+        }
+    }
+}
+
+/// Error saving or restoring saveable state
+#[derive(Debug)]
+pub enum SaveableStateError {
+    /// A value could not be serialized
+    Encode(String),
+    /// A saved value could not be deserialized into the remembered type
+    Decode(String),
+    /// Saved values could not be read or written
+    Io(io::Error),
+}
+
+impl fmt::Display for SaveableStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveableStateError::Encode(error) => write!(f, "failed to encode state: {error}"),
+            SaveableStateError::Decode(error) => write!(f, "failed to decode state: {error}"),
+            SaveableStateError::Io(error) => write!(f, "failed to access saved state: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for SaveableStateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SaveableStateError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SaveableStateError {
+    fn from(error: io::Error) -> Self {
+        SaveableStateError::Io(error)
+    }
+}
+
+/// Storage for the values of saveable states, encoded in the registry's
+/// `format`
+pub trait SaveableStateRegistry: Send + Sync + 'static {
+    /// Format the registry stores values in
+    fn format(&self) -> SaveFormat {
+        SaveFormat::Json
+    }
+
+    /// The value saved under `key`, if any
+    fn restore(&mut self, key: &str) -> Option<String>;
+
+    /// Save a value under `key`, replacing the previous one
+    fn save(&mut self, key: &str, value: String);
+
+    /// Write the saved values to persistent storage, if the registry has any
+    fn flush(&mut self) -> Result<(), SaveableStateError> {
+        Ok(())
+    }
+}
+
+/// Registry keeping saved values in memory.
+///
+/// Clones share their values, so a test can hand a clone to one app, exit it,
+/// and hand another clone to a new app to check what it restores.
+#[derive(Clone, Default)]
+pub struct InMemorySaveableRegistry {
+    values: Arc<Mutex<HashMap<String, String>>>,
+    format: SaveFormat,
+}
+
+impl InMemorySaveableRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store values in the given format
+    pub fn with_format(mut self, format: SaveFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// The encoded value saved under `key`
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.values.lock().unwrap().get(key).cloned()
+    }
+
+    /// Number of saved values
+    pub fn len(&self) -> usize {
+        self.values.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl SaveableStateRegistry for InMemorySaveableRegistry {
+    fn format(&self) -> SaveFormat {
+        self.format
+    }
+
+    fn restore(&mut self, key: &str) -> Option<String> {
+        self.get(key)
+    }
+
+    fn save(&mut self, key: &str, value: String) {
+        self.values.lock().unwrap().insert(key.to_string(), value);
+    }
+}
+
+/// Registry storing each saved value in its own file of a directory.
+///
+/// Files are read the first time their key is restored and written when the
+/// registry is flushed, which happens when the app exits.
+///
+/// # Example
+/// ```ignore
+/// BecomposeApp::new()
+///     .saveable_registry(FileSaveableRegistry::new("ui_state").with_format(SaveFormat::Ron))
+///     .content(app_content)
+///     .run();
+/// ```
+pub struct FileSaveableRegistry {
+    directory: PathBuf,
+    format: SaveFormat,
+    /// Values read or saved so far, by key
+    values: HashMap<String, String>,
+    /// Keys saved since the last flush
+    changed: HashSet<String>,
+}
+
+impl FileSaveableRegistry {
+    /// Store values in `directory`, which is created when values are first
+    /// written
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            format: SaveFormat::Json,
+            values: HashMap::new(),
+            changed: HashSet::new(),
+        }
+    }
+
+    /// Store values in the given format
+    pub fn with_format(mut self, format: SaveFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Path of the file holding the value of `key`.
+    ///
+    /// Bytes of the key other than ASCII letters, digits, `-` and `_` are
+    /// escaped, so every key maps to a distinct file inside the directory.
+    pub fn path_for(&self, key: &str) -> PathBuf {
+        let mut name = String::with_capacity(key.len());
+        for byte in key.bytes() {
+            if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+                name.push(byte as char);
+            } else {
+                let _ = write!(name, "%{byte:02X}");
+            }
+        }
+        self.directory
+            .join(format!("{name}.{}", self.format.extension()))
+    }
+}
+
+impl SaveableStateRegistry for FileSaveableRegistry {
+    fn format(&self) -> SaveFormat {
+        self.format
+    }
+
+    fn restore(&mut self, key: &str) -> Option<String> {
+        if let Some(value) = self.values.get(key) {
+            return Some(value.clone());
+        }
+        let value = std::fs::read_to_string(self.path_for(key)).ok()?;
+        self.values.insert(key.to_string(), value.clone());
+        Some(value)
+    }
+
+    fn save(&mut self, key: &str, value: String) {
+        self.values.insert(key.to_string(), value);
+        self.changed.insert(key.to_string());
+    }
+
+    fn flush(&mut self) -> Result<(), SaveableStateError> {
+        if self.changed.is_empty() {
+            return Ok(());
+        }
+        std::fs::create_dir_all(&self.directory)?;
+        for key in std::mem::take(&mut self.changed) {
+            if let Some(value) = self.values.get(&key) {
+                std::fs::write(self.path_for(&key), value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Encodes the current value of a live saveable state, or `None` if the state
+/// was dropped
+type EncodeLive =
+    Box<dyn Fn(SaveFormat) -> Option<Result<String, SaveableStateError>> + Send + Sync>;
+
+/// A saveable state currently remembered by a scope
+struct LiveState {
+    /// Distinguishes this state from a later one remembered under the same key
+    id: u64,
+    encode: EncodeLive,
+}
+
+struct SaveableShared {
+    registry: Mutex<Box<dyn SaveableStateRegistry>>,
+    /// Saveable states in the composition, by key
+    live: Mutex<HashMap<String, LiveState>>,
+}
+
+/// The saveable states of a `ComposeRuntime` and the registry they are saved
+/// to
+#[derive(Clone)]
+pub struct SaveableStates {
+    shared: Arc<SaveableShared>,
+}
+
+impl Default for SaveableStates {
+    fn default() -> Self {
+        Self {
+            shared: Arc::new(SaveableShared {
+                registry: Mutex::new(Box::new(InMemorySaveableRegistry::default())),
+                live: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+}
+
+impl SaveableStates {
+    /// Save to and restore from `registry` from now on
+    pub fn set_registry(&self, registry: impl SaveableStateRegistry) {
+        self.set_boxed_registry(Box::new(registry));
+    }
+
+    pub(crate) fn set_boxed_registry(&self, registry: Box<dyn SaveableStateRegistry>) {
+        *self.shared.registry.lock().unwrap() = registry;
+    }
+
+    /// Save the value of every saveable state in the composition and flush
+    /// the registry
+    pub fn save_all(&self) -> Result<(), SaveableStateError> {
+        let mut registry = self.shared.registry.lock().unwrap();
+        let format = registry.format();
+        for (key, live) in self.shared.live.lock().unwrap().iter() {
+            save_live(registry.as_mut(), key, live, format);
+        }
+        registry.flush()
+    }
+
+    /// Decode the value saved under `key`
+    fn restore<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let mut registry = self.shared.registry.lock().unwrap();
+        let format = registry.format();
+        let text = registry.restore(key)?;
+        match format.decode(&text) {
+            Ok(value) => Some(value),
+            Err(error) => {
+                warn!("saveable state `{key}` not restored: {error}");
+                None
+            }
+        }
+    }
+
+    /// Start tracking a saveable state, returning its id
+    fn track<T>(&self, key: &str, state: State<T>) -> u64
+    where
+        T: Clone + Send + Sync + Serialize + 'static,
+    {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let encode: EncodeLive =
+            Box::new(move |format| state.try_read_value().map(|value| format.encode(&value)));
+        let previous = self
+            .shared
+            .live
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), LiveState { id, encode });
+        if previous.is_some() {
+            warn!("saveable state `{key}` is remembered more than once");
+        }
+        id
+    }
+
+    /// Save a saveable state whose scope left the composition and stop
+    /// tracking it
+    fn release(&self, key: &str, id: u64) {
+        let live = {
+            let mut live = self.shared.live.lock().unwrap();
+            match live.get(key) {
+                Some(state) if state.id == id => live.remove(key),
+                _ => None,
+            }
+        };
+        if let Some(live) = live {
+            let mut registry = self.shared.registry.lock().unwrap();
+            let format = registry.format();
+            save_live(registry.as_mut(), key, &live, format);
+        }
+    }
+}
+
+fn save_live(
+    registry: &mut dyn SaveableStateRegistry,
+    key: &str,
+    live: &LiveState,
+    format: SaveFormat,
+) {
+    match (live.encode)(format) {
+        Some(Ok(value)) => registry.save(key, value),
+        Some(Err(error)) => warn!("saveable state `{key}` not saved: {error}"),
+        None => {}
+    }
+}
+
+/// Remember a `State` that is saved under `key` and restored from it.
+///
+/// The first time a scope remembers the key, the state starts from the value
+/// saved under it, or from `init` if nothing was saved or the saved value no
+/// longer deserializes into `T`. The value is saved when the scope leaves the
+/// composition, so re-entering a screen restores it, and when the app exits,
+/// so a `FileSaveableRegistry` restores it on the next launch.
+///
+/// Keys are global to the app: two scopes remembering the same key at once
+/// overwrite each other's saved value.
+///
+/// # Example
+/// ```ignore
+/// let tab = remember_saveable("settings.tab", || 0usize);
+/// Button("Next tab", Modifiers::new(), move || tab.update(|tab| *tab += 1));
+/// ```
+pub fn remember_saveable<T, F>(key: &str, init: F) -> State<T>
+where
+    T: Clone + Send + Sync + Serialize + DeserializeOwned + 'static,
+    F: FnOnce() -> T,
+{
+    remember(|| {
+        let Some(runtime) = current_runtime() else {
+            return State::new(init());
+        };
+        let saveable = runtime.saveable_states().clone();
+        let state = State::new(saveable.restore(key).unwrap_or_else(init));
+        let id = saveable.track(key, state);
+
+        if let Some(scope_id) = current_scope_id() {
+            let key = key.to_string();
+            with_scope_node(scope_id, |node| {
+                node.add_disposal(Box::new(move || saveable.release(&key, id)));
+            });
+        }
+        state
+    })
+}
+
+/// System that saves every saveable state when the app exits
+pub(crate) fn save_saveable_states(
+    mut exits: MessageReader<AppExit>,
+    runtime: Res<ComposeRuntime>,
+) {
+    if exits.read().last().is_none() {
+        return;
+    }
+    if let Err(error) = runtime.saveable_states().save_all() {
+        warn!("saveable states not saved: {error}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bevy_integration::{remember_state, Column, Scope, Text};
+    use crate::components::TextStyle;
+    use crate::modifier::Modifiers;
+    use crate::testing::*;
+
+    /// App saving to `registry`, composing `content` for one frame
+    fn saveable_app(
+        registry: &InMemorySaveableRegistry,
+        content: impl Fn() + Send + Sync + 'static,
+    ) -> App {
+        compose_app_with(
+            |app| {
+                app.world()
+                    .resource::<ComposeRuntime>()
+                    .set_saveable_registry(registry.clone());
+            },
+            content,
+        )
+    }
+
+    fn counter(captured: Captured<State<i32>>) -> impl Fn() + Send + Sync + 'static {
+        move || {
+            let value = remember_saveable("counter", || 0);
+            captured.set(value);
+            Text(format!("{}", value.get()), TextStyle::body());
+        }
+    }
+
+    #[test]
+    fn states_are_restored_when_their_scope_returns() {
+        let registry = InMemorySaveableRegistry::new();
+        let (shown, captured) = (Captured::new(), Captured::new());
+        let mut app = saveable_app(&registry, {
+            let shown = shown.clone();
+            let captured = captured.clone();
+            move || {
+                let visible = remember_state(true);
+                shown.set(visible);
+                let captured = captured.clone();
+                Column(Modifiers::new(), move || {
+                    if visible.get() {
+                        Scope(counter(captured.clone()));
+                    }
+                });
+            }
+        });
+
+        captured.get().set(5);
+        shown.get().set(false);
+        app.update();
+        assert_eq!(registry.get("counter").as_deref(), Some("5"));
+        assert!(texts(&mut app).is_empty());
+
+        shown.get().set(true);
+        app.update();
+        assert_eq!(texts(&mut app), ["5"]);
+    }
+
+    #[test]
+    fn states_are_saved_on_exit_and_restored_by_the_next_app() {
+        let registry = InMemorySaveableRegistry::new().with_format(SaveFormat::Ron);
+        let captured = Captured::new();
+        let mut app = saveable_app(&registry, counter(captured.clone()));
+        captured.get().set(7);
+        app.update();
+        assert!(registry.is_empty());
+
+        app.world_mut().write_message(AppExit::Success);
+        app.update();
+        assert_eq!(registry.get("counter").as_deref(), Some("7"));
+
+        let mut next = saveable_app(&registry, counter(Captured::new()));
+        assert_eq!(texts(&mut next), ["7"]);
+    }
+
+    #[test]
+    fn values_that_no_longer_decode_start_from_init() {
+        let mut registry = InMemorySaveableRegistry::new();
+        registry.save("counter", "\"seven\"".to_string());
+
+        let mut app = saveable_app(&registry, counter(Captured::new()));
+
+        assert_eq!(texts(&mut app), ["0"]);
+    }
+
+    #[test]
+    fn file_registries_write_one_escaped_file_per_key() {
+        let directory = std::env::temp_dir().join(format!(
+            "becompose-saveable-{}-{:?}",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        let mut registry = FileSaveableRegistry::new(&directory).with_format(SaveFormat::Ron);
+        let path = registry.path_for("settings/tab 1");
+        assert_eq!(path, directory.join("settings%2Ftab%201.ron"));
+
+        registry.save("settings/tab 1", "3".to_string());
+        registry.flush().unwrap();
+        let mut reopened = FileSaveableRegistry::new(&directory).with_format(SaveFormat::Ron);
+        let restored = reopened.restore("settings/tab 1");
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(restored.as_deref(), Some("3"));
+        assert_eq!(reopened.restore("missing"), None);
+    }
+}
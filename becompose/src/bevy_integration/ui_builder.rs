@@ -5,6 +5,7 @@
 use bevy::prelude::*;
 use std::sync::Arc;
 
+use super::composables::{emit_child, with_commands};
 use crate::components::*;
 use crate::layout::*;
 use crate::modifier::Modifiers;
@@ -80,6 +81,17 @@ impl UiElement {
             UiElement::Spacer(e) => e.build(commands),
         }
     }
+
+    /// Build this element as a child of the current composable.
+    ///
+    /// The element is tracked by the composition tree like any other emitted
+    /// entity and is despawned once the composable stops emitting it. Builder
+    /// elements are rebuilt on every pass rather than patched in place.
+    pub fn compose(self) -> Entity {
+        let entity = with_commands(|commands| self.build(commands));
+        emit_child(entity);
+        entity
+    }
 }
 
 /// Text element builder
@@ -137,9 +149,9 @@ pub struct ButtonElement {
 }
 
 impl ButtonElement {
-    pub fn new<F: Fn() + Send + Sync + 'static>(on_click: F) -> Self {
+    pub fn new<M>(on_click: impl IntoOnClick<M>) -> Self {
         Self {
-            on_click: Arc::new(on_click),
+            on_click: on_click.into_on_click(),
             modifier: Modifiers::default(),
             enabled: true,
             children: Vec::new(),
@@ -474,7 +486,7 @@ pub fn text_styled(content: impl Into<String>, style: TextStyle) -> UiElement {
 }
 
 /// Create a button element
-pub fn button<F: Fn() + Send + Sync + 'static>(on_click: F, content: UiElement) -> UiElement {
+pub fn button<M>(on_click: impl IntoOnClick<M>, content: UiElement) -> UiElement {
     UiElement::Button(ButtonElement::new(on_click).with_child(content))
 }
 
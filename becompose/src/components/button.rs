@@ -2,6 +2,7 @@
 //!
 //! Clickable button composable.
 
+use crate::bevy_integration::try_with_commands;
 use crate::modifier::Modifiers;
 use bevy::prelude::*;
 use std::sync::Arc;
@@ -9,6 +10,125 @@ use std::sync::Arc;
 /// Click handler type
 pub type OnClick = Arc<dyn Fn() + Send + Sync>;
 
+/// Closures that can handle a click.
+///
+/// Besides `Fn()`, a handler can take `&mut Commands` to queue changes to the
+/// world, or `&mut World` to run as a command with full access to it. These
+/// are applied in the frame the click is dispatched. The argument type of
+/// such a closure has to be written out, since it picks the variant.
+///
+/// # Example
+/// ```ignore
+/// Button("Spawn", Modifiers::new(), |commands: &mut Commands| {
+///     commands.spawn(Enemy::default());
+/// });
+/// Button("Play", Modifiers::new(), |world: &mut World| {
+///     world.resource_mut::<NextState<GameState>>().set(GameState::Playing);
+/// });
+/// ```
+pub trait IntoOnClick<Marker> {
+    fn into_on_click(self) -> OnClick;
+}
+
+impl<F> IntoOnClick<fn()> for F
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    fn into_on_click(self) -> OnClick {
+        Arc::new(self)
+    }
+}
+
+impl<F> IntoOnClick<fn(&mut Commands)> for F
+where
+    F: Fn(&mut Commands) + Send + Sync + 'static,
+{
+    fn into_on_click(self) -> OnClick {
+        Arc::new(move || {
+            if try_with_commands(|commands| self(commands)).is_none() {
+                warn!("click handler taking `Commands` called outside of event dispatch");
+            }
+        })
+    }
+}
+
+impl<F> IntoOnClick<fn(&mut World)> for F
+where
+    F: Fn(&mut World) + Send + Sync + 'static,
+{
+    fn into_on_click(self) -> OnClick {
+        let handler = Arc::new(self);
+        Arc::new(move || {
+            let handler = handler.clone();
+            let queued = try_with_commands(|commands| {
+                commands.queue(move |world: &mut World| handler(world));
+            });
+            if queued.is_none() {
+                warn!("click handler taking `World` called outside of event dispatch");
+            }
+        })
+    }
+}
+
+/// Value change handler type
+pub type OnChange<T> = Arc<dyn Fn(T) + Send + Sync>;
+
+/// Closures that can handle a new value, such as the value of a slider.
+///
+/// Like `IntoOnClick`, a handler can also take `&mut Commands` or
+/// `&mut World` after the value, written out in the closure's arguments.
+///
+/// # Example
+/// ```ignore
+/// Slider(0.5, 0.0, 1.0, |volume: f32, world: &mut World| {
+///     world.resource_mut::<GlobalVolume>().volume = Volume::Linear(volume);
+/// });
+/// ```
+pub trait IntoOnChange<T, Marker> {
+    fn into_on_change(self) -> OnChange<T>;
+}
+
+impl<T, F> IntoOnChange<T, fn(T)> for F
+where
+    F: Fn(T) + Send + Sync + 'static,
+{
+    fn into_on_change(self) -> OnChange<T> {
+        Arc::new(self)
+    }
+}
+
+impl<T, F> IntoOnChange<T, fn(T, &mut Commands)> for F
+where
+    F: Fn(T, &mut Commands) + Send + Sync + 'static,
+{
+    fn into_on_change(self) -> OnChange<T> {
+        Arc::new(move |value| {
+            if try_with_commands(|commands| self(value, commands)).is_none() {
+                warn!("change handler taking `Commands` called outside of event dispatch");
+            }
+        })
+    }
+}
+
+impl<T, F> IntoOnChange<T, fn(T, &mut World)> for F
+where
+    T: Send + 'static,
+    F: Fn(T, &mut World) + Send + Sync + 'static,
+{
+    fn into_on_change(self) -> OnChange<T> {
+        let handler = Arc::new(self);
+        Arc::new(move |value| {
+            let handler = handler.clone();
+            let queued = try_with_commands(|commands| {
+                commands.queue(move |world: &mut World| handler(value, world));
+            });
+            if queued.is_none() {
+                warn!("change handler taking `World` called outside of event dispatch");
+            }
+        })
+    }
+}
+
 /// Configuration for a Button
 #[derive(Clone)]
 pub struct ButtonConfig {
@@ -18,9 +138,9 @@ pub struct ButtonConfig {
 }
 
 impl ButtonConfig {
-    pub fn new<F: Fn() + Send + Sync + 'static>(on_click: F) -> Self {
+    pub fn new<M>(on_click: impl IntoOnClick<M>) -> Self {
         Self {
-            on_click: Arc::new(on_click),
+            on_click: on_click.into_on_click(),
             modifier: Modifiers::default(),
             enabled: true,
         }
@@ -68,9 +188,9 @@ pub struct Clickable {
 }
 
 impl Clickable {
-    pub fn new<F: Fn() + Send + Sync + 'static>(on_click: F) -> Self {
+    pub fn new<M>(on_click: impl IntoOnClick<M>) -> Self {
         Self {
-            on_click: Arc::new(on_click),
+            on_click: on_click.into_on_click(),
         }
     }
 }
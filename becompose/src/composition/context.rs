@@ -1,12 +1,22 @@
 //! Composition Context
 //!
 //! Provides the runtime context for composable functions.
+//!
+//! Groups started through the context are nodes of the live `CompositionTree`,
+//! so `#[composable]` functions get the same positional identity, remembered
+//! slots and invalidation as the built-in composables. Functions returning
+//! nothing start skippable groups, which are skipped when called again with
+//! equal parameters.
 
 use std::cell::RefCell;
 use std::rc::Rc;
 
-use crate::composition::{ComposableType, CompositionId, CompositionKey, CompositionNode};
-use crate::state::StateSlotManager;
+use crate::bevy_integration::{
+    current_scope_id, end_group, end_skippable_group, start_group, start_skippable_group,
+};
+use crate::composition::{ComposableType, CompositionId, CompositionKey, SkipParams};
+use crate::state::snapshot::{begin_mutable_snapshot, end_mutable_snapshot};
+use crate::state::{in_mutable_snapshot, SnapshotConflict, StateSlotManager};
 
 thread_local! {
     static CURRENT_CONTEXT: RefCell<Option<CompositionContext>> = const { RefCell::new(None) };
@@ -19,27 +29,15 @@ pub struct CompositionContext {
 }
 
 struct CompositionContextInner {
-    /// Stack of current composition nodes
-    node_stack: Vec<CompositionId>,
-    /// State manager for the current composition
-    state_manager: StateSlotManager,
-    /// Pending nodes to be added to the tree
-    pending_nodes: Vec<CompositionNode>,
-    /// Whether we're currently in batch mode
-    batch_mode: bool,
-    /// Whether composition is active
-    active: bool,
+    /// Groups started through this context that have not ended yet
+    group_stack: Vec<CompositionId>,
 }
 
 impl CompositionContext {
     pub fn new() -> Self {
         Self {
             inner: Rc::new(RefCell::new(CompositionContextInner {
-                node_stack: Vec::new(),
-                state_manager: StateSlotManager::new(),
-                pending_nodes: Vec::new(),
-                batch_mode: false,
-                active: false,
+                group_stack: Vec::new(),
             })),
         }
     }
@@ -47,12 +45,10 @@ impl CompositionContext {
     /// Get the current composition context
     pub fn current() -> Self {
         CURRENT_CONTEXT.with(|ctx| {
-            ctx.borrow().clone().unwrap_or_else(|| {
-                // Create a default context if none exists
-                let new_ctx = CompositionContext::new();
-                *ctx.borrow_mut() = Some(new_ctx.clone());
-                new_ctx
-            })
+            // Create a default context if none exists
+            ctx.borrow_mut()
+                .get_or_insert_with(CompositionContext::new)
+                .clone()
         })
     }
 
@@ -70,73 +66,83 @@ impl CompositionContext {
         });
     }
 
-    /// Start a new composition group
+    /// Start a new composition group as a child of the current scope,
+    /// identified by the call site of the caller
+    #[track_caller]
     pub fn start_group(&self, type_id: &str, key: Option<CompositionKey>) -> CompositionId {
-        let mut inner = self.inner.borrow_mut();
-        inner.active = true;
-
-        let mut node = CompositionNode::new(ComposableType::Custom(type_id.to_string()));
-        if let Some(k) = key {
-            node.key = Some(k);
-        }
-
-        let id = node.id;
-        inner.pending_nodes.push(node);
-        inner.node_stack.push(id);
-
+        let id = start_group(ComposableType::Custom(type_id.to_string()), key);
+        self.inner.borrow_mut().group_stack.push(id);
         id
     }
 
     /// End the current composition group
-    pub fn end_group(&self, _id: CompositionId) {
-        let mut inner = self.inner.borrow_mut();
-        inner.node_stack.pop();
+    pub fn end_group(&self, id: CompositionId) {
+        self.inner.borrow_mut().group_stack.pop();
+        end_group(id);
+    }
 
-        if inner.node_stack.is_empty() {
-            inner.active = false;
-        }
+    /// Start a composition group that is skipped if it ran before with equal
+    /// `params`, returning `None` if it was skipped
+    #[track_caller]
+    pub fn start_skippable_group(
+        &self,
+        type_id: &str,
+        key: Option<CompositionKey>,
+        params: SkipParams,
+    ) -> Option<CompositionId> {
+        let id = start_skippable_group(ComposableType::Custom(type_id.to_string()), key, params)?;
+        self.inner.borrow_mut().group_stack.push(id);
+        Some(id)
+    }
+
+    /// End a group started by `start_skippable_group`
+    pub fn end_skippable_group(&self, id: CompositionId) {
+        self.inner.borrow_mut().group_stack.pop();
+        end_skippable_group(id);
     }
 
     /// Get the current parent node ID
     pub fn current_parent(&self) -> Option<CompositionId> {
-        self.inner.borrow().node_stack.last().copied()
+        current_scope_id()
     }
 
-    /// Access the state manager
+    /// Access the state manager for the current node
     pub fn state_manager(&self) -> StateSlotManager {
-        self.inner.borrow().state_manager.clone()
+        match current_scope_id() {
+            Some(id) => StateSlotManager::for_node(id),
+            None => StateSlotManager::new(),
+        }
     }
 
-    /// Start batch mode for multiple state updates
+    /// Start batching state updates in a mutable snapshot, like
+    /// `with_mutable_snapshot`
     pub fn begin_batch(&self) {
-        self.inner.borrow_mut().batch_mode = true;
+        begin_mutable_snapshot();
     }
 
-    /// End batch mode and trigger recomposition
-    pub fn end_batch(&self) {
-        self.inner.borrow_mut().batch_mode = false;
+    /// Apply the state updates made since `begin_batch` and trigger
+    /// recomposition of the scopes reading them
+    pub fn end_batch(&self) -> Result<(), SnapshotConflict> {
+        end_mutable_snapshot()
     }
 
-    /// Check if currently in batch mode
+    /// Check if state updates are currently batched
     pub fn is_batching(&self) -> bool {
-        self.inner.borrow().batch_mode
+        in_mutable_snapshot()
     }
 
     /// Check if composition is active
     pub fn is_active(&self) -> bool {
-        self.inner.borrow().active
-    }
-
-    /// Take pending nodes for processing
-    pub fn take_pending_nodes(&self) -> Vec<CompositionNode> {
-        std::mem::take(&mut self.inner.borrow_mut().pending_nodes)
+        current_scope_id().is_some()
     }
 
     /// Skip to end of current group (for optimization)
     pub fn skip_to_end_group(&self) {
         // Used when skipping recomposition of unchanged subtrees
-        let mut inner = self.inner.borrow_mut();
-        inner.node_stack.pop();
+        let id = self.inner.borrow_mut().group_stack.pop();
+        if let Some(id) = id {
+            end_group(id);
+        }
     }
 }
 
@@ -145,3 +151,60 @@ impl Default for CompositionContext {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::App;
+
+    use crate::bevy_integration::{current_scope_id, Column};
+    use crate::composable;
+    use crate::composition::{CompositionId, CompositionTree};
+    use crate::modifier::Modifiers;
+    use crate::testing::*;
+
+    #[composable]
+    fn group_id() -> Option<CompositionId> {
+        current_scope_id()
+    }
+
+    /// Ids of the groups of `group_id` calls from a loop and from two other
+    /// call sites
+    fn group_ids(ids: Captured<Vec<CompositionId>>) -> App {
+        compose_app(move || {
+            let ids = ids.clone();
+            Column(Modifiers::new(), move || {
+                let mut composed: Vec<CompositionId> = (0..3).filter_map(|_| group_id()).collect();
+                composed.extend(group_id());
+                composed.extend(group_id());
+                ids.set(composed);
+            });
+        })
+    }
+
+    #[test]
+    fn composables_keep_their_groups_across_recompositions() {
+        let ids = Captured::new();
+        let mut app = group_ids(ids.clone());
+        let first = ids.get();
+
+        recompose_root(&mut app);
+
+        assert_eq!(ids.get(), first);
+    }
+
+    #[test]
+    fn call_sites_and_loop_indices_get_their_own_groups() {
+        let ids = Captured::new();
+        let app = group_ids(ids.clone());
+        let ids = ids.get();
+
+        let tree = app.world().resource::<CompositionTree>();
+        let mut keys: Vec<_> = ids
+            .iter()
+            .map(|id| tree.get(*id).unwrap().group_key)
+            .collect();
+        keys.sort_by_key(|key| key.0);
+        keys.dedup();
+        assert_eq!(keys.len(), 5);
+    }
+}
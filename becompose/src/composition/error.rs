@@ -0,0 +1,82 @@
+//! Composition Errors
+//!
+//! Errors raised while composing, as caught by `ErrorBoundary`.
+
+use std::any::Any;
+use std::fmt;
+
+/// Errors that can occur during composition
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompositionError {
+    /// State accessed outside of composition
+    StateAccessOutsideComposition,
+    /// Composable called outside of composition context
+    NoCompositionContext,
+    /// Too many recompositions (infinite loop detection)
+    RecompositionLimit { limit: u32 },
+    /// Layout constraint violation
+    LayoutConstraintViolation { constraint: String },
+    /// Content panicked while composing
+    Panic { message: String },
+    /// Content returned an error
+    Failed { message: String },
+}
+
+impl CompositionError {
+    /// Error for a panic payload caught while composing
+    pub fn from_panic(payload: Box<dyn Any + Send>) -> Self {
+        let message = if let Some(message) = payload.downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "content panicked".to_string()
+        };
+        Self::Panic { message }
+    }
+}
+
+impl fmt::Display for CompositionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StateAccessOutsideComposition => {
+                write!(f, "state accessed outside of composition")
+            }
+            Self::NoCompositionContext => {
+                write!(f, "composable called outside of composition context")
+            }
+            Self::RecompositionLimit { limit } => {
+                write!(f, "recomposition limit of {limit} exceeded")
+            }
+            Self::LayoutConstraintViolation { constraint } => {
+                write!(f, "layout constraint violated: {constraint}")
+            }
+            Self::Panic { message } => write!(f, "composition panicked: {message}"),
+            Self::Failed { message } => write!(f, "composition failed: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for CompositionError {}
+
+/// Result of composing a piece of content.
+///
+/// Implemented for `()` and for `Result<(), E>`, so content closures may
+/// either return nothing or use `?` to bail out with an error.
+pub trait IntoCompositionResult {
+    fn into_composition_result(self) -> Result<(), CompositionError>;
+}
+
+impl IntoCompositionResult for () {
+    fn into_composition_result(self) -> Result<(), CompositionError> {
+        Ok(())
+    }
+}
+
+impl<E: fmt::Display> IntoCompositionResult for Result<(), E> {
+    fn into_composition_result(self) -> Result<(), CompositionError> {
+        self.map_err(|error| CompositionError::Failed {
+            message: error.to_string(),
+        })
+    }
+}
@@ -1,14 +1,20 @@
 //! Composition runtime module
 //!
 //! This module contains the core composition tree management,
-//! context handling, and recomposition logic.
+//! context handling, and recomposition logic and scheduling.
 
 mod context;
+mod error;
 mod recomposition;
 mod reconciler;
+mod scheduler;
+mod skip;
 mod tree;
 
 pub use context::*;
+pub use error::*;
 pub use recomposition::*;
 pub use reconciler::*;
+pub use scheduler::*;
+pub use skip::*;
 pub use tree::*;
@@ -61,6 +61,7 @@ pub fn process_recompositions(tree: &mut CompositionTree, dirty: &mut DirtyFlags
         if let Some(node) = tree.get_mut(id) {
             node.mark_clean();
         }
+        tree.pending_recomposition.remove(&id);
     }
 
     dirty.needs_recomposition.clear();
@@ -2,7 +2,241 @@
 //!
 //! Handles diffing and updating the composition tree.
 
-use crate::composition::{CompositionId, CompositionKey, CompositionNode, CompositionTree};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use bevy::prelude::Entity;
+
+use crate::composition::{
+    CallSite, ComposableType, CompositionId, CompositionKey, CompositionNode, CompositionTree,
+    GroupKey,
+};
+
+/// Children of a node from its previous pass, matched against the children
+/// requested while the node composes again
+#[derive(Debug, Default)]
+pub(crate) struct ChildPass {
+    /// All children from the previous pass
+    previous: Vec<CompositionId>,
+    /// Unkeyed children from the previous pass by call site, in order,
+    /// matched by their index among children from the same call site
+    positional: HashMap<Option<CallSite>, VecDeque<CompositionId>>,
+    /// Unkeyed children requested so far during this pass, by call site
+    occurrences: HashMap<Option<CallSite>, usize>,
+    /// Keyed children from the previous pass, matched by key
+    keyed: HashMap<CompositionKey, CompositionId>,
+    /// Keys requested during this pass
+    visited: HashSet<CompositionKey>,
+    /// Movable content emitted during this pass
+    movables: HashSet<u64>,
+}
+
+impl CompositionTree {
+    /// Begin composing the children of `id` again.
+    ///
+    /// The node's slot cursor is rewound and its children are set aside to be
+    /// matched by `child_for` until `end_pass` is called. The subscriptions of
+    /// the previous pass are queued for disposal, since the node subscribes
+    /// again to whatever it reads this time. The node is no longer dirty once
+    /// it composes again.
+    pub fn begin_pass(&mut self, id: CompositionId) {
+        let Some(node) = self.get_mut(id) else { return };
+        node.mark_clean();
+        let previous = std::mem::take(&mut node.children);
+        let subscriptions = std::mem::take(&mut node.subscriptions);
+        node.slot_cursor = 0;
+        node.entities.clear();
+        self.pending_disposals.extend(subscriptions);
+
+        let mut pass = ChildPass::default();
+        for &child in &previous {
+            let Some(node) = self.get(child) else {
+                continue;
+            };
+            match node.key.clone() {
+                Some(key) => {
+                    pass.keyed.insert(key, child);
+                }
+                None => pass
+                    .positional
+                    .entry(node.call_site)
+                    .or_default()
+                    .push_back(child),
+            }
+        }
+        pass.previous = previous;
+
+        if let Some(node) = self.get_mut(id) {
+            node.pass = Some(pass);
+        }
+    }
+
+    /// Get the child of `parent` for the next composable it emits.
+    ///
+    /// Keyed children are matched by key wherever they were. Unkeyed children
+    /// are matched by call site and by their index among the children emitted
+    /// from that call site, so a composable keeps its node when siblings from
+    /// other call sites come and go; children without a call site are matched
+    /// by position among themselves. A previous child is only reused if it was
+    /// emitted by the same type of composable, otherwise a new node is created
+    /// in its place. Returns `None` if `key` was already requested during this
+    /// pass.
+    pub fn child_for(
+        &mut self,
+        parent: CompositionId,
+        composable_type: ComposableType,
+        key: Option<CompositionKey>,
+        call_site: Option<CallSite>,
+    ) -> Option<CompositionId> {
+        let parent_key = self
+            .get(parent)
+            .map_or(GroupKey::ROOT, |node| node.group_key);
+        let (candidate, index) = match self.get_mut(parent) {
+            Some(node) => {
+                let pass = node.pass.get_or_insert_with(ChildPass::default);
+                match &key {
+                    Some(key) => {
+                        if !pass.visited.insert(key.clone()) {
+                            return None;
+                        }
+                        (pass.keyed.remove(key), 0)
+                    }
+                    None => {
+                        let occurrence = pass.occurrences.entry(call_site).or_default();
+                        let index = *occurrence;
+                        *occurrence += 1;
+                        let candidate = pass
+                            .positional
+                            .get_mut(&call_site)
+                            .and_then(|children| children.pop_front());
+                        (candidate, index)
+                    }
+                }
+            }
+            None => (None, 0),
+        };
+
+        let reused = candidate.filter(|id| {
+            self.get(*id)
+                .is_some_and(|node| node.composable_type == composable_type)
+        });
+        let id = match reused {
+            Some(id) => id,
+            None => {
+                let mut node = CompositionNode::new(composable_type);
+                node.group_key = parent_key.child(call_site, key.as_ref(), index);
+                node.key = key;
+                node.call_site = call_site;
+                self.insert(node)
+            }
+        };
+        self.add_child(parent, id);
+        Some(id)
+    }
+
+    /// Finish composing the children of `id`, removing previous children that
+    /// were not requested again
+    pub fn end_pass(&mut self, id: CompositionId) {
+        let Some(node) = self.get_mut(id) else { return };
+        let Some(pass) = node.pass.take() else { return };
+        let current: HashSet<CompositionId> = node.children.iter().copied().collect();
+        for child in pass.previous {
+            if !current.contains(&child) {
+                remove_subtree(self, child);
+            }
+        }
+    }
+
+    /// Record that movable content `movable` is emitted by `parent`,
+    /// returning `false` if the pass that emitted it last is still running and
+    /// already emitted it.
+    ///
+    /// A parent that composes again in the same frame starts a new pass, so it
+    /// can emit the content again.
+    pub fn mark_movable_emitted(&mut self, movable: u64, parent: CompositionId) -> bool {
+        let emitting = self
+            .emitted_movables
+            .get(&movable)
+            .and_then(|previous| self.get(*previous))
+            .and_then(|node| node.pass.as_ref())
+            .is_some_and(|pass| pass.movables.contains(&movable));
+        if emitting {
+            return false;
+        }
+        self.emitted_movables.insert(movable, parent);
+        if let Some(pass) = self.get_mut(parent).and_then(|node| node.pass.as_mut()) {
+            pass.movables.insert(movable);
+        }
+        true
+    }
+
+    /// Move the node composing movable content `movable` under `parent`,
+    /// keeping its subtree as it is.
+    ///
+    /// The node is detached from its previous parent, including that parent's
+    /// current pass, so it is not removed when the previous parent finishes.
+    /// Returns `None` if the content has not been composed yet.
+    pub fn claim_movable(&mut self, movable: u64, parent: CompositionId) -> Option<CompositionId> {
+        let id = *self.movables.get(&movable)?;
+        self.parked_movables.remove(&id);
+
+        let previous_parent = self.get(id).and_then(|node| node.parent);
+        if let Some(node) = previous_parent.and_then(|parent| self.get_mut(parent)) {
+            node.children.retain(|&child| child != id);
+            if let Some(pass) = &mut node.pass {
+                pass.previous.retain(|&child| child != id);
+                for children in pass.positional.values_mut() {
+                    children.retain(|&child| child != id);
+                }
+                pass.keyed.retain(|_, child| *child != id);
+            }
+        }
+
+        // The previous parent entity no longer lists the container once it
+        // has finished composing
+        if let Some(entity) = self.get_entity(id) {
+            for children in self.emitted_children.values_mut() {
+                children.retain(|&child| child != entity);
+            }
+        }
+
+        self.add_child(parent, id);
+        Some(id)
+    }
+
+    /// Detach movable content from its parent until it is emitted again
+    fn park_movable(&mut self, id: CompositionId) {
+        let parent = self.get_mut(id).and_then(|node| node.parent.take());
+        if let Some(parent) = parent.and_then(|parent| self.get_mut(parent)) {
+            parent.children.retain(|&child| child != id);
+        }
+        self.parked_movables.insert(id);
+    }
+
+    /// Check if movable content was emitted or dropped this frame
+    pub fn has_frame_movables(&self) -> bool {
+        !self.emitted_movables.is_empty()
+            || !self.attached_movables.is_empty()
+            || !self.parked_movables.is_empty()
+    }
+
+    /// Finish a frame of movable content, returning the movable content that
+    /// was dropped and not emitted again.
+    ///
+    /// The returned nodes and their subtrees are removed from the tree; their
+    /// container entities are left for the caller to despawn.
+    pub fn release_parked_movables(&mut self) -> Vec<Entity> {
+        self.emitted_movables.clear();
+        self.attached_movables.clear();
+
+        let parked: Vec<CompositionId> = self.parked_movables.drain().collect();
+        let mut entities = Vec::new();
+        for id in parked {
+            entities.extend(self.get_entity(id));
+            discard_subtree(self, id);
+        }
+        entities
+    }
+}
 
 /// Reconciles old children with new children, handling keys for efficient updates
 pub fn reconcile_children(
@@ -72,7 +306,15 @@ pub fn reconcile_children(
 }
 
 /// Recursively remove a subtree from the composition tree
+///
+/// Movable content in the subtree is parked instead of removed, so it can be
+/// emitted elsewhere in the same frame.
 pub fn remove_subtree(tree: &mut CompositionTree, id: CompositionId) {
+    if tree.get(id).is_some_and(|node| node.movable.is_some()) {
+        tree.park_movable(id);
+        return;
+    }
+
     // First, collect all descendant IDs
     let children: Vec<CompositionId> = tree.get(id).map(|n| n.children.clone()).unwrap_or_default();
 
@@ -84,3 +326,12 @@ pub fn remove_subtree(tree: &mut CompositionTree, id: CompositionId) {
     // Remove the node itself
     tree.remove(id);
 }
+
+/// Recursively remove a subtree, including any movable content in it
+fn discard_subtree(tree: &mut CompositionTree, id: CompositionId) {
+    let children: Vec<CompositionId> = tree.get(id).map(|n| n.children.clone()).unwrap_or_default();
+    for child_id in children {
+        discard_subtree(tree, child_id);
+    }
+    tree.remove(id);
+}
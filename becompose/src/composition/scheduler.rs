@@ -0,0 +1,215 @@
+//! Recomposition Scheduler
+//!
+//! Decides which invalidated scopes are rebuilt in a frame and in what order.
+//! Scopes are rebuilt outermost first; a scope whose ancestor is rebuilt in
+//! the same frame is skipped, since the ancestor's rebuild recomposes it too.
+//! With a frame budget, scopes left when the budget runs out wait for the
+//! next frame.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use bevy::platform::time::Instant;
+use bevy::prelude::*;
+
+use crate::composition::{CompositionId, CompositionTree};
+
+/// Scheduler for incremental recomposition
+#[derive(Resource, Debug, Clone, Default)]
+pub struct RecomposeScheduler {
+    /// Time a frame may spend rebuilding scopes, or `None` for no limit
+    pub frame_budget: Option<Duration>,
+    /// Scopes waiting to be rebuilt
+    pending: HashSet<CompositionId>,
+}
+
+/// The scopes to rebuild this frame
+#[derive(Debug, Clone, Default)]
+pub struct RecomposeBatch {
+    /// Scopes to rebuild, outermost first
+    pub scopes: Vec<CompositionId>,
+    /// Scopes that are rebuilt as part of an ancestor in `scopes`
+    pub coalesced: Vec<CompositionId>,
+}
+
+impl RecomposeScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scheduler that defers scopes once a frame has spent `budget` rebuilding
+    pub fn with_frame_budget(budget: Duration) -> Self {
+        Self {
+            frame_budget: Some(budget),
+            ..default()
+        }
+    }
+
+    /// Queue a scope for rebuilding
+    pub fn schedule(&mut self, id: CompositionId) {
+        self.pending.insert(id);
+    }
+
+    /// Check if any scopes are waiting to be rebuilt
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Number of scopes waiting to be rebuilt
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Plan the rebuilds for this frame.
+    ///
+    /// Scopes no longer in the tree are dropped, and scopes with a pending
+    /// ancestor are coalesced into it. The groups between a coalesced scope
+    /// and its ancestor are marked dirty, so the ancestor's rebuild doesn't
+    /// skip them and reaches the scope. The remaining scopes stay pending
+    /// until they are passed to `complete`.
+    pub fn plan(&mut self, tree: &mut CompositionTree) -> RecomposeBatch {
+        self.pending.retain(|id| tree.contains(*id));
+
+        let mut batch = RecomposeBatch::default();
+        for &id in &self.pending {
+            let pending_ancestor =
+                ancestors(tree, id).position(|ancestor| self.pending.contains(&ancestor));
+            match pending_ancestor {
+                Some(depth) => {
+                    let groups: Vec<_> = ancestors(tree, id).take(depth).collect();
+                    for group in groups {
+                        if let Some(node) = tree.get_mut(group) {
+                            node.mark_dirty();
+                        }
+                    }
+                    batch.coalesced.push(id);
+                }
+                None => batch.scopes.push(id),
+            }
+        }
+        for id in &batch.coalesced {
+            self.pending.remove(id);
+        }
+
+        batch
+            .scopes
+            .sort_by_key(|id| (ancestors(tree, *id).count(), id.0));
+        batch
+    }
+
+    /// Mark a scope as rebuilt
+    pub fn complete(&mut self, id: CompositionId) {
+        self.pending.remove(&id);
+    }
+
+    /// Start timing a frame against the frame budget
+    pub fn start_frame(&self) -> FrameDeadline {
+        FrameDeadline {
+            deadline: self.frame_budget.map(|budget| Instant::now() + budget),
+        }
+    }
+}
+
+/// The point at which a frame stops rebuilding scopes
+#[derive(Debug, Clone, Copy)]
+pub struct FrameDeadline {
+    deadline: Option<Instant>,
+}
+
+impl FrameDeadline {
+    /// Check if the frame budget has been spent
+    pub fn is_exceeded(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+/// Parents of a node, nearest first
+fn ancestors(
+    tree: &CompositionTree,
+    id: CompositionId,
+) -> impl Iterator<Item = CompositionId> + '_ {
+    std::iter::successors(tree.get(id).and_then(|node| node.parent), |&parent| {
+        tree.get(parent).and_then(|node| node.parent)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::composition::{ComposableType, CompositionNode};
+
+    fn scope(tree: &mut CompositionTree, parent: Option<CompositionId>) -> CompositionId {
+        let mut node = CompositionNode::new(ComposableType::Custom("Scope".to_string()));
+        node.parent = parent;
+        tree.insert(node)
+    }
+
+    #[test]
+    fn pending_descendants_are_coalesced_into_their_ancestor() {
+        let mut tree = CompositionTree::new();
+        let root = scope(&mut tree, None);
+        let child = scope(&mut tree, Some(root));
+        let grandchild = scope(&mut tree, Some(child));
+        let mut scheduler = RecomposeScheduler::new();
+        scheduler.schedule(grandchild);
+        scheduler.schedule(child);
+
+        let batch = scheduler.plan(&mut tree);
+
+        assert_eq!(batch.scopes, [child]);
+        assert_eq!(batch.coalesced, [grandchild]);
+        scheduler.complete(child);
+        assert!(!scheduler.has_pending());
+    }
+
+    #[test]
+    fn scopes_are_planned_outermost_first() {
+        let mut tree = CompositionTree::new();
+        let root = scope(&mut tree, None);
+        let left = scope(&mut tree, Some(root));
+        let right = scope(&mut tree, Some(root));
+        let nested = scope(&mut tree, Some(left));
+        let mut scheduler = RecomposeScheduler::new();
+        scheduler.schedule(nested);
+        scheduler.schedule(right);
+
+        let batch = scheduler.plan(&mut tree);
+
+        assert_eq!(batch.scopes, [right, nested]);
+        assert!(batch.coalesced.is_empty());
+    }
+
+    #[test]
+    fn removed_scopes_are_dropped() {
+        let mut tree = CompositionTree::new();
+        let root = scope(&mut tree, None);
+        let child = scope(&mut tree, Some(root));
+        let mut scheduler = RecomposeScheduler::new();
+        scheduler.schedule(child);
+        tree.remove(child);
+
+        let batch = scheduler.plan(&mut tree);
+
+        assert!(batch.scopes.is_empty());
+        assert!(!scheduler.has_pending());
+    }
+
+    #[test]
+    fn unplanned_scopes_stay_pending_until_completed() {
+        let mut tree = CompositionTree::new();
+        let root = scope(&mut tree, None);
+        let left = scope(&mut tree, Some(root));
+        let right = scope(&mut tree, Some(root));
+        let mut scheduler = RecomposeScheduler::with_frame_budget(Duration::ZERO);
+        scheduler.schedule(left);
+        scheduler.schedule(right);
+
+        let batch = scheduler.plan(&mut tree);
+        assert!(scheduler.start_frame().is_exceeded());
+        scheduler.complete(batch.scopes[0]);
+
+        assert_eq!(scheduler.pending_count(), 1);
+        assert_eq!(scheduler.plan(&mut tree).scopes, [batch.scopes[1]]);
+    }
+}
@@ -0,0 +1,218 @@
+//! Skippable Groups
+//!
+//! `#[composable]` functions record the parameters they were called with in
+//! their group. When the parent recomposes and calls the function again with
+//! equal parameters, the group is skipped and the entities it emitted last
+//! time are kept as they are.
+//!
+//! A parameter can be compared if its type implements `PartialEq` and
+//! `ToOwned` (which every `Clone` type does), or if it implements `Stable`.
+//! Which of these applies is picked at compile time by `ParamProbe`; a
+//! function with a parameter that fits neither always runs.
+
+use std::any::Any;
+use std::borrow::Borrow;
+use std::marker::PhantomData;
+
+use bevy::prelude::Entity;
+
+/// Marker for types whose values never need to be compared to skip a group.
+///
+/// A `Stable` value always compares equal to the value it replaces, either
+/// because it cannot change (e.g. a handle that is `Copy` and immutable) or
+/// because changes to it invalidate its readers on their own, like `State`.
+pub trait Stable {}
+
+/// A parameter recorded by a skippable group
+pub trait SkipParam: Send + Sync {
+    /// Whether this parameter is equal to the one recorded by the previous
+    /// run of the group
+    fn same_as(&self, previous: &dyn SkipParam) -> bool;
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// Owned copy of a `PartialEq` parameter
+struct Compared<T: ?Sized + ToOwned>(T::Owned, PhantomData<fn(&T)>);
+
+impl<T> SkipParam for Compared<T>
+where
+    T: ?Sized + ToOwned + PartialEq + 'static,
+    T::Owned: Send + Sync + 'static,
+{
+    fn same_as(&self, previous: &dyn SkipParam) -> bool {
+        previous
+            .as_any()
+            .downcast_ref::<Self>()
+            .is_some_and(|previous| previous.0.borrow() == self.0.borrow())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Stand-in for a `Stable` parameter
+struct StableParam;
+
+impl SkipParam for StableParam {
+    fn same_as(&self, previous: &dyn SkipParam) -> bool {
+        previous.as_any().is::<StableParam>()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Borrowed parameter of a `#[composable]` function, probed for how it can be
+/// compared.
+///
+/// Called as `(&&&ParamProbe::new(&value)).skip_param()`: method resolution
+/// picks the first of `ProbeCompared`, `ProbeStable` and `ProbeUnstable` the
+/// type implements.
+pub struct ParamProbe<'a, T: ?Sized>(&'a T);
+
+impl<'a, T: ?Sized> ParamProbe<'a, T> {
+    pub fn new(value: &'a T) -> Self {
+        Self(value)
+    }
+}
+
+/// Parameters compared with `PartialEq`
+pub trait ProbeCompared {
+    fn skip_param(&self) -> Option<Box<dyn SkipParam>>;
+}
+
+impl<T> ProbeCompared for &&ParamProbe<'_, T>
+where
+    T: ?Sized + ToOwned + PartialEq + 'static,
+    T::Owned: Send + Sync + 'static,
+{
+    fn skip_param(&self) -> Option<Box<dyn SkipParam>> {
+        Some(Box::new(Compared::<T>(self.0.to_owned(), PhantomData)))
+    }
+}
+
+/// Parameters that are `Stable`
+pub trait ProbeStable {
+    fn skip_param(&self) -> Option<Box<dyn SkipParam>>;
+}
+
+impl<T: ?Sized + Stable> ProbeStable for &ParamProbe<'_, T> {
+    fn skip_param(&self) -> Option<Box<dyn SkipParam>> {
+        Some(Box::new(StableParam))
+    }
+}
+
+/// Parameters that cannot be compared
+pub trait ProbeUnstable {
+    fn skip_param(&self) -> Option<Box<dyn SkipParam>>;
+}
+
+impl<T: ?Sized> ProbeUnstable for ParamProbe<'_, T> {
+    fn skip_param(&self) -> Option<Box<dyn SkipParam>> {
+        None
+    }
+}
+
+/// Parameters of a skippable group, or `None` if any of them cannot be
+/// compared
+pub type SkipParams = Option<Vec<Box<dyn SkipParam>>>;
+
+/// What a skippable group recorded the last time it ran
+pub(crate) struct SkipRecord {
+    pub(crate) params: Vec<Box<dyn SkipParam>>,
+    /// Entities the group emitted into its parent, in order
+    pub(crate) entities: Vec<Entity>,
+}
+
+impl SkipRecord {
+    /// Whether the group can be skipped when called with `params`
+    pub(crate) fn matches(&self, params: &[Box<dyn SkipParam>]) -> bool {
+        self.params.len() == params.len()
+            && self
+                .params
+                .iter()
+                .zip(params)
+                .all(|(previous, param)| param.same_as(previous.as_ref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bevy_integration::{remember_state, Column, State, Text};
+    use crate::components::TextStyle;
+    use crate::composable;
+    use crate::modifier::Modifiers;
+    use crate::testing::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn parameters_are_compared_by_value() {
+        let one = (&&&ParamProbe::new("one")).skip_param().unwrap();
+        let same = (&&&ParamProbe::new("one")).skip_param().unwrap();
+        let other = (&&&ParamProbe::new("two")).skip_param().unwrap();
+
+        assert!(same.same_as(one.as_ref()));
+        assert!(!other.same_as(one.as_ref()));
+    }
+
+    // Probed like `#[composable]` does: the references pick the probe
+    #[allow(clippy::needless_borrow)]
+    #[test]
+    fn stable_parameters_always_match_and_others_cannot_be_compared() {
+        struct Handle;
+        impl Stable for Handle {}
+        struct Opaque;
+
+        let first = (&&&ParamProbe::new(&Handle)).skip_param().unwrap();
+        let second = (&&&ParamProbe::new(&Handle)).skip_param().unwrap();
+
+        assert!(second.same_as(first.as_ref()));
+        assert!((&&&ParamProbe::new(&Opaque)).skip_param().is_none());
+    }
+
+    static LABEL_RUNS: AtomicUsize = AtomicUsize::new(0);
+    static CALLBACK_RUNS: AtomicUsize = AtomicUsize::new(0);
+
+    #[composable]
+    fn counted_label(text: String) {
+        LABEL_RUNS.fetch_add(1, Ordering::SeqCst);
+        Text(text, TextStyle::body());
+    }
+
+    #[composable]
+    fn with_callback(on_click: impl Fn()) {
+        CALLBACK_RUNS.fetch_add(1, Ordering::SeqCst);
+        on_click();
+    }
+
+    #[test]
+    fn composables_called_with_equal_parameters_are_skipped() {
+        let label = Captured::new();
+        let mut app = compose_app({
+            let label = label.clone();
+            move || {
+                let label = label.clone();
+                Column(Modifiers::new(), move || {
+                    let text: State<String> = remember_state("first".to_string());
+                    label.set(text);
+                    counted_label(text.get());
+                    counted_label("fixed".to_string());
+                    with_callback(|| {});
+                });
+            }
+        });
+        assert_eq!(LABEL_RUNS.load(Ordering::SeqCst), 2);
+        assert_eq!(CALLBACK_RUNS.load(Ordering::SeqCst), 1);
+
+        label.get().set("second".to_string());
+        app.update();
+
+        assert_eq!(texts(&mut app), ["second", "fixed"]);
+        assert_eq!(LABEL_RUNS.load(Ordering::SeqCst), 3);
+        assert_eq!(CALLBACK_RUNS.load(Ordering::SeqCst), 2);
+    }
+}
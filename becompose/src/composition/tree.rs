@@ -1,11 +1,20 @@
 //! Composition Tree
 //!
 //! The composition tree represents the hierarchical structure of UI elements.
+//!
+//! It is the single source of truth for the running UI: every composable that
+//! is emitted gets a node holding its remembered slots, its children (matched by
+//! key or position) and the entities it materialized.
 
 use bevy::prelude::*;
+use generational_box::{AnyStorage, Owner, SyncStorage};
+use std::any::TypeId;
 use std::collections::{HashMap, HashSet};
+use std::panic::Location;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
+use crate::composition::{ChildPass, CompositionError, SkipRecord};
 use crate::modifier::Modifiers;
 use crate::state::StateSlot;
 
@@ -18,6 +27,11 @@ impl CompositionId {
         static COUNTER: AtomicU64 = AtomicU64::new(1);
         Self(COUNTER.fetch_add(1, Ordering::SeqCst))
     }
+
+    /// Root node ID (always 0)
+    pub fn root() -> Self {
+        Self(0)
+    }
 }
 
 impl Default for CompositionId {
@@ -36,6 +50,86 @@ impl<T: ToString> From<T> for CompositionKey {
     }
 }
 
+/// Source location a composable was called from
+///
+/// Built-in composables and `#[composable]` functions are `#[track_caller]`,
+/// so this is where the composable appears in user code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CallSite {
+    pub file: &'static str,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl CallSite {
+    /// Call site of the caller, followed through `#[track_caller]` functions
+    #[track_caller]
+    pub fn caller() -> Self {
+        Location::caller().into()
+    }
+}
+
+impl From<&'static Location<'static>> for CallSite {
+    fn from(location: &'static Location<'static>) -> Self {
+        Self {
+            file: location.file(),
+            line: location.line(),
+            column: location.column(),
+        }
+    }
+}
+
+impl std::fmt::Display for CallSite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// Identity of a group that is stable across recompositions and runs
+///
+/// Derived from the parent's group key, the call site, and the item key or
+/// the index among the parent's children from the same call site.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct GroupKey(pub u64);
+
+impl GroupKey {
+    /// Group key of a root scope
+    pub const ROOT: Self = Self(0xcbf2_9ce4_8422_2325);
+
+    /// Group key of a child group emitted from `call_site`
+    pub fn child(
+        self,
+        call_site: Option<CallSite>,
+        key: Option<&CompositionKey>,
+        index: usize,
+    ) -> Self {
+        // FNV-1a, so keys don't depend on the standard library's hasher
+        let mut hash = self.0;
+        let mut write = |bytes: &[u8]| {
+            for byte in bytes {
+                hash ^= u64::from(*byte);
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+        if let Some(site) = call_site {
+            write(site.file.as_bytes());
+            write(&site.line.to_le_bytes());
+            write(&site.column.to_le_bytes());
+        }
+        match key {
+            Some(key) => {
+                write(&[1]);
+                write(key.0.as_bytes());
+            }
+            None => {
+                write(&[0]);
+                write(&(index as u64).to_le_bytes());
+            }
+        }
+        Self(hash)
+    }
+}
+
 /// Types of composable nodes
 #[derive(Debug, Clone, PartialEq)]
 pub enum ComposableType {
@@ -63,8 +157,18 @@ pub enum LeafType {
     Spacer,
 }
 
+/// Stored content function for a node that can recompose on its own
+pub type ScopedContentFn = Arc<dyn Fn() + Send + Sync>;
+
+/// Cleanup to run once a removed node is no longer needed, such as
+/// unsubscribing it from a state it read
+pub type Disposal = Box<dyn FnOnce() + Send + Sync>;
+
+/// Receives errors raised while a descendant of an error boundary recomposes
+/// on its own
+pub type ErrorHandler = Arc<dyn Fn(CompositionError) + Send + Sync>;
+
 /// Represents a node in the composition tree
-#[derive(Debug)]
 pub struct CompositionNode {
     /// Unique identifier for this node
     pub id: CompositionId,
@@ -72,6 +176,10 @@ pub struct CompositionNode {
     pub composable_type: ComposableType,
     /// Key for list reconciliation
     pub key: Option<CompositionKey>,
+    /// Where the composable was called from, if known
+    pub call_site: Option<CallSite>,
+    /// Identity of this node that is stable across recompositions
+    pub group_key: GroupKey,
     /// Parent node reference
     pub parent: Option<CompositionId>,
     /// Child nodes
@@ -84,6 +192,51 @@ pub struct CompositionNode {
     pub modifiers: Modifiers,
     /// Whether this node needs recomposition
     pub dirty: bool,
+    /// Content to run when this node recomposes on its own. Only restartable
+    /// nodes (containers and explicit scopes) have one.
+    pub content: Option<ScopedContentFn>,
+    /// Entities emitted while composing this node, in emission order
+    pub entities: Vec<Entity>,
+    /// Owner of the `State` values created while composing this node
+    pub(crate) state_owner: Option<Owner<SyncStorage>>,
+    /// Unsubscribes this node from each state it has read
+    pub(crate) subscriptions: Vec<Disposal>,
+    /// Cleanup of what hooks hold for the lifetime of this node, kept across
+    /// recompositions
+    pub(crate) disposals: Vec<Disposal>,
+    /// Set on error boundaries to catch errors from their descendants
+    pub(crate) error_handler: Option<ErrorHandler>,
+    /// Composition local provided to this node's subtree, by local ID
+    pub(crate) provided_local: Option<(u64, StateSlot)>,
+    /// Movable content ID, if this node composes movable content
+    pub(crate) movable: Option<u64>,
+    /// Parameters and entities of the last run, if this node is a skippable
+    /// group
+    pub(crate) skip: Option<SkipRecord>,
+    /// Index of the next state slot during the current pass
+    pub(crate) slot_cursor: usize,
+    /// Children from the previous pass while this node is being composed
+    pub(crate) pass: Option<ChildPass>,
+}
+
+impl std::fmt::Debug for CompositionNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CompositionNode")
+            .field("id", &self.id)
+            .field("composable_type", &self.composable_type)
+            .field("key", &self.key)
+            .field("call_site", &self.call_site)
+            .field("parent", &self.parent)
+            .field("children", &self.children)
+            .field("entity", &self.entity)
+            .field("state_slots", &self.state_slots.len())
+            .field("dirty", &self.dirty)
+            .field("restartable", &self.content.is_some())
+            .field("entities", &self.entities)
+            .field("subscriptions", &self.subscriptions.len())
+            .field("disposals", &self.disposals.len())
+            .finish_non_exhaustive()
+    }
 }
 
 impl CompositionNode {
@@ -92,12 +245,25 @@ impl CompositionNode {
             id: CompositionId::new(),
             composable_type,
             key: None,
+            call_site: None,
+            group_key: GroupKey::ROOT,
             parent: None,
             children: Vec::new(),
             entity: None,
             state_slots: Vec::new(),
             modifiers: Modifiers::default(),
             dirty: true,
+            content: None,
+            entities: Vec::new(),
+            state_owner: None,
+            subscriptions: Vec::new(),
+            disposals: Vec::new(),
+            error_handler: None,
+            provided_local: None,
+            movable: None,
+            skip: None,
+            slot_cursor: 0,
+            pass: None,
         }
     }
 
@@ -122,6 +288,64 @@ impl CompositionNode {
     pub fn mark_clean(&mut self) {
         self.dirty = false;
     }
+
+    /// Whether this node can be recomposed without recomposing its parent
+    pub fn is_restartable(&self) -> bool {
+        self.content.is_some()
+    }
+
+    /// Advance to the next state slot, returning its index and the value it
+    /// holds if that value is a `T`
+    pub fn next_slot<T: Clone + 'static>(&mut self) -> (usize, Option<T>) {
+        let index = self.slot_cursor;
+        self.slot_cursor += 1;
+        let value = self
+            .state_slots
+            .get(index)
+            .and_then(|slot| slot.downcast_ref::<T>())
+            .cloned();
+        (index, value)
+    }
+
+    /// Store a value in the state slot at `index`
+    pub fn set_slot<T: Send + Sync + 'static>(&mut self, index: usize, value: T) {
+        let slot: StateSlot = Box::new(value);
+        if index < self.state_slots.len() {
+            self.state_slots[index] = slot;
+        } else {
+            self.state_slots.push(slot);
+        }
+    }
+
+    /// Register the cleanup that unsubscribes this node from a state.
+    ///
+    /// Subscriptions last for one pass: they are disposed when the node
+    /// composes again, as well as when it is removed.
+    pub fn add_subscription(&mut self, unsubscribe: Disposal) {
+        self.subscriptions.push(unsubscribe);
+    }
+
+    /// Register cleanup to run only when this node is removed
+    pub fn add_disposal(&mut self, dispose: Disposal) {
+        self.disposals.push(dispose);
+    }
+
+    /// Cleanup to run when this node is removed: its subscriptions and
+    /// disposals are run first, then the states it owns are freed
+    fn drain_disposals(&mut self) -> impl Iterator<Item = Disposal> {
+        let owner = self.state_owner.take();
+        std::mem::take(&mut self.subscriptions)
+            .into_iter()
+            .chain(std::mem::take(&mut self.disposals))
+            .chain(owner.map(|owner| Box::new(move || drop(owner)) as Disposal))
+    }
+
+    /// Owner for `State` values created while composing this node
+    pub(crate) fn state_owner(&mut self) -> Owner<SyncStorage> {
+        self.state_owner
+            .get_or_insert_with(SyncStorage::owner)
+            .clone()
+    }
 }
 
 /// The composition tree manager
@@ -135,6 +359,50 @@ pub struct CompositionTree {
     pub new_nodes: Vec<CompositionId>,
     /// Nodes that were removed and need entity cleanup
     pub removed_nodes: Vec<CompositionId>,
+    /// Children emitted under each parent entity during the last pass
+    /// (`None` is the root level)
+    pub(crate) emitted_children: HashMap<Option<Entity>, Vec<Entity>>,
+    /// Bundle type each reconcilable entity was spawned with
+    pub(crate) entity_kinds: HashMap<Entity, TypeId>,
+    /// Item key and emission index of entities emitted by keyed list items
+    pub(crate) entity_keys: HashMap<Entity, (CompositionKey, usize)>,
+    /// Node each entity was emitted by
+    pub(crate) entity_nodes: HashMap<Entity, CompositionId>,
+    /// Node with each group key
+    group_keys: HashMap<GroupKey, CompositionId>,
+    /// Entity each node was linked to with a `CompositionBridge`
+    pub(crate) bridged: HashMap<CompositionId, Entity>,
+    /// Cleanup of removed nodes and stale subscriptions that has not run yet
+    pub(crate) pending_disposals: Vec<Disposal>,
+    /// Movable content nodes by movable content ID
+    pub(crate) movables: HashMap<u64, CompositionId>,
+    /// Movable content nodes by container entity
+    movable_entities: HashMap<Entity, CompositionId>,
+    /// Movable content dropped by its parent this frame, waiting to be emitted
+    /// elsewhere
+    pub(crate) parked_movables: HashSet<CompositionId>,
+    /// Movable content emitted this frame, with the node that emitted it last
+    pub(crate) emitted_movables: HashMap<u64, CompositionId>,
+    /// Movable content entities attached to a parent this frame
+    pub(crate) attached_movables: HashSet<Entity>,
+}
+
+/// Counts of what a composition tree holds on to, for checking that removed
+/// scopes don't leak
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompositionStats {
+    /// Nodes in the tree
+    pub nodes: usize,
+    /// Nodes owning at least one `State`
+    pub state_owners: usize,
+    /// State subscriptions held by nodes
+    pub subscriptions: usize,
+    /// Entities mapped to the node that emitted them
+    pub entity_mappings: usize,
+    /// Entities tracked for reconciliation
+    pub tracked_entities: usize,
+    /// Queued cleanup that has not run yet
+    pub pending_disposals: usize,
 }
 
 impl CompositionTree {
@@ -158,16 +426,73 @@ impl CompositionTree {
         self.nodes.get_mut(&id)
     }
 
+    /// Get a node, inserting an empty node with this ID under `parent` if
+    /// there is none
+    ///
+    /// An inserted node has no call site or position to derive its group key
+    /// from, so the key is derived from its ID under the parent's key.
+    pub fn get_or_insert(
+        &mut self,
+        id: CompositionId,
+        composable_type: ComposableType,
+        parent: Option<CompositionId>,
+    ) -> &mut CompositionNode {
+        if !self.nodes.contains_key(&id) {
+            let mut node = CompositionNode::new(composable_type);
+            node.id = id;
+            node.parent = parent;
+            if id == CompositionId::root() {
+                self.root = Some(id);
+            } else {
+                let parent_key = parent
+                    .and_then(|parent| self.get(parent))
+                    .map_or(GroupKey::ROOT, |parent| parent.group_key);
+                node.group_key = parent_key.child(None, Some(&CompositionKey::from(id.0)), 0);
+            }
+            self.insert(node);
+        }
+        self.nodes.get_mut(&id).unwrap()
+    }
+
+    pub fn contains(&self, id: CompositionId) -> bool {
+        self.nodes.contains_key(&id)
+    }
+
     pub fn insert(&mut self, node: CompositionNode) -> CompositionId {
         let id = node.id;
         self.new_nodes.push(id);
+        self.group_keys.insert(node.group_key, id);
         self.nodes.insert(id, node);
         id
     }
 
+    /// Remove a node from the tree.
+    ///
+    /// The node's subscriptions and owned states are queued for disposal; see
+    /// `take_disposals`.
     pub fn remove(&mut self, id: CompositionId) -> Option<CompositionNode> {
-        if let Some(node) = self.nodes.remove(&id) {
+        if let Some(mut node) = self.nodes.remove(&id) {
             self.removed_nodes.push(id);
+            self.pending_disposals.extend(node.drain_disposals());
+            if self.group_keys.get(&node.group_key) == Some(&id) {
+                self.group_keys.remove(&node.group_key);
+            }
+            if let Some(movable) = node.movable {
+                if self.movables.get(&movable) == Some(&id) {
+                    self.movables.remove(&movable);
+                }
+                self.parked_movables.remove(&id);
+            }
+            if let Some(entity) = node.entity {
+                if self.movable_entities.get(&entity) == Some(&id) {
+                    self.movable_entities.remove(&entity);
+                }
+            }
+            for entity in &node.entities {
+                if self.entity_nodes.get(entity) == Some(&id) {
+                    self.entity_nodes.remove(entity);
+                }
+            }
             // Remove from parent's children list
             if let Some(parent_id) = node.parent {
                 if let Some(parent) = self.nodes.get_mut(&parent_id) {
@@ -199,6 +524,82 @@ impl CompositionTree {
         self.nodes.get(&node_id).and_then(|n| n.entity)
     }
 
+    /// Get the node that emitted an entity
+    pub fn node_for_entity(&self, entity: Entity) -> Option<CompositionId> {
+        self.entity_nodes.get(&entity).copied()
+    }
+
+    /// Get the node with a given group key
+    pub fn node_for_group_key(&self, group_key: GroupKey) -> Option<CompositionId> {
+        self.group_keys.get(&group_key).copied()
+    }
+
+    /// Get the movable content node whose container is `entity`
+    pub fn movable_for_entity(&self, entity: Entity) -> Option<CompositionId> {
+        self.movable_entities.get(&entity).copied()
+    }
+
+    /// Record that `node_id` composes movable content `movable` inside the
+    /// node's entity
+    pub fn register_movable(&mut self, movable: u64, node_id: CompositionId) {
+        let Some(node) = self.nodes.get_mut(&node_id) else {
+            return;
+        };
+        node.movable = Some(movable);
+        self.movables.insert(movable, node_id);
+        if let Some(entity) = node.entity {
+            self.movable_entities.retain(|_, id| *id != node_id);
+            self.movable_entities.insert(entity, node_id);
+        }
+    }
+
+    /// Record that `entity` was emitted while composing `node_id`
+    pub fn register_entity(&mut self, entity: Entity, node_id: CompositionId) {
+        self.entity_nodes.insert(entity, node_id);
+        if let Some(node) = self.nodes.get_mut(&node_id) {
+            node.entities.push(entity);
+        }
+    }
+
+    /// Forget the entities recorded for a node
+    pub fn clear_entities(&mut self, node_id: CompositionId) {
+        let Some(node) = self.nodes.get_mut(&node_id) else {
+            return;
+        };
+        for entity in std::mem::take(&mut node.entities) {
+            if self.entity_nodes.get(&entity) == Some(&node_id) {
+                self.entity_nodes.remove(&entity);
+            }
+        }
+    }
+
+    /// Find the nearest ancestor of `id` that is an error boundary
+    pub fn error_boundary_of(&self, id: CompositionId) -> Option<CompositionId> {
+        let mut current = self.nodes.get(&id)?.parent?;
+        loop {
+            let node = self.nodes.get(&current)?;
+            if node.error_handler.is_some() {
+                return Some(current);
+            }
+            current = node.parent?;
+        }
+    }
+
+    /// Find the node to recompose when `id` is invalidated.
+    ///
+    /// Walks up from `id` to the nearest node that can recompose on its own,
+    /// or to the root. Returns `None` if `id` is no longer in the tree.
+    pub fn restartable_ancestor(&self, id: CompositionId) -> Option<CompositionId> {
+        let mut current = id;
+        loop {
+            let node = self.nodes.get(&current)?;
+            match node.parent {
+                Some(parent) if !node.is_restartable() => current = parent,
+                _ => return Some(current),
+            }
+        }
+    }
+
     pub fn mark_dirty(&mut self, id: CompositionId) {
         if let Some(node) = self.nodes.get_mut(&id) {
             node.mark_dirty();
@@ -206,18 +607,148 @@ impl CompositionTree {
         }
     }
 
+    /// Mark `id` as needing recomposition along with its ancestors up to the
+    /// nearest restartable one, which is returned.
+    ///
+    /// The groups in between are marked too, so skippable groups on the way
+    /// down from the restarted ancestor run again instead of being skipped.
+    pub fn invalidate(&mut self, id: CompositionId) -> Option<CompositionId> {
+        let target = self.restartable_ancestor(id)?;
+        let mut current = id;
+        while current != target {
+            let node = self.nodes.get_mut(&current)?;
+            node.mark_dirty();
+            current = node.parent?;
+        }
+        self.mark_dirty(target);
+        Some(target)
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = (&CompositionId, &CompositionNode)> {
         self.nodes.iter()
     }
 
+    /// Take the cleanup queued so far.
+    ///
+    /// Disposals may touch state and composition, so they are run by the
+    /// caller once it no longer borrows the tree.
+    pub fn take_disposals(&mut self) -> Vec<Disposal> {
+        std::mem::take(&mut self.pending_disposals)
+    }
+
+    /// Count what the tree holds on to
+    pub fn stats(&self) -> CompositionStats {
+        CompositionStats {
+            nodes: self.nodes.len(),
+            state_owners: self
+                .nodes
+                .values()
+                .filter(|node| node.state_owner.is_some())
+                .count(),
+            subscriptions: self
+                .nodes
+                .values()
+                .map(|node| node.subscriptions.len())
+                .sum(),
+            entity_mappings: self.entity_nodes.len(),
+            tracked_entities: self.entity_kinds.len(),
+            pending_disposals: self.pending_disposals.len(),
+        }
+    }
+
     pub fn clear(&mut self) {
         // Mark all entities for removal
-        for (id, _) in self.nodes.iter() {
-            self.removed_nodes.push(*id);
+        for (id, mut node) in self.nodes.drain() {
+            self.removed_nodes.push(id);
+            self.pending_disposals.extend(node.drain_disposals());
         }
-        self.nodes.clear();
         self.root = None;
         self.pending_recomposition.clear();
         self.new_nodes.clear();
+        self.emitted_children.clear();
+        self.entity_kinds.clear();
+        self.entity_keys.clear();
+        self.entity_nodes.clear();
+        self.group_keys.clear();
+        self.movables.clear();
+        self.movable_entities.clear();
+        self.parked_movables.clear();
+        self.emitted_movables.clear();
+        self.attached_movables.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node_with_entity(tree: &mut CompositionTree, entity: Entity) -> CompositionId {
+        let mut node = CompositionNode::new(ComposableType::Custom("Test".to_string()));
+        node.group_key = GroupKey::ROOT.child(None, None, tree.nodes.len());
+        node.entity = Some(entity);
+        tree.insert(node)
+    }
+
+    #[test]
+    fn nodes_are_found_by_group_key_until_removed() {
+        let mut tree = CompositionTree::new();
+        let id = node_with_entity(&mut tree, Entity::from_raw_u32(1).unwrap());
+        let group_key = tree.get(id).unwrap().group_key;
+
+        assert_eq!(tree.node_for_group_key(group_key), Some(id));
+        tree.remove(id);
+        assert_eq!(tree.node_for_group_key(group_key), None);
+    }
+
+    #[test]
+    fn inserted_scopes_get_distinct_group_keys() {
+        let mut tree = CompositionTree::new();
+        let scope = ComposableType::Custom("Scope".to_string());
+        tree.get_or_insert(CompositionId::root(), scope.clone(), None);
+        let first = CompositionId::new();
+        let second = CompositionId::new();
+        tree.get_or_insert(first, scope.clone(), Some(CompositionId::root()));
+        tree.get_or_insert(second, scope, Some(CompositionId::root()));
+
+        let first_key = tree.get(first).unwrap().group_key;
+        let second_key = tree.get(second).unwrap().group_key;
+        assert_ne!(first_key, second_key);
+        assert_ne!(first_key, GroupKey::ROOT);
+        assert_eq!(tree.node_for_group_key(first_key), Some(first));
+        assert_eq!(tree.node_for_group_key(second_key), Some(second));
+        assert_eq!(
+            tree.node_for_group_key(GroupKey::ROOT),
+            Some(CompositionId::root())
+        );
+    }
+
+    #[test]
+    fn movable_content_is_found_by_container_until_removed() {
+        let mut tree = CompositionTree::new();
+        let container = Entity::from_raw_u32(1).unwrap();
+        let id = node_with_entity(&mut tree, container);
+        node_with_entity(&mut tree, Entity::from_raw_u32(2).unwrap());
+
+        assert_eq!(tree.movable_for_entity(container), None);
+        tree.register_movable(7, id);
+        assert_eq!(tree.movable_for_entity(container), Some(id));
+        tree.remove(id);
+        assert_eq!(tree.movable_for_entity(container), None);
+        assert!(tree.movables.is_empty());
+    }
+
+    #[test]
+    fn removed_nodes_queue_their_cleanup() {
+        let mut tree = CompositionTree::new();
+        let id = node_with_entity(&mut tree, Entity::from_raw_u32(1).unwrap());
+        let node = tree.get_mut(id).unwrap();
+        node.add_subscription(Box::new(|| {}));
+        node.add_disposal(Box::new(|| {}));
+        node.state_owner();
+
+        tree.remove(id);
+
+        assert_eq!(tree.take_disposals().len(), 3);
+        assert_eq!(tree.stats(), CompositionStats::default());
     }
 }
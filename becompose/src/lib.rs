@@ -25,7 +25,7 @@
 //!     
 //!     // Build UI using BECOMPOSE
 //!     ColumnElement::new()
-//!         .with_modifier(Modifiers::padding(24.0).fill_max_size())
+//!         .with_modifier(Modifiers::new().padding(24.0).fill_max_size())
 //!         .with_child(text("Hello, BECOMPOSE!"))
 //!         .build(&mut commands);
 //! }
@@ -38,6 +38,13 @@ pub mod layout;
 pub mod modifier;
 pub mod state;
 
+#[cfg(test)]
+mod testing;
+
+// Lets `#[composable]` functions in unit tests refer to this crate by name
+#[cfg(test)]
+extern crate self as becompose;
+
 /// Re-export the composable macro
 pub use becompose_macros::composable;
 
@@ -48,13 +55,15 @@ pub mod prelude {
 
     // Composition
     pub use crate::composition::{
-        CompositionContext, CompositionId, CompositionKey, CompositionTree,
+        CompositionContext, CompositionError, CompositionId, CompositionKey, CompositionTree,
+        IntoCompositionResult, RecomposeScheduler,
     };
 
     // State management
     pub use crate::state::{
-        derived_state_of, disposable_effect, launched_effect, mutable_state_of, remember,
-        remember_mutable_state, side_effect, DerivedState, DisposableEffect, MutableState,
+        derived_state_of, disposable_effect, mutable_state_of, remember_mutable_state, side_effect,
+        with_mutable_snapshot, with_snapshot, DerivedState, DisposableEffect, MutableState,
+        SnapshotConflict,
     };
 
     // Modifiers
@@ -72,32 +81,71 @@ pub mod prelude {
     // Components
     pub use crate::components::{
         BoxConfig, BoxNode, ButtonConfig, ButtonNode, CardConfig, CardNode, Clickable,
-        ColumnConfig, ColumnNode, ImageConfig, ImageNode, OnClick, RowConfig, RowNode,
-        SpacerConfig, SpacerNode, TextConfig, TextNode, TextStyle,
+        ColumnConfig, ColumnNode, ImageConfig, ImageNode, IntoOnChange, IntoOnClick, OnChange,
+        OnClick, RowConfig, RowNode, SpacerConfig, SpacerNode, TextConfig, TextNode, TextStyle,
     };
 
     // Bevy integration - core
     pub use crate::bevy_integration::{
+        // Produced state
+        collect_as_state,
+        // Composition locals
+        composition_local_of,
         invalidate,
+        // Effects
+        launched_effect,
+        launched_io_effect,
+        // Movable content
+        movable_content_of,
+        produce_state,
+        // Positional memoization
+        remember,
+        // Saveable state
+        remember_saveable,
+        remember_state,
         run_app,
         run_app_with_config,
+        use_query,
+        use_resource,
+        use_resource_map,
+        use_resource_map_opt,
+        use_resource_opt,
         // App
         BecomposeApp,
         BecomposeCommands,
         BecomposePlugin,
         Box,
         BoxElement,
+        BoxWithConstraints,
         Button,
         ButtonElement,
         Column,
         ColumnElement,
+        // Compose roots in an existing app
+        ComposeRoot,
+        ComposeRootAppExt,
+        ComposeRoots,
+        // Per-App runtime
+        ComposeRuntime,
+        ComposeTarget,
         CompositionBridge,
+        CompositionErrorEvent,
+        CompositionLocal,
+        ErrorBoundary,
+        ErrorBoundaryReset,
+        FileSaveableRegistry,
         FixedSpacer,
         ForEach,
+        ForEachKeyed,
         If,
         IfElse,
+        InMemorySaveableRegistry,
+        MovableContent,
+        ProvideLocal,
         Row,
         RowElement,
+        SaveFormat,
+        SaveableStateRegistry,
         Scope,
         // Scope-based recomposition
         ScopeId,
@@ -105,6 +153,8 @@ pub mod prelude {
         SpacerElement,
         // Reactive State
         State,
+        StateReceiver,
+        StateSink,
         Surface,
         // Composable functions (Jetpack Compose style)
         Text,
@@ -112,6 +162,7 @@ pub mod prelude {
         UiBuilder,
         UiElement,
         UiRoot,
+        WatchResourceAppExt,
         WindowConfig,
     };
 
@@ -1,47 +1,341 @@
 //! Derived State
 //!
 //! State that is computed from other state values.
+//!
+//! A derived state records the states read while computing its value and
+//! observes them. When one of them changes, the value is computed again, and
+//! the scopes reading the derived state are only invalidated if the value is
+//! different. A derived state nobody reads is computed again lazily, the next
+//! time it is read.
 
 use std::cell::RefCell;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+
+use generational_box::{GenerationalBox, GenerationalBoxId, SyncStorage};
+
+use crate::bevy_integration::{create_state_box, current_scope_id, with_scope_node, Subscriber};
+use crate::composition::{CompositionId, Stable};
+
+/// A state read while computing a derived state
+pub(crate) trait Dependency: Send + Sync {
+    /// Identity of the state
+    fn key(&self) -> GenerationalBoxId;
+
+    /// Start notifying `observer` when the state changes
+    fn observe(&self, observer: &Subscriber);
+
+    /// Stop notifying `observer`
+    fn unobserve(&self, observer: &Subscriber);
+
+    /// Whether this thread reads a value of the state other than the applied
+    /// one: a write of its mutable snapshot that is not applied yet, or an
+    /// older value kept for its read-only snapshot
+    fn is_pending(&self) -> bool;
+}
+
+thread_local! {
+    /// Dependencies recorded by the derived states computing on this thread,
+    /// innermost last
+    static DERIVATIONS: RefCell<Vec<Vec<Box<dyn Dependency>>>> = const { RefCell::new(Vec::new()) };
+}
 
-/// Derived state that computes its value from other state
-#[derive(Clone)]
-pub struct DerivedState<T> {
-    inner: Arc<RefCell<DerivedStateInner<T>>>,
+/// Record a state read by the derived state computing on this thread.
+///
+/// Returns `false` if no derived state is computing, in which case the read
+/// subscribes the current scope as usual.
+pub(crate) fn record_dependency(dependency: impl FnOnce() -> Box<dyn Dependency>) -> bool {
+    DERIVATIONS.with(|derivations| {
+        let mut derivations = derivations.borrow_mut();
+        let Some(dependencies) = derivations.last_mut() else {
+            return false;
+        };
+        let dependency = dependency();
+        if !dependencies.iter().any(|d| d.key() == dependency.key()) {
+            dependencies.push(dependency);
+        }
+        true
+    })
+}
+
+/// Run a calculation, returning its value and the states it read
+fn track<T>(calculation: &(dyn Fn() -> T + Send + Sync)) -> (T, Vec<Box<dyn Dependency>>) {
+    /// Pops the calculation's dependencies even if it panics
+    struct Tracking;
+
+    impl Drop for Tracking {
+        fn drop(&mut self) {
+            DERIVATIONS.with(|derivations| derivations.borrow_mut().pop());
+        }
+    }
+
+    DERIVATIONS.with(|derivations| derivations.borrow_mut().push(Vec::new()));
+    let tracking = Tracking;
+    let value = calculation();
+    let dependencies = DERIVATIONS.with(|derivations| {
+        derivations
+            .borrow_mut()
+            .last_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    });
+    drop(tracking);
+    (value, dependencies)
 }
 
 struct DerivedStateInner<T> {
+    /// Value of the last calculation
     value: Option<T>,
-    calculation: Box<dyn Fn() -> T + Send + Sync>,
+    calculation: Arc<dyn Fn() -> T + Send + Sync>,
+    /// Whether a dependency changed since the last calculation
     dirty: bool,
+    /// States read by the last calculation
+    dependencies: Vec<Box<dyn Dependency>>,
+    /// Scopes and derived states that read this state
+    subscribers: Vec<Subscriber>,
+    /// Observes the dependencies on behalf of this state
+    observer: Option<Subscriber>,
+}
+
+impl<T> Drop for DerivedStateInner<T> {
+    fn drop(&mut self) {
+        if let Some(observer) = &self.observer {
+            for dependency in &self.dependencies {
+                dependency.unobserve(observer);
+            }
+        }
+    }
+}
+
+/// State computed from the `State`s and derived states its calculation reads
+///
+/// Like `State`, a derived state is `Copy` and is owned by the scope it was
+/// created in; remember it to keep it across recompositions.
+///
+/// # Example
+/// ```ignore
+/// let visible = remember(|| {
+///     derived_state_of(move || {
+///         let filter = filter.get();
+///         todos.get().into_iter().filter(|todo| filter.shows(todo)).collect::<Vec<_>>()
+///     })
+/// });
+/// // Only recomposes when the visible todos change
+/// ForEach(&visible.get(), |todo| TodoRow(todo.clone()));
+/// ```
+pub struct DerivedState<T: 'static> {
+    inner: GenerationalBox<RwLock<DerivedStateInner<T>>, SyncStorage>,
+}
+
+impl<T: 'static> Copy for DerivedState<T> {}
+
+impl<T: 'static> Clone for DerivedState<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
 }
 
+// Derived states invalidate their readers when their value changes
+impl<T: 'static> Stable for DerivedState<T> {}
+
 impl<T: Clone + PartialEq + Send + Sync + 'static> DerivedState<T> {
     pub fn new<F: Fn() -> T + Send + Sync + 'static>(calculation: F) -> Self {
-        Self {
-            inner: Arc::new(RefCell::new(DerivedStateInner {
+        let state = Self {
+            inner: create_state_box(RwLock::new(DerivedStateInner {
                 value: None,
-                calculation: Box::new(calculation),
+                calculation: Arc::new(calculation),
                 dirty: true,
+                dependencies: Vec::new(),
+                subscribers: Vec::new(),
+                observer: None,
             })),
+        };
+        let observer = Subscriber::derived(Arc::new(move || state.dependency_changed()));
+        if let Ok(inner) = state.inner.try_read() {
+            inner.write().unwrap().observer = Some(observer);
         }
+        state
     }
 
+    /// Get the value, computing it if a dependency changed, and subscribe the
+    /// current scope
     pub fn get(&self) -> T {
-        let mut inner = self.inner.borrow_mut();
-        if inner.dirty || inner.value.is_none() {
-            let new_value = (inner.calculation)();
-            inner.value = Some(new_value.clone());
-            inner.dirty = false;
-            new_value
-        } else {
-            inner.value.clone().unwrap()
+        // Computed before subscribing, so the reader isn't invalidated by
+        // the value it is about to read
+        let value = self.value();
+        if !record_dependency(|| Box::new(*self)) {
+            if let Some(scope_id) = current_scope_id() {
+                self.subscribe(scope_id);
+            }
         }
+        value
+    }
+
+    /// Get the value without subscribing
+    pub fn get_untracked(&self) -> T {
+        self.value()
     }
 
+    /// Compute the value again, even if no dependency changed
     pub fn invalidate(&self) {
-        self.inner.borrow_mut().dirty = true;
+        if let Ok(inner) = self.inner.try_read() {
+            inner.write().unwrap().dirty = true;
+        }
+        self.dependency_changed();
+    }
+
+    /// Number of scopes and derived states reading this state
+    pub fn subscriber_count(&self) -> usize {
+        self.inner
+            .try_read()
+            .ok()
+            .and_then(|guard| guard.read().ok().map(|inner| inner.subscribers.len()))
+            .unwrap_or(0)
+    }
+
+    fn value(&self) -> T {
+        let (cached, pending, calculation) = {
+            let inner_guard = self.inner.try_read().expect("DerivedState was dropped");
+            let inner = inner_guard.read().unwrap();
+            (
+                inner.value.clone().filter(|_| !inner.dirty),
+                inner.dependencies.iter().any(|d| d.is_pending()),
+                inner.calculation.clone(),
+            )
+        };
+        // This thread's snapshot reads values other than the applied ones, so
+        // a value computed from them is not kept, nor the cached one used
+        if pending {
+            return track(calculation.as_ref()).0;
+        }
+        match cached {
+            Some(value) => value,
+            None => self.recompute(),
+        }
+    }
+
+    /// Called when a dependency changed. The value is computed again right
+    /// away if anything reads it, or else the next time it is read.
+    fn dependency_changed(&self) {
+        let observed = self.inner.try_read().is_ok_and(|inner_guard| {
+            let mut inner = inner_guard.write().unwrap();
+            inner.dirty = true;
+            !inner.subscribers.is_empty()
+        });
+        if observed {
+            self.recompute();
+        }
+    }
+
+    /// Compute the value, observe the states it read and invalidate the
+    /// subscribers if it changed
+    fn recompute(&self) -> T {
+        let calculation = self
+            .inner
+            .try_read()
+            .expect("DerivedState was dropped")
+            .read()
+            .unwrap()
+            .calculation
+            .clone();
+        let (value, dependencies) = track(calculation.as_ref());
+
+        let Ok(inner_guard) = self.inner.try_read() else {
+            return value;
+        };
+        let (observer, previous, invalidated) = {
+            let mut inner = inner_guard.write().unwrap();
+            let changed = inner.value.as_ref() != Some(&value);
+            inner.value = Some(value.clone());
+            inner.dirty = false;
+            let previous = std::mem::replace(&mut inner.dependencies, dependencies);
+            let invalidated = if changed {
+                inner.subscribers.clone()
+            } else {
+                Vec::new()
+            };
+            (inner.observer.clone(), previous, invalidated)
+        };
+
+        if let Some(observer) = observer {
+            let inner = inner_guard.read().unwrap();
+            for dependency in &previous {
+                if !inner
+                    .dependencies
+                    .iter()
+                    .any(|d| d.key() == dependency.key())
+                {
+                    dependency.unobserve(&observer);
+                }
+            }
+            for dependency in &inner.dependencies {
+                dependency.observe(&observer);
+            }
+        }
+        drop(inner_guard);
+
+        for subscriber in invalidated {
+            subscriber.notify();
+        }
+        value
+    }
+
+    /// Subscribe a scope of the current runtime, until the scope is disposed
+    fn subscribe(&self, scope_id: CompositionId) {
+        let Some(subscriber) = Subscriber::new(scope_id) else {
+            return;
+        };
+        if self.add_subscriber(&subscriber) {
+            let state = *self;
+            with_scope_node(scope_id, |node| {
+                node.add_subscription(Box::new(move || state.remove_subscriber(&subscriber)));
+            });
+        }
+    }
+
+    fn add_subscriber(&self, subscriber: &Subscriber) -> bool {
+        self.inner.try_read().is_ok_and(|inner_guard| {
+            let mut inner = inner_guard.write().unwrap();
+            let added = !inner.subscribers.contains(subscriber);
+            if added {
+                inner.subscribers.push(subscriber.clone());
+            }
+            added
+        })
+    }
+
+    fn remove_subscriber(&self, subscriber: &Subscriber) {
+        if let Ok(inner_guard) = self.inner.try_read() {
+            inner_guard
+                .write()
+                .unwrap()
+                .subscribers
+                .retain(|s| s != subscriber);
+        }
+    }
+}
+
+impl<T: Clone + PartialEq + Send + Sync + 'static> Dependency for DerivedState<T> {
+    fn key(&self) -> GenerationalBoxId {
+        self.inner.id()
+    }
+
+    fn observe(&self, observer: &Subscriber) {
+        self.add_subscriber(observer);
+    }
+
+    fn unobserve(&self, observer: &Subscriber) {
+        self.remove_subscriber(observer);
+    }
+
+    fn is_pending(&self) -> bool {
+        self.inner.try_read().is_ok_and(|inner_guard| {
+            inner_guard
+                .read()
+                .unwrap()
+                .dependencies
+                .iter()
+                .any(|d| d.is_pending())
+        })
     }
 }
 
@@ -53,3 +347,86 @@ where
 {
     DerivedState::new(calculation)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bevy_integration::{remember, Scope, State, Text};
+    use crate::components::TextStyle;
+    use crate::testing::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn derived_states_recompute_only_when_a_dependency_changes() {
+        let (source, unrelated) = (State::new(2), State::new(0));
+        let computations = Arc::new(AtomicUsize::new(0));
+        let doubled = derived_state_of({
+            let computations = computations.clone();
+            move || {
+                computations.fetch_add(1, Ordering::SeqCst);
+                source.get() * 2
+            }
+        });
+
+        assert_eq!((doubled.get(), doubled.get()), (4, 4));
+        unrelated.set(1);
+        assert_eq!(doubled.get(), 4);
+        assert_eq!(computations.load(Ordering::SeqCst), 1);
+
+        source.set(3);
+        assert_eq!(doubled.get(), 6);
+        assert_eq!(computations.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn dependencies_follow_the_last_computation() {
+        let (use_fallback, primary, fallback) = (State::new(false), State::new(1), State::new(10));
+        let value = derived_state_of(move || {
+            if use_fallback.get() {
+                fallback.get()
+            } else {
+                primary.get()
+            }
+        });
+        assert_eq!(value.get(), 1);
+
+        use_fallback.set(true);
+        assert_eq!(value.get(), 10);
+        fallback.set(20);
+        assert_eq!(value.get(), 20);
+    }
+
+    #[test]
+    fn readers_recompose_only_when_the_derived_value_changes() {
+        let items = Captured::new();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let mut app = compose_app({
+            let items = items.clone();
+            let runs = runs.clone();
+            move || {
+                let list = remember(|| State::new(vec![1, 2, 3]));
+                items.set(list);
+                let even = remember(|| {
+                    derived_state_of(move || {
+                        list.get().iter().filter(|item| *item % 2 == 0).count()
+                    })
+                });
+                let runs = runs.clone();
+                Scope(move || {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                    Text(format!("{} even", even.get()), TextStyle::body());
+                });
+            }
+        });
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        items.get().update(|list| list.push(5));
+        app.update();
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        items.get().update(|list| list.push(4));
+        app.update();
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        assert_eq!(texts(&mut app), ["2 even"]);
+    }
+}
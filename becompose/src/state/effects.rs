@@ -34,23 +34,6 @@ impl Drop for DisposableEffect {
     }
 }
 
-/// Run an effect when a key changes
-pub fn launched_effect<K, F>(key: K, effect: F)
-where
-    K: PartialEq + Clone + Send + Sync + 'static,
-    F: FnOnce() + Send + Sync + 'static,
-{
-    let ctx = CompositionContext::current();
-    let prev_key: Option<K> = ctx.state_manager().remember(|| None);
-
-    if prev_key.as_ref() != Some(&key) {
-        // Key changed, run effect
-        ctx.state_manager()
-            .update(ctx.state_manager().current_index() - 1, Some(key));
-        effect();
-    }
-}
-
 /// Run a disposable effect
 pub fn disposable_effect<K, F>(key: K, effect: F) -> DisposableEffect
 where
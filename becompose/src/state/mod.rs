@@ -7,9 +7,11 @@ mod effects;
 mod mutable_state;
 mod remember;
 mod slot;
+pub(crate) mod snapshot;
 
 pub use derived_state::*;
 pub use effects::*;
 pub use mutable_state::*;
 pub use remember::*;
 pub use slot::*;
+pub use snapshot::{in_mutable_snapshot, with_mutable_snapshot, with_snapshot, SnapshotConflict};
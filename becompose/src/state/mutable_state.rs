@@ -4,12 +4,16 @@
 
 use std::sync::{Arc, RwLock};
 
+use crate::bevy_integration::{current_scope_id, with_scope_node, Subscriber};
 use crate::composition::CompositionId;
 
 /// Callback type for state change notifications
 pub type StateChangeCallback = Arc<dyn Fn() + Send + Sync>;
 
 /// Mutable state holder with change tracking
+///
+/// Reading the value during composition subscribes the current node; setting
+/// a different value marks every subscribed node for recomposition.
 #[derive(Clone)]
 pub struct MutableState<T> {
     inner: Arc<RwLock<MutableStateInner<T>>>,
@@ -18,7 +22,7 @@ pub struct MutableState<T> {
 struct MutableStateInner<T> {
     value: T,
     version: u64,
-    subscribers: Vec<CompositionId>,
+    subscribers: Vec<Subscriber>,
     on_change: Option<StateChangeCallback>,
 }
 
@@ -35,24 +39,37 @@ impl<T: Clone + PartialEq + Send + Sync + 'static> MutableState<T> {
     }
 
     pub fn get(&self) -> T {
+        if let Some(id) = current_scope_id() {
+            self.subscribe(id);
+        }
+        self.inner.read().unwrap().value.clone()
+    }
+
+    /// Get the value without subscribing the current node
+    pub fn get_untracked(&self) -> T {
         self.inner.read().unwrap().value.clone()
     }
 
     pub fn set(&self, new_value: T) {
-        let callback = {
+        let changed = {
             let mut inner = self.inner.write().unwrap();
             if inner.value != new_value {
                 inner.value = new_value;
                 inner.version += 1;
-                inner.on_change.clone()
+                Some((inner.subscribers.clone(), inner.on_change.clone()))
             } else {
                 None
             }
         };
 
-        // Trigger change callback outside of lock
-        if let Some(cb) = callback {
-            cb();
+        // Notify outside of lock
+        if let Some((subscribers, callback)) = changed {
+            for subscriber in subscribers {
+                subscriber.notify();
+            }
+            if let Some(cb) = callback {
+                cb();
+            }
         }
     }
 
@@ -68,8 +85,38 @@ impl<T: Clone + PartialEq + Send + Sync + 'static> MutableState<T> {
         self.inner.read().unwrap().version
     }
 
+    /// Subscribe a node of the runtime active on this thread.
+    ///
+    /// The subscription is removed again when the node is disposed.
     pub fn subscribe(&self, id: CompositionId) {
-        self.inner.write().unwrap().subscribers.push(id);
+        let Some(subscriber) = Subscriber::new(id) else {
+            return;
+        };
+        {
+            let mut inner = self.inner.write().unwrap();
+            if inner.subscribers.contains(&subscriber) {
+                return;
+            }
+            inner.subscribers.push(subscriber.clone());
+        }
+
+        let state = Arc::downgrade(&self.inner);
+        with_scope_node(id, |node| {
+            node.add_subscription(Box::new(move || {
+                if let Some(state) = state.upgrade() {
+                    state
+                        .write()
+                        .unwrap()
+                        .subscribers
+                        .retain(|s| s != &subscriber);
+                }
+            }));
+        });
+    }
+
+    /// Number of nodes subscribed to this state
+    pub fn subscriber_count(&self) -> usize {
+        self.inner.read().unwrap().subscribers.len()
     }
 
     pub fn set_on_change(&self, callback: StateChangeCallback) {
@@ -6,13 +6,27 @@ use std::any::Any;
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use crate::bevy_integration::{remember_in_scope, with_scope_node};
+use crate::composition::CompositionId;
+
 /// A slot for storing state
 pub type StateSlot = Box<dyn Any + Send + Sync>;
 
 /// Manages state slots for a composable
+///
+/// A manager obtained during composition reads and writes the slots of a node
+/// in the composition tree; a manager created with `new` owns its own slots.
 #[derive(Clone)]
 pub struct StateSlotManager {
-    inner: Rc<RefCell<StateSlotManagerInner>>,
+    storage: SlotStorage,
+}
+
+#[derive(Clone)]
+enum SlotStorage {
+    /// Slots owned by this manager
+    Local(Rc<RefCell<StateSlotManagerInner>>),
+    /// Slots of a node in the composition tree
+    Node(CompositionId),
 }
 
 struct StateSlotManagerInner {
@@ -23,10 +37,17 @@ struct StateSlotManagerInner {
 impl StateSlotManager {
     pub fn new() -> Self {
         Self {
-            inner: Rc::new(RefCell::new(StateSlotManagerInner {
+            storage: SlotStorage::Local(Rc::new(RefCell::new(StateSlotManagerInner {
                 slots: Vec::new(),
                 current_index: 0,
-            })),
+            }))),
+        }
+    }
+
+    /// Manage the slots of a composition tree node
+    pub fn for_node(id: CompositionId) -> Self {
+        Self {
+            storage: SlotStorage::Node(id),
         }
     }
 
@@ -36,7 +57,12 @@ impl StateSlotManager {
         T: Clone + Send + Sync + 'static,
         F: FnOnce() -> T,
     {
-        let mut inner = self.inner.borrow_mut();
+        let inner = match &self.storage {
+            SlotStorage::Local(inner) => inner,
+            SlotStorage::Node(id) => return remember_in_scope(*id, init),
+        };
+
+        let mut inner = inner.borrow_mut();
         let index = inner.current_index;
         inner.current_index += 1;
 
@@ -60,20 +86,39 @@ impl StateSlotManager {
 
     /// Update a state value at a given index
     pub fn update<T: Clone + Send + Sync + 'static>(&self, index: usize, value: T) {
-        let mut inner = self.inner.borrow_mut();
-        if index < inner.slots.len() {
-            inner.slots[index] = Box::new(value);
+        match &self.storage {
+            SlotStorage::Local(inner) => {
+                let mut inner = inner.borrow_mut();
+                if index < inner.slots.len() {
+                    inner.slots[index] = Box::new(value);
+                }
+            }
+            SlotStorage::Node(id) => {
+                with_scope_node(*id, |node| {
+                    if index < node.state_slots.len() {
+                        node.set_slot(index, value);
+                    }
+                });
+            }
         }
     }
 
     /// Reset index at start of recomposition
     pub fn reset(&self) {
-        self.inner.borrow_mut().current_index = 0;
+        match &self.storage {
+            SlotStorage::Local(inner) => inner.borrow_mut().current_index = 0,
+            SlotStorage::Node(id) => {
+                with_scope_node(*id, |node| node.slot_cursor = 0);
+            }
+        }
     }
 
     /// Get the current slot index
     pub fn current_index(&self) -> usize {
-        self.inner.borrow().current_index
+        match &self.storage {
+            SlotStorage::Local(inner) => inner.borrow().current_index,
+            SlotStorage::Node(id) => with_scope_node(*id, |node| node.slot_cursor).unwrap_or(0),
+        }
     }
 }
 
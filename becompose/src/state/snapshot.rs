@@ -0,0 +1,566 @@
+//! Snapshots
+//!
+//! Transactional state updates. Writes made inside a mutable snapshot are
+//! buffered and applied to their states all at once when the snapshot ends,
+//! so no reader sees some of them without the others, and the scopes they
+//! invalidate are collected once. Reads inside a mutable snapshot see the
+//! snapshot's own writes. Reads inside a read-only snapshot see the states of
+//! the current App as they were when the snapshot started.
+//!
+//! Each App's `ComposeRuntime` has its own `WriteLock`, held only while writes
+//! to its states are applied. Each applied write gives its state a new
+//! version. A read-only snapshot records the last version applied when it
+//! started, and states written after that keep their older values until the
+//! snapshot ends, so readers never hold up writes. A mutable snapshot that
+//! wrote a state which was written elsewhere since the snapshot started is not
+//! applied, since applying it would lose that other write.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, PoisonError};
+
+use generational_box::GenerationalBoxId;
+
+use crate::bevy_integration::{current_runtime, Subscriber};
+
+/// Orders the writes to the states of one runtime.
+///
+/// Held while writes are applied, and tracks the versions the open read-only
+/// snapshots read at. Snapshots only start between writes, so they never see
+/// writes applied part way.
+pub(crate) struct WriteLock {
+    holders: Mutex<LockHolders>,
+    released: Condvar,
+    /// Version given to the states written by the next write
+    next_version: AtomicU64,
+}
+
+#[derive(Default)]
+struct LockHolders {
+    /// Whether writes are being applied
+    writing: bool,
+    /// Versions the open read-only snapshots read at, with how many read at
+    /// each
+    readers: BTreeMap<u64, usize>,
+}
+
+impl Default for WriteLock {
+    fn default() -> Self {
+        Self {
+            holders: Mutex::new(LockHolders::default()),
+            released: Condvar::new(),
+            // States start at version 0
+            next_version: AtomicU64::new(1),
+        }
+    }
+}
+
+impl WriteLock {
+    /// Lock for the states created outside any App
+    pub(crate) fn detached() -> Arc<Self> {
+        static DETACHED: OnceLock<Arc<WriteLock>> = OnceLock::new();
+        DETACHED.get_or_init(Arc::default).clone()
+    }
+
+    /// Lock of the runtime active on this thread, or the detached lock
+    fn current() -> Arc<Self> {
+        current_runtime()
+            .map(|runtime| runtime.write_lock())
+            .unwrap_or_else(Self::detached)
+    }
+
+    /// Allocate the version of a new write
+    pub(crate) fn next_version(&self) -> u64 {
+        self.next_version.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn holders(&self) -> MutexGuard<'_, LockHolders> {
+        self.holders.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn wait<'a>(&self, holders: MutexGuard<'a, LockHolders>) -> MutexGuard<'a, LockHolders> {
+        self.released
+            .wait(holders)
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Start reading at the last applied version, waiting for writes being
+    /// applied to finish first
+    fn open_read(&self) -> u64 {
+        let mut holders = self.holders();
+        while holders.writing {
+            holders = self.wait(holders);
+        }
+        let version = self.next_version.load(Ordering::Relaxed) - 1;
+        *holders.readers.entry(version).or_default() += 1;
+        version
+    }
+
+    fn close_read(&self, version: u64) {
+        let mut holders = self.holders();
+        if let Some(count) = holders.readers.get_mut(&version) {
+            *count -= 1;
+            if *count == 0 {
+                holders.readers.remove(&version);
+            }
+        }
+    }
+
+    /// Version the oldest open read-only snapshot reads at
+    pub(crate) fn oldest_read(&self) -> Option<u64> {
+        self.holders().readers.keys().next().copied()
+    }
+
+    fn acquire_write(&self) {
+        let mut holders = self.holders();
+        while holders.writing {
+            holders = self.wait(holders);
+        }
+        holders.writing = true;
+    }
+
+    fn release_write(&self) {
+        self.holders().writing = false;
+        self.released.notify_all();
+    }
+}
+
+/// A mutable snapshot could not be applied because states it wrote were
+/// written elsewhere after it started. None of its writes were applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotConflict {
+    /// Number of conflicting states
+    pub states: usize,
+}
+
+impl fmt::Display for SnapshotConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "snapshot conflicts with writes to {} state(s) made after it started",
+            self.states
+        )
+    }
+}
+
+impl std::error::Error for SnapshotConflict {}
+
+/// A write buffered by a mutable snapshot
+pub(crate) trait SnapshotWrite: Send {
+    /// Version of the state when the snapshot first wrote it
+    fn base(&self) -> u64;
+
+    /// Whether the state was written elsewhere since `base`
+    fn conflicts(&self) -> bool;
+
+    /// Lock of the runtime the state belongs to
+    fn write_lock(&self) -> Arc<WriteLock>;
+
+    fn as_any(&self) -> &dyn Any;
+
+    /// Write the value to the state with the given version, returning the
+    /// scopes to invalidate. The old value is kept if read-only snapshots
+    /// reading at `oldest_read` or later may still read it.
+    fn apply(self: Box<Self>, version: u64, oldest_read: Option<u64>) -> Vec<Subscriber>;
+}
+
+/// Snapshots open on this thread
+#[derive(Default)]
+struct ThreadSnapshots {
+    /// Number of open read-only snapshots
+    read_only: usize,
+    /// Number of open mutable snapshots; nested ones share the outermost's
+    /// writes
+    mutable: usize,
+    /// Writes of the outermost mutable snapshot, by state
+    writes: HashMap<GenerationalBoxId, Box<dyn SnapshotWrite>>,
+    /// Lock and version the outermost read-only snapshot reads at
+    read: Option<(Arc<WriteLock>, u64)>,
+}
+
+thread_local! {
+    static SNAPSHOTS: RefCell<ThreadSnapshots> = RefCell::new(ThreadSnapshots::default());
+    /// Locks this thread holds for writing
+    static WRITING: RefCell<Vec<Arc<WriteLock>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Run `f` in a read-only snapshot.
+///
+/// Every state of the current App read inside `f` shows the value it had when
+/// the snapshot started, or the value written by an enclosing mutable
+/// snapshot. Writes from other threads are not held up: they are applied
+/// while `f` runs, but `f` does not see them. Writing a state inside a
+/// read-only snapshot panics.
+pub fn with_snapshot<R>(f: impl FnOnce() -> R) -> R {
+    let _snapshot = OpenSnapshot::open(false);
+    f()
+}
+
+/// Run `f` in a mutable snapshot and apply its writes atomically.
+///
+/// Writes made by `f` are only visible inside the snapshot until it ends;
+/// then they are applied together and the union of the scopes reading the
+/// written states is invalidated once. No lock is held while `f` runs, only
+/// while the writes are applied. If another write to one of those
+/// states was applied after the snapshot started, nothing is applied and a
+/// `SnapshotConflict` is returned. If `f` panics, its writes are discarded.
+///
+/// Inside another mutable snapshot, the writes join the outer snapshot and
+/// are applied with it.
+///
+/// # Example
+/// ```ignore
+/// Button("Add", Modifiers::new(), move || {
+///     let applied = with_mutable_snapshot(|| {
+///         let id = next_id.get();
+///         next_id.set(id + 1);
+///         todos.update(|todos| todos.push(Todo::new(id)));
+///     });
+///     if let Err(conflict) = applied {
+///         warn!("todo not added: {conflict}");
+///     }
+/// });
+/// ```
+pub fn with_mutable_snapshot<R>(f: impl FnOnce() -> R) -> Result<R, SnapshotConflict> {
+    let snapshot = OpenSnapshot::open(true);
+    let result = f();
+    snapshot.apply().map(|()| result)
+}
+
+/// Open a mutable snapshot on this thread, to be ended by
+/// `end_mutable_snapshot`
+pub(crate) fn begin_mutable_snapshot() {
+    open(true);
+}
+
+/// End a mutable snapshot opened by `begin_mutable_snapshot`, applying its
+/// writes if it is the outermost one
+pub(crate) fn end_mutable_snapshot() -> Result<(), SnapshotConflict> {
+    match close(true) {
+        Some(writes) => apply_writes(writes),
+        None => Ok(()),
+    }
+}
+
+/// Whether a mutable snapshot is open on this thread
+pub fn in_mutable_snapshot() -> bool {
+    SNAPSHOTS.with(|snapshots| snapshots.borrow().mutable > 0)
+}
+
+/// A snapshot opened by `with_snapshot` or `with_mutable_snapshot`, closed
+/// without applying its writes if it is dropped
+struct OpenSnapshot {
+    mutable: bool,
+    closed: bool,
+}
+
+impl OpenSnapshot {
+    fn open(mutable: bool) -> Self {
+        open(mutable);
+        Self {
+            mutable,
+            closed: false,
+        }
+    }
+
+    fn apply(mut self) -> Result<(), SnapshotConflict> {
+        self.closed = true;
+        match close(self.mutable) {
+            Some(writes) => apply_writes(writes),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for OpenSnapshot {
+    fn drop(&mut self) {
+        if !self.closed {
+            close(self.mutable);
+        }
+    }
+}
+
+fn open(mutable: bool) {
+    let needs_read = SNAPSHOTS.with(|snapshots| {
+        let snapshots = snapshots.borrow();
+        if mutable && snapshots.read_only > 0 {
+            panic!("cannot open a mutable snapshot inside a read-only snapshot");
+        }
+        !mutable && snapshots.read_only == 0
+    });
+    // Opened outside the borrow, since it may wait for writes on other threads
+    let read = needs_read.then(WriteLock::current).filter(|lock| {
+        // A thread applying writes already keeps them from changing
+        let writing =
+            WRITING.with(|writing| writing.borrow().iter().any(|held| Arc::ptr_eq(held, lock)));
+        !writing
+    });
+    let read = read.map(|lock| {
+        let version = lock.open_read();
+        (lock, version)
+    });
+
+    SNAPSHOTS.with(|snapshots| {
+        let mut snapshots = snapshots.borrow_mut();
+        if read.is_some() {
+            snapshots.read = read;
+        }
+        if mutable {
+            snapshots.mutable += 1;
+        } else {
+            snapshots.read_only += 1;
+        }
+    });
+}
+
+/// Close the innermost snapshot of the given kind, returning the writes to
+/// apply if it was the outermost mutable snapshot
+fn close(mutable: bool) -> Option<HashMap<GenerationalBoxId, Box<dyn SnapshotWrite>>> {
+    let (writes, read) = SNAPSHOTS.with(|snapshots| {
+        let mut snapshots = snapshots.borrow_mut();
+        let writes = if mutable {
+            snapshots.mutable = snapshots.mutable.saturating_sub(1);
+            (snapshots.mutable == 0).then(|| std::mem::take(&mut snapshots.writes))
+        } else {
+            snapshots.read_only = snapshots.read_only.saturating_sub(1);
+            None
+        };
+        let read = if snapshots.read_only == 0 {
+            snapshots.read.take()
+        } else {
+            None
+        };
+        (writes, read)
+    });
+    if let Some((lock, version)) = read {
+        lock.close_read(version);
+    }
+    writes
+}
+
+/// Apply the writes of a mutable snapshot, then invalidate their readers
+fn apply_writes(
+    writes: HashMap<GenerationalBoxId, Box<dyn SnapshotWrite>>,
+) -> Result<(), SnapshotConflict> {
+    if writes.is_empty() {
+        return Ok(());
+    }
+
+    let locks = writes.values().map(|write| write.write_lock()).collect();
+    let invalidated = with_write_locks(locks, || {
+        let conflicts = writes.values().filter(|write| write.conflicts()).count();
+        if conflicts > 0 {
+            return Err(SnapshotConflict { states: conflicts });
+        }
+
+        let mut invalidated: Vec<Subscriber> = Vec::new();
+        for write in writes.into_values() {
+            let lock = write.write_lock();
+            let version = lock.next_version();
+            for subscriber in write.apply(version, lock.oldest_read()) {
+                if !invalidated.contains(&subscriber) {
+                    invalidated.push(subscriber);
+                }
+            }
+        }
+        Ok(invalidated)
+    })?;
+
+    for subscriber in invalidated {
+        subscriber.notify();
+    }
+    Ok(())
+}
+
+/// How a state write made on this thread takes effect
+pub(crate) enum WriteMode {
+    /// Applied right away
+    Direct,
+    /// Buffered in the open mutable snapshot
+    Buffered,
+}
+
+/// Get how a state write made on this thread takes effect.
+///
+/// Panics inside a read-only snapshot.
+pub(crate) fn write_mode() -> WriteMode {
+    SNAPSHOTS.with(|snapshots| {
+        let snapshots = snapshots.borrow();
+        if snapshots.read_only > 0 {
+            panic!("state written inside a read-only snapshot");
+        }
+        if snapshots.mutable > 0 {
+            WriteMode::Buffered
+        } else {
+            WriteMode::Direct
+        }
+    })
+}
+
+/// Look at the write the open mutable snapshot buffered for a state
+pub(crate) fn pending_write<R>(
+    id: GenerationalBoxId,
+    f: impl FnOnce(&dyn SnapshotWrite) -> R,
+) -> Option<R> {
+    SNAPSHOTS.with(|snapshots| {
+        let snapshots = snapshots.borrow();
+        snapshots.writes.get(&id).map(|write| f(write.as_ref()))
+    })
+}
+
+/// Version the read-only snapshot open on this thread reads the states
+/// ordered by `lock` at, if there is one. `lock` is only called while a
+/// read-only snapshot is open.
+pub(crate) fn read_version(lock: impl FnOnce() -> Arc<WriteLock>) -> Option<u64> {
+    let (read_lock, version) = SNAPSHOTS.with(|snapshots| snapshots.borrow().read.clone())?;
+    Arc::ptr_eq(&read_lock, &lock()).then_some(version)
+}
+
+/// Buffer a write in the open mutable snapshot, replacing any earlier write
+/// to the same state
+pub(crate) fn buffer_write(id: GenerationalBoxId, write: Box<dyn SnapshotWrite>) {
+    SNAPSHOTS.with(|snapshots| {
+        snapshots.borrow_mut().writes.insert(id, write);
+    });
+}
+
+/// Run a direct write with `lock` held, so it never lands while another write
+/// is applied or a read-only snapshot of the same App is starting. Writes
+/// nested in `f` reuse the lock.
+pub(crate) fn with_write_lock<R>(lock: Arc<WriteLock>, f: impl FnOnce() -> R) -> R {
+    with_write_locks(vec![lock], f)
+}
+
+/// Run `f` with all of `locks` held for writing. Locks are taken in a fixed
+/// order, so threads taking overlapping sets never wait for each other in a
+/// cycle.
+fn with_write_locks<R>(mut locks: Vec<Arc<WriteLock>>, f: impl FnOnce() -> R) -> R {
+    /// Releases the locks even if `f` panics
+    struct Release(Vec<Arc<WriteLock>>);
+
+    impl Drop for Release {
+        fn drop(&mut self) {
+            WRITING.with(|writing| {
+                writing
+                    .borrow_mut()
+                    .retain(|held| !self.0.iter().any(|lock| Arc::ptr_eq(held, lock)))
+            });
+            for lock in &self.0 {
+                lock.release_write();
+            }
+        }
+    }
+
+    locks.sort_by_key(|lock| Arc::as_ptr(lock) as usize);
+    locks.dedup_by(|a, b| Arc::ptr_eq(a, b));
+    WRITING.with(|writing| {
+        let writing = writing.borrow();
+        locks.retain(|lock| !writing.iter().any(|held| Arc::ptr_eq(held, lock)));
+    });
+
+    for lock in &locks {
+        lock.acquire_write();
+    }
+    WRITING.with(|writing| writing.borrow_mut().extend(locks.iter().cloned()));
+    let _release = Release(locks);
+    f()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bevy_integration::{with_runtime, ComposeRuntime, State};
+    use std::thread;
+
+    #[test]
+    fn writes_are_applied_when_the_snapshot_ends() {
+        let first = State::new(0);
+        let second = State::new(0);
+
+        let applied = with_mutable_snapshot(|| {
+            first.set(1);
+            second.update(|value| *value = first.get_untracked() + 1);
+            thread::scope(|scope| {
+                scope.spawn(|| assert_eq!((first.get_untracked(), second.get_untracked()), (0, 0)));
+            });
+        });
+
+        assert_eq!(applied, Ok(()));
+        assert_eq!((first.get_untracked(), second.get_untracked()), (1, 2));
+    }
+
+    #[test]
+    fn writes_of_a_panicking_snapshot_are_discarded() {
+        let state = State::new(0);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            with_mutable_snapshot(|| {
+                state.set(1);
+                panic!("handler failed");
+            })
+        }));
+
+        assert!(result.is_err());
+        assert!(!in_mutable_snapshot());
+        assert_eq!(state.get_untracked(), 0);
+    }
+
+    #[test]
+    fn snapshots_conflicting_with_later_writes_are_not_applied() {
+        let state = State::new(0);
+        let other = State::new(0);
+
+        let applied = with_mutable_snapshot(|| {
+            state.set(1);
+            other.set(1);
+            // Not held up by the open snapshot
+            thread::scope(|scope| {
+                scope.spawn(|| state.set(2));
+            });
+        });
+
+        assert_eq!(applied, Err(SnapshotConflict { states: 1 }));
+        assert_eq!((state.get_untracked(), other.get_untracked()), (2, 0));
+    }
+
+    #[test]
+    fn read_only_snapshots_see_their_apps_states_as_they_started() {
+        let runtime = ComposeRuntime::new();
+        let state = with_runtime(&runtime, || State::new(0));
+
+        with_runtime(&runtime, || {
+            with_snapshot(|| {
+                // Not held up by the open snapshot
+                thread::spawn(move || {
+                    state.set(1);
+                    state.set(2);
+                })
+                .join()
+                .unwrap();
+                assert_eq!(state.get_untracked(), 0);
+                let elsewhere = thread::spawn(move || state.get_untracked());
+                assert_eq!(elsewhere.join().unwrap(), 2);
+            })
+        });
+
+        assert_eq!(state.get_untracked(), 2);
+    }
+
+    #[test]
+    fn read_only_snapshots_leave_other_apps_alone() {
+        let (runtime, other_runtime) = (ComposeRuntime::new(), ComposeRuntime::new());
+        let other = with_runtime(&other_runtime, || State::new(0));
+
+        with_runtime(&runtime, || {
+            with_snapshot(|| {
+                thread::scope(|scope| {
+                    scope.spawn(|| other.set(1));
+                });
+                assert_eq!(other.get_untracked(), 1);
+            })
+        });
+    }
+}
@@ -0,0 +1,126 @@
+//! Test Support
+//!
+//! Helpers for driving the composable runtime headlessly in unit tests.
+
+use bevy::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::bevy_integration::{BecomposePlugin, ComposeRuntime, CompositionRoot, ContentFn};
+use crate::composition::{CompositionStats, CompositionTree};
+
+/// App composing `content` as its main content, run for one frame
+pub(crate) fn compose_app(content: impl Fn() + Send + Sync + 'static) -> App {
+    compose_app_with(|_| {}, content)
+}
+
+/// Like `compose_app`, with `setup` run on the app before its first frame
+pub(crate) fn compose_app_with(
+    setup: impl FnOnce(&mut App),
+    content: impl Fn() + Send + Sync + 'static,
+) -> App {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, BecomposePlugin));
+    app.insert_resource(ContentFn {
+        compose_fn: Arc::new(Mutex::new(Box::new(content))),
+    });
+    setup(&mut app);
+    app.update();
+    app
+}
+
+/// Run frames until `done` holds, failing after a second
+pub(crate) fn update_until(app: &mut App, done: impl Fn(&mut App) -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while !done(app) {
+        assert!(Instant::now() < deadline, "timed out");
+        app.update();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Recompose the app's main content from the root and run a frame
+pub(crate) fn recompose_root(app: &mut App) {
+    app.world().resource::<ComposeRuntime>().invalidate();
+    app.update();
+}
+
+/// What the app's composition tree holds on to
+pub(crate) fn stats(app: &App) -> CompositionStats {
+    app.world().resource::<CompositionTree>().stats()
+}
+
+/// Number of entities with a `C`
+pub(crate) fn count<C: Component>(app: &mut App) -> usize {
+    entities::<C>(app).len()
+}
+
+/// Entities with a `C`, sorted
+pub(crate) fn entities<C: Component>(app: &mut App) -> Vec<Entity> {
+    let world = app.world_mut();
+    let mut entities: Vec<Entity> = world
+        .query_filtered::<Entity, With<C>>()
+        .iter(world)
+        .collect();
+    entities.sort();
+    entities
+}
+
+/// Composed texts, in tree order
+pub(crate) fn texts(app: &mut App) -> Vec<String> {
+    fn collect(world: &World, entity: Entity, out: &mut Vec<String>) {
+        if let Some(text) = world.get::<Text>(entity) {
+            out.push(text.0.clone());
+        }
+        if let Some(children) = world.get::<Children>(entity) {
+            for &child in children {
+                collect(world, child, out);
+            }
+        }
+    }
+
+    let world = app.world_mut();
+    let roots: Vec<Entity> = world
+        .query_filtered::<Entity, (With<CompositionRoot>, Without<ChildOf>)>()
+        .iter(world)
+        .collect();
+    let mut out = Vec::new();
+    for root in roots {
+        collect(world, root, &mut out);
+    }
+    out
+}
+
+/// A value handed out of composed content, such as a remembered `State`
+pub(crate) struct Captured<T>(Arc<Mutex<Option<T>>>);
+
+impl<T> Clone for Captured<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: Clone> Captured<T> {
+    pub(crate) fn new() -> Self {
+        Self(Arc::new(Mutex::new(None)))
+    }
+
+    pub(crate) fn set(&self, value: T) {
+        *self.0.lock().unwrap() = Some(value);
+    }
+
+    /// The last value set
+    pub(crate) fn get(&self) -> T {
+        self.0.lock().unwrap().clone().expect("nothing captured")
+    }
+}
+
+/// Sets its flag when dropped, such as with the future holding it
+pub(crate) struct DropFlag(pub(crate) Arc<AtomicBool>);
+
+impl Drop for DropFlag {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Release);
+    }
+}
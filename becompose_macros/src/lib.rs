@@ -4,15 +4,22 @@
 //! composable functions in the BECOMPOSE framework.
 
 use proc_macro::TokenStream;
-use proc_macro2::TokenStream as TokenStream2;
-use quote::quote;
-use syn::{parse_macro_input, ItemFn};
+use proc_macro2::{TokenStream as TokenStream2, TokenTree};
+use quote::{quote, ToTokens};
+use syn::{parse_macro_input, FnArg, GenericParam, Generics, ItemFn, Pat, ReturnType, Type};
 
 /// Marks a function as a composable function.
 ///
 /// Composable functions are the building blocks of BECOMPOSE UIs.
 /// They describe UI elements and can be recomposed when state changes.
 ///
+/// A composable that returns nothing is skipped when its parent recomposes
+/// and it is called with parameters equal to last time: the UI it emitted is
+/// kept without running its body. Parameters are compared if they implement
+/// `PartialEq` and `Clone` (or `ToOwned`, for `&str` and slices) or the
+/// `Stable` marker trait. Composables with a parameter that cannot be compared,
+/// such as a closure or a generic parameter, always run.
+///
 /// # Example
 ///
 /// ```rust
@@ -47,20 +54,116 @@ fn expand_composable(input: ItemFn) -> TokenStream2 {
     // Generate a unique type ID based on function name
     let type_id_str = fn_name.to_string();
 
+    // `#[track_caller]` makes `start_group` identify the group by where the
+    // composable is called from, rather than by its name alone
+    let Some(probes) = skip_probes(&sig.inputs, generics, output) else {
+        return quote! {
+            #(#attrs)*
+            #[track_caller]
+            #vis fn #fn_name #generics (#inputs) #output #where_clause {
+                use becompose::composition::CompositionContext;
+
+                let __ctx = CompositionContext::current();
+                let __node_id = __ctx.start_group(#type_id_str, None);
+
+                let __result = (|| {
+                    #block
+                })();
+
+                __ctx.end_group(__node_id);
+                __result
+            }
+        };
+    };
+    let param_count = probes.len();
+
     quote! {
         #(#attrs)*
+        #[track_caller]
         #vis fn #fn_name #generics (#inputs) #output #where_clause {
-            use becompose::composition::CompositionContext;
+            use becompose::composition::{
+                CompositionContext, ParamProbe, ProbeCompared as _, ProbeStable as _,
+                ProbeUnstable as _, SkipParam,
+            };
 
             let __ctx = CompositionContext::current();
-            let __node_id = __ctx.start_group(#type_id_str, None);
+            let __params: [Option<Box<dyn SkipParam>>; #param_count] = [#(#probes),*];
+            let __params = __params.into_iter().collect::<Option<Vec<_>>>();
 
-            let __result = (|| {
-                #block
-            })();
+            if let Some(__node_id) = __ctx.start_skippable_group(#type_id_str, None, __params) {
+                (|| {
+                    #block
+                })();
 
-            __ctx.end_group(__node_id);
-            __result
+                __ctx.end_skippable_group(__node_id);
+            }
         }
     }
 }
+
+/// Expressions recording each parameter of a composable that returns nothing,
+/// or `None` if the composable cannot be skipped.
+///
+/// References are compared by the value they point to. Parameters whose type
+/// borrows, is generic or is an `impl Trait` are never compared, since their
+/// values cannot be kept until the next call.
+fn skip_probes(
+    inputs: &syn::punctuated::Punctuated<FnArg, syn::Token![,]>,
+    generics: &Generics,
+    output: &ReturnType,
+) -> Option<Vec<TokenStream2>> {
+    match output {
+        ReturnType::Default => {}
+        ReturnType::Type(_, ty) => match ty.as_ref() {
+            Type::Tuple(tuple) if tuple.elems.is_empty() => {}
+            _ => return None,
+        },
+    }
+
+    let type_params: Vec<String> = generics
+        .params
+        .iter()
+        .filter_map(|param| match param {
+            GenericParam::Type(param) => Some(param.ident.to_string()),
+            _ => None,
+        })
+        .collect();
+
+    inputs
+        .iter()
+        .map(|input| {
+            let FnArg::Typed(input) = input else {
+                return None;
+            };
+            let Pat::Ident(pat) = input.pat.as_ref() else {
+                return None;
+            };
+            let name = &pat.ident;
+            let (ty, value) = match input.ty.as_ref() {
+                Type::Reference(reference) if reference.mutability.is_none() => {
+                    (reference.elem.as_ref(), quote!(#name))
+                }
+                ty => (ty, quote!(&#name)),
+            };
+            Some(if is_owned(ty.to_token_stream(), &type_params) {
+                quote!((&&&ParamProbe::new(#value)).skip_param())
+            } else {
+                quote!(None)
+            })
+        })
+        .collect()
+}
+
+/// Whether a type neither borrows nor names a generic or opaque type
+fn is_owned(tokens: TokenStream2, type_params: &[String]) -> bool {
+    tokens.into_iter().all(|token| match token {
+        TokenTree::Group(group) => is_owned(group.stream(), type_params),
+        TokenTree::Punct(punct) => punct.as_char() != '&' && punct.as_char() != '\'',
+        TokenTree::Ident(ident) => {
+            let ident = ident.to_string();
+            !matches!(ident.as_str(), "impl" | "dyn" | "Self" | "fn")
+                && !type_params.contains(&ident)
+        }
+        TokenTree::Literal(_) => true,
+    })
+}
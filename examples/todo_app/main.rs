@@ -54,16 +54,23 @@ impl AppState {
     }
 
     fn add_todo(&self) {
-        let id = self.next_id.get();
-        self.next_id.set(id + 1);
-        self.todos.update(|todos| {
-            todos.push(Todo {
-                id,
-                title: format!("New Todo #{}", id),
-                completed: false,
+        // Both states change together, so nothing sees the new ID without
+        // the todo that uses it
+        let added = with_mutable_snapshot(|| {
+            let id = self.next_id.get();
+            self.next_id.set(id + 1);
+            self.todos.update(|todos| {
+                todos.push(Todo {
+                    id,
+                    title: format!("New Todo #{}", id),
+                    completed: false,
+                });
             });
         });
-        println!("Added new todo!");
+        match added {
+            Ok(()) => info!("Added new todo"),
+            Err(conflict) => warn!("Todo not added: {conflict}"),
+        }
     }
 
     fn toggle_todo(&self, id: u32) {
@@ -72,14 +79,14 @@ impl AppState {
                 todo.completed = !todo.completed;
             }
         });
-        println!("Toggled todo {}", id);
+        info!("Toggled todo {}", id);
     }
 
     fn delete_todo(&self, id: u32) {
         self.todos.update(|todos| {
             todos.retain(|t| t.id != id);
         });
-        println!("Deleted todo {}", id);
+        info!("Deleted todo {}", id);
     }
 }
 